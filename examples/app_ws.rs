@@ -21,6 +21,7 @@ async fn main() -> Result<()> {
         host: std::net::IpAddr::from([0, 0, 0, 0]),
         port: 2456,
         access_token: None,
+        ..Default::default()
     };
     config.websocket_rev.push(ws_server);
     ob.start(config, (), true).await.unwrap();
@@ -1,4 +1,4 @@
-use walle_core::event::{BaseEvent, Event};
+use walle_core::event::{BaseEvent, Event, ImplLevel};
 use walle_core::prelude::{PushToValueMap, ToEvent, TryFromEvent};
 use walle_core::segment::{MsgSegment, Segments};
 use walle_core::structs::Selft;
@@ -35,12 +35,20 @@ pub struct Walle {
     yyy: i64,
 }
 
+#[derive(Debug, ToEvent, TryFromEvent, PushToValueMap, PartialEq)]
+#[event(impl)]
+pub struct Lagrange;
+
 #[derive(Debug, ToEvent, TryFromEvent, PushToValueMap, PartialEq)]
 #[event(impl)]
 pub enum Impls {
     Gocq,
-    Walle { xxx: String, yyy: i64 },
-    // XX(YY), unsupportted
+    Walle {
+        xxx: String,
+        yyy: i64,
+    },
+    #[event(delegate)]
+    Lagrange(Lagrange),
 }
 
 fn main() {
@@ -167,4 +175,8 @@ fn main() {
     };
     let tgme: BaseEvent<MessageE, Group> = raw_gme.clone().try_into().unwrap();
     assert_eq!(tgme, gmbe);
+    let implt: Impls =
+        TryFromEvent::<ImplLevel>::try_from_event(raw_gme.clone(), "lagrange").unwrap();
+    assert_eq!(implt, Impls::Lagrange(Lagrange));
+    assert_eq!(ToEvent::<ImplLevel>::ty(&implt), "lagrange");
 }
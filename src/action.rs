@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     prelude::{WalleError, WalleResult},
     structs::Selft,
-    util::{GetSelf, PushToValueMap, ValueMap, ValueMapExt},
+    util::{ActionName, GetSelf, PushToValueMap, ValueMap, ValueMapExt},
 };
 
 /// 标准 Action 模型
@@ -17,6 +17,8 @@ pub struct Action {
 }
 
 pub trait ToAction: PushToValueMap {
+    /// 扩展动作前缀，如 `qq`
+    const PREFIX: Option<&'static str> = None;
     fn ty(&self) -> &'static str;
     fn selft(&self) -> Option<Selft> {
         None
@@ -50,6 +52,12 @@ impl GetSelf for Action {
     }
 }
 
+impl ActionName for Action {
+    fn action_name(&self) -> Option<&str> {
+        Some(&self.action)
+    }
+}
+
 /// 泛型可扩展 Action 模型
 #[derive(Debug, Clone, PartialEq)]
 pub struct BaseAction<T> {
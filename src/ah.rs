@@ -1,11 +1,13 @@
+use std::collections::HashMap;
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 
 use crate::action::Action;
 use crate::error::WalleResult;
 use crate::event::Event;
 use crate::prelude::Version;
-use crate::resp::Resp;
+use crate::resp::{resp_error, Resp, RespError};
 use crate::structs::{Bot, Selft, Status};
 use crate::util::GetSelf;
 use crate::EventHandler;
@@ -57,7 +59,19 @@ pub trait ActionHandler<E = Event, A = Action, R = Resp>: GetStatus + GetVersion
     {
         async { Ok(()) }
     }
-    fn shutdown(&self) -> impl Future<Output = ()> {
+    /// 运行中以新配置重载，仅启动或停止发生变化的部分，返回新启动的任务
+    fn reload<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _config: Self::Config,
+    ) -> impl Future<Output = WalleResult<Vec<tokio::task::JoinHandle<()>>>> + Send
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        async { Err(crate::WalleError::ReloadNotSupported) }
+    }
+    fn shutdown(&self) -> impl Future<Output = ()> + Send {
         async {}
     }
     fn on_onebot_connect<AH, EH>(
@@ -86,6 +100,19 @@ pub trait ActionHandler<E = Event, A = Action, R = Resp>: GetStatus + GetVersion
 pub trait GetSelfs {
     fn get_selfs(&self) -> impl Future<Output = Vec<Selft>> + Send;
     fn get_impl(&self, selft: &Selft) -> impl Future<Output = String> + Send;
+    /// 是否持有该 bot，默认实现遍历 get_selfs
+    fn has_self(&self, selft: &Selft) -> impl Future<Output = bool> + Send
+    where
+        Self: Sync,
+    {
+        async move { self.get_selfs().await.contains(selft) }
+    }
+    /// bot 列表的变更计数，bot 上线、下线或连接断开时递增，SelftRouter 据此使缓存失效
+    ///
+    /// 默认为 0，即 bot 列表不会变化
+    fn selfs_epoch(&self) -> u64 {
+        0
+    }
 }
 
 impl<T: GetSelfs + Send + Sync> GetSelfs for Arc<T> {
@@ -95,11 +122,134 @@ impl<T: GetSelfs + Send + Sync> GetSelfs for Arc<T> {
     async fn get_selfs(&self) -> Vec<Selft> {
         self.as_ref().get_selfs().await
     }
+    async fn has_self(&self, selft: &Selft) -> bool {
+        self.as_ref().has_self(selft).await
+    }
+    fn selfs_epoch(&self) -> u64 {
+        self.as_ref().selfs_epoch()
+    }
+}
+
+/// 以 Selft 为键的路由表，缓存各 bot 所在的后端
+///
+/// 命中缓存时直接返回，后端的 selfs_epoch 变化（bot 断开或迁移）时清空缓存并重新探测，
+/// selft 为空的 Action 发往默认后端
+#[derive(Debug)]
+pub struct SelftRouter<K = usize> {
+    routes: RwLock<HashMap<Selft, K>>,
+    default: RwLock<Option<K>>,
+    epoch: AtomicU64,
+}
+
+impl<K> Default for SelftRouter<K> {
+    fn default() -> Self {
+        Self {
+            routes: RwLock::default(),
+            default: RwLock::new(None),
+            epoch: AtomicU64::default(),
+        }
+    }
+}
+
+impl<K: Clone + PartialEq> SelftRouter<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_default(target: K) -> Self {
+        Self {
+            routes: RwLock::default(),
+            default: RwLock::new(Some(target)),
+            epoch: AtomicU64::default(),
+        }
+    }
+    /// 设置 selft 为空的 Action 的默认后端
+    pub fn set_default(&self, target: Option<K>) {
+        *self.default.write().unwrap() = target;
+    }
+    pub fn default_target(&self) -> Option<K> {
+        self.default.read().unwrap().clone()
+    }
+    pub fn get(&self, selft: &Selft) -> Option<K> {
+        self.routes.read().unwrap().get(selft).cloned()
+    }
+    pub fn insert(&self, selft: Selft, target: K) {
+        self.routes.write().unwrap().insert(selft, target);
+    }
+    pub fn remove(&self, selft: &Selft) -> Option<K> {
+        self.routes.write().unwrap().remove(selft)
+    }
+    /// 移除指向该后端的全部路由
+    pub fn remove_target(&self, target: &K) {
+        self.routes.write().unwrap().retain(|_, k| k != target);
+        let mut default = self.default.write().unwrap();
+        if default.as_ref() == Some(target) {
+            *default = None;
+        }
+    }
+    pub fn clear(&self) {
+        self.routes.write().unwrap().clear();
+    }
+    /// 查找 bot 所在后端
+    ///
+    /// epoch 返回各后端 selfs_epoch 之和，与上次不同时清空缓存；缓存未命中时才取 targets 并依次以 has_self 探测
+    pub async fn route<I, F, Fut>(
+        &self,
+        selft: &Selft,
+        epoch: impl FnOnce() -> u64,
+        targets: impl FnOnce() -> I,
+        has_self: F,
+    ) -> Option<K>
+    where
+        I: IntoIterator<Item = K>,
+        F: Fn(K) -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        if selft == &Selft::default() {
+            if let Some(target) = self.default_target() {
+                return Some(target);
+            }
+        }
+        let epoch = epoch();
+        if self.epoch.swap(epoch, Ordering::Relaxed) != epoch {
+            self.clear();
+        } else if let Some(target) = self.get(selft) {
+            return Some(target);
+        }
+        for target in targets() {
+            if has_self(target.clone()).await {
+                self.insert(selft.clone(), target.clone());
+                return Some(target);
+            }
+        }
+        None
+    }
+}
+
+/// 找不到 bot 时的响应，包含所请求的 bot
+pub(crate) fn unknown_self(selft: &Selft) -> RespError {
+    if selft == &Selft::default() {
+        resp_error::who_am_i("未指定 self 且未设置默认后端")
+    } else {
+        resp_error::who_am_i(format!("未找到 bot {}-{}", selft.platform, selft.user_id))
+    }
 }
 
 /// supertrait for ActionHandler
 pub trait GetStatus: GetSelfs + Sync {
     fn is_good(&self) -> impl Future<Output = bool> + Send;
+    /// 所有 bot 及其在线状态，默认 get_selfs 中的 bot 均在线
+    fn get_bots(&self) -> impl Future<Output = Vec<Bot>> + Send {
+        async {
+            self.get_selfs()
+                .await
+                .into_iter()
+                .map(|selft| Bot {
+                    selft,
+                    online: true,
+                })
+                .collect()
+        }
+    }
     fn get_status(&self) -> impl Future<Output = Status> + Send
     where
         Self: Sized,
@@ -107,15 +257,7 @@ pub trait GetStatus: GetSelfs + Sync {
         async {
             Status {
                 good: self.is_good().await,
-                bots: self
-                    .get_selfs()
-                    .await
-                    .into_iter()
-                    .map(|selft| Bot {
-                        selft,
-                        online: true,
-                    })
-                    .collect(),
+                bots: self.get_bots().await,
             }
         }
     }
@@ -125,8 +267,82 @@ pub trait GetVersion {
     fn get_version(&self) -> Version;
 }
 
+/// 组合两个 Handler
+///
+/// 作为 ActionHandler 时依次以 has_self 查找 bot 所在后端，作为 EventHandler 时依次分发
 pub struct JoinedHandler<H0, H1>(pub H0, pub H1);
 
+impl<H0, H1> JoinedHandler<H0, H1>
+where
+    H0: GetSelfs + Sync,
+    H1: GetSelfs + Sync,
+{
+    async fn probe(&self, selft: &Selft) -> Option<usize> {
+        if self.0.has_self(selft).await {
+            Some(0)
+        } else if self.1.has_self(selft).await {
+            Some(1)
+        } else {
+            None
+        }
+    }
+}
+
+/// 组合两个 ActionHandler，以路由表缓存各 bot 所在的后端（0 或 1）
+pub struct JoinedActionHandler<H0, H1> {
+    handlers: JoinedHandler<H0, H1>,
+    router: SelftRouter,
+}
+
+impl<H0, H1> JoinedActionHandler<H0, H1> {
+    pub fn new(h0: H0, h1: H1) -> Self {
+        Self {
+            handlers: JoinedHandler(h0, h1),
+            router: SelftRouter::default(),
+        }
+    }
+    /// selft 为空的 Action 发往该后端，0 或 1
+    pub fn with_default(self, target: usize) -> Self {
+        self.router.set_default(Some(target));
+        self
+    }
+    pub fn first(&self) -> &H0 {
+        &self.handlers.0
+    }
+    pub fn second(&self) -> &H1 {
+        &self.handlers.1
+    }
+    pub fn router(&self) -> &SelftRouter {
+        &self.router
+    }
+    pub fn into_inner(self) -> (H0, H1) {
+        (self.handlers.0, self.handlers.1)
+    }
+}
+
+impl<H0, H1> JoinedActionHandler<H0, H1>
+where
+    H0: GetSelfs + Send + Sync,
+    H1: GetSelfs + Send + Sync,
+{
+    async fn route(&self, selft: &Selft) -> Option<usize> {
+        let JoinedHandler(h0, h1) = &self.handlers;
+        self.router
+            .route(
+                selft,
+                || self.handlers.selfs_epoch(),
+                || [0, 1],
+                |target| async move {
+                    match target {
+                        0 => h0.has_self(selft).await,
+                        _ => h1.has_self(selft).await,
+                    }
+                },
+            )
+            .await
+    }
+}
+
 pub trait AHExt<E, A, R> {
     fn join<AH1>(self, action_handler: AH1) -> JoinedHandler<Self, AH1>
     where
@@ -134,32 +350,66 @@ pub trait AHExt<E, A, R> {
     {
         JoinedHandler(self, action_handler)
     }
+    /// 组合两个 ActionHandler，并缓存各 bot 所在的后端
+    fn join_routed<AH1>(self, action_handler: AH1) -> JoinedActionHandler<Self, AH1>
+    where
+        Self: Sized,
+    {
+        JoinedActionHandler::new(self, action_handler)
+    }
 }
 
 impl<T: ActionHandler<E, A, R>, E, A, R> AHExt<E, A, R> for T {}
 
 impl<H0, H1> GetSelfs for JoinedHandler<H0, H1>
-//todo
 where
     H0: GetSelfs + Send + Sync,
     H1: GetSelfs + Send + Sync,
 {
     async fn get_selfs(&self) -> Vec<crate::structs::Selft> {
         let mut r = self.0.get_selfs().await;
-        r.extend(self.1.get_selfs().await.into_iter());
+        r.extend(self.1.get_selfs().await);
         r
     }
     async fn get_impl(&self, selft: &Selft) -> String {
-        if self.0.get_selfs().await.contains(selft) {
-            self.0.get_impl(selft).await
-        } else {
-            self.1.get_impl(selft).await
+        match self.probe(selft).await {
+            Some(0) => self.0.get_impl(selft).await,
+            Some(_) => self.1.get_impl(selft).await,
+            None => String::default(),
+        }
+    }
+    async fn has_self(&self, selft: &Selft) -> bool {
+        self.probe(selft).await.is_some()
+    }
+    fn selfs_epoch(&self) -> u64 {
+        self.0.selfs_epoch().wrapping_add(self.1.selfs_epoch())
+    }
+}
+
+impl<H0, H1> GetSelfs for JoinedActionHandler<H0, H1>
+where
+    H0: GetSelfs + Send + Sync,
+    H1: GetSelfs + Send + Sync,
+{
+    async fn get_selfs(&self) -> Vec<crate::structs::Selft> {
+        self.handlers.get_selfs().await
+    }
+    async fn get_impl(&self, selft: &Selft) -> String {
+        match self.route(selft).await {
+            Some(0) => self.handlers.0.get_impl(selft).await,
+            Some(_) => self.handlers.1.get_impl(selft).await,
+            None => String::default(),
         }
     }
+    async fn has_self(&self, selft: &Selft) -> bool {
+        self.route(selft).await.is_some()
+    }
+    fn selfs_epoch(&self) -> u64 {
+        self.handlers.selfs_epoch()
+    }
 }
 
 impl<H0, H1> GetStatus for JoinedHandler<H0, H1>
-//todo
 where
     H0: GetStatus + Send + Sync,
     H1: GetStatus + Send + Sync,
@@ -167,6 +417,33 @@ where
     async fn is_good(&self) -> bool {
         self.0.is_good().await && self.1.is_good().await
     }
+    async fn get_bots(&self) -> Vec<Bot> {
+        merge_bots(self.0.get_bots().await, self.1.get_bots().await)
+    }
+}
+
+impl<H0, H1> GetStatus for JoinedActionHandler<H0, H1>
+where
+    H0: GetStatus + Send + Sync,
+    H1: GetStatus + Send + Sync,
+{
+    async fn is_good(&self) -> bool {
+        self.handlers.is_good().await
+    }
+    async fn get_bots(&self) -> Vec<Bot> {
+        self.handlers.get_bots().await
+    }
+}
+
+/// 合并 bot 列表，同一 bot 在任一后端在线即视为在线
+pub(crate) fn merge_bots(mut bots: Vec<Bot>, other: Vec<Bot>) -> Vec<Bot> {
+    for bot in other {
+        match bots.iter_mut().find(|b| b.selft == bot.selft) {
+            Some(b) => b.online |= bot.online,
+            None => bots.push(bot),
+        }
+    }
+    bots
 }
 
 impl<H0, H1> GetVersion for JoinedHandler<H0, H1>
@@ -178,6 +455,15 @@ where
     }
 }
 
+impl<H0, H1> GetVersion for JoinedActionHandler<H0, H1>
+where
+    H0: GetVersion,
+{
+    fn get_version(&self) -> Version {
+        self.handlers.get_version()
+    }
+}
+
 impl<AH0, AH1, E, A, R> ActionHandler<E, A, R> for JoinedHandler<AH0, AH1>
 where
     AH0: ActionHandler<E, A, R> + Send + Sync + 'static,
@@ -185,7 +471,7 @@ where
     AH1: ActionHandler<E, A, R> + Send + Sync + 'static,
     AH1::Config: Send + Sync + 'static,
     A: GetSelf + Send + Sync + 'static,
-    R: From<crate::resp::RespError>,
+    R: From<RespError>,
 {
     type Config = (AH0::Config, AH1::Config);
     async fn start<AH, EH>(
@@ -198,7 +484,20 @@ where
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
         let mut joins = self.0.start(ob, config.0).await?;
-        joins.extend(self.1.start(ob, config.1).await?.into_iter());
+        joins.extend(self.1.start(ob, config.1).await?);
+        Ok(joins)
+    }
+    async fn reload<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let mut joins = self.0.reload(ob, config.0).await?;
+        joins.extend(self.1.reload(ob, config.1).await?);
         Ok(joins)
     }
     async fn call<AH, EH>(&self, action: A, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
@@ -206,12 +505,11 @@ where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        if self.0.get_selfs().await.contains(&action.get_self()) {
-            self.0.call(action, ob).await
-        } else if self.1.get_selfs().await.contains(&action.get_self()) {
-            self.1.call(action, ob).await
-        } else {
-            Ok(crate::resp::resp_error::who_am_i("").into())
+        let selft = action.get_self();
+        match self.probe(&selft).await {
+            Some(0) => self.0.call(action, ob).await,
+            Some(_) => self.1.call(action, ob).await,
+            None => Ok(unknown_self(&selft).into()),
         }
     }
     async fn before_call_event<AH, EH>(&self, event: E, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<E>
@@ -253,3 +551,83 @@ where
         self.1.on_onebot_disconnect(ob).await
     }
 }
+
+impl<AH0, AH1, E, A, R> ActionHandler<E, A, R> for JoinedActionHandler<AH0, AH1>
+where
+    AH0: ActionHandler<E, A, R> + Send + Sync + 'static,
+    AH0::Config: Send + Sync + 'static,
+    AH1: ActionHandler<E, A, R> + Send + Sync + 'static,
+    AH1::Config: Send + Sync + 'static,
+    A: GetSelf + Send + Sync + 'static,
+    R: From<RespError>,
+{
+    type Config = (AH0::Config, AH1::Config);
+    async fn start<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.start(ob, config).await
+    }
+    async fn reload<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.reload(ob, config).await
+    }
+    async fn call<AH, EH>(&self, action: A, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let selft = action.get_self();
+        match self.route(&selft).await {
+            Some(0) => self.handlers.0.call(action, ob).await,
+            Some(_) => self.handlers.1.call(action, ob).await,
+            None => Ok(unknown_self(&selft).into()),
+        }
+    }
+    async fn before_call_event<AH, EH>(&self, event: E, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<E>
+    where
+        E: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.before_call_event(event, ob).await
+    }
+    async fn after_call_event<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.after_call_event(ob).await
+    }
+    async fn shutdown(&self) {
+        self.handlers.shutdown().await
+    }
+    async fn on_onebot_connect<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.router.clear();
+        self.handlers.on_onebot_connect(ob).await
+    }
+    async fn on_onebot_disconnect<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.router.clear();
+        self.handlers.on_onebot_disconnect(ob).await
+    }
+}
@@ -1,162 +1,300 @@
 use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 
 use crate::{
+    action::Action,
     event::{
-        self, new_event, Connect, DetailTypeLevel, Event, Group, Heartbeat, Message, MessageEvent,
-        Meta, Private,
+        self, new_event, Connect, DetailTypeLevel, Event, Group, Heartbeat, Message, Meta, Private,
+        ToEvent,
     },
-    prelude::{MsgSegment, Selft, Version},
-    segment::{Image, Mention, Reply, Text},
-    util::{new_uuid, PushToValueMap},
-    v11::{self, V11Event, V11MsgSegment, V11MsgType},
+    prelude::{MsgSegment, Resp, Segments, Selft, Version, WalleError, WalleResult},
+    segment::{Image, Mention, MentionAll, Reply, Text, Video, Voice},
+    structs::Status,
+    util::{new_uuid, ContentType, Echo, EchoS, Value, ValueMap, ValueMapExt},
+    v11::{self, V11Event, V11MsgSegment, V11MsgSubType},
 };
 
+/// v11 实现的平台名
+pub const V11_PLATFORM: &str = "qq";
+
+/// 无对应 v11 元事件，转换时以原名保留的 v12 元事件
+const V12_META: [&str; 2] = ["status_update", "version"];
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(untagged)]
-
 pub enum ComEvent {
     V12Event(Event),
     V11Event(V11Event),
 }
 
 impl ComEvent {
-    pub fn to_v11(self) -> V11Event {
-        dbg!(self.clone());
+    pub fn to_v11(self) -> WalleResult<V11Event> {
         match self {
-            ComEvent::V12Event(_) => todo!(),
-            ComEvent::V11Event(event) => event,
+            ComEvent::V12Event(event) => event.try_into(),
+            ComEvent::V11Event(event) => Ok(event),
         }
     }
+    /// 无连接状态的转换，实现名等信息未知
     pub fn to_v12(self) -> Event {
-        match self {
-            ComEvent::V12Event(event) => event,
-            ComEvent::V11Event(event) => {
-                dbg!(event.clone());
-                let event: Event = match event.post_type {
-                    v11::Post::Meta(meta_event) => match meta_event {
-                        v11::MetaEvent::HeartBeatEvent {
-                            interval,
-                            sub_type,
-                            status,
-                        } => new_event(
-                            new_uuid(),
-                            event.time as f64,
-                            Meta,
-                            Heartbeat { interval },
-                            (),
-                            (),
-                            (),
-                            HashMap::with_capacity(0),
-                        )
-                        .into(),
-                        v11::MetaEvent::LifecycleEvent { sub_type, status } => new_event(
-                            new_uuid(),
-                            event.time as f64,
-                            Meta,
-                            // TODO assert sub_type = connect
-                            Connect {
-                                version: Version {
-                                    implt: "walle-adapter".into(),
-                                    version: crate::VERSION.into(),
-                                    onebot_version: "12".into(),
-                                },
-                            },
-                            (),
-                            (),
-                            (),
-                            HashMap::with_capacity(0),
-                        )
-                        .into(),
-                    },
-                    v11::Post::Message(message) => match message {
-                        v11::MessageEvent::PrivateMessage {
-                            sub_type,
-                            message_id,
-                            user_id,
-                            message,
-                            raw_message,
-                            sender,
-                            target_id,
-                            temp_source,
-                            peer_id,
-                        } => new_event(
-                            new_uuid(),
-                            event.time as f64,
-                            Message {
-                                selft: Selft {
-                                    platform: "walle-adapter".into(), // TODO cache platform
-                                    user_id: event.self_id.to_string(),
-                                },
-                                message_id: message_id.to_string(),
-                                message: message
-                                    .into_iter()
-                                    .map(|msg| msg.trans(&sender.user_id.to_string()))
-                                    .collect(),
-                                alt_message: raw_message,
-                                user_id: user_id.to_string(),
-                            },
-                            Private,
-                            (),
-                            (),
-                            (),
-                            HashMap::with_capacity(0),
-                        )
-                        .into(),
-                        v11::MessageEvent::GroupMessage {
-                            sub_type,
-                            message_id,
-                            user_id,
-                            message,
-                            raw_message,
-                            sender,
-                            group_id,
-                            target_id,
-                            temp_source,
-                            peer_id,
-                        } => new_event(
-                            new_uuid(),
-                            event.time as f64,
-                            Message {
-                                selft: Selft {
-                                    platform: "walle-adapter".into(), // TODO cache platform
-                                    user_id: event.self_id.to_string(),
-                                },
-                                message_id: message_id.to_string(),
-                                message: message
-                                    .into_iter()
-                                    .map(|msg| msg.trans(&sender.user_id.to_string()))
-                                    .collect(),
-                                alt_message: raw_message,
-                                user_id: user_id.to_string(),
-                            },
-                            Group {
-                                group_id: group_id.unwrap_or_default().to_string(),
-                            },
-                            (),
-                            (),
-                            (),
-                            HashMap::with_capacity(0),
-                        )
-                        .into(),
-                    },
+        ConnState::default().to_v12(self)
+    }
+}
 
-                    v11::Post::MessageSent(_) => {
-                        todo!()
-                    }
-                    v11::Post::Notice(_) => {
-                        todo!()
-                    }
-                    v11::Post::Request(_) => {
-                        todo!()
-                    }
-                };
-                dbg!(event.clone());
-                return event;
-            }
+/// v11 `message_sent` 上报在 v12 中的 detail_type
+pub const MESSAGE_SENT: &str = "qq.message_sent";
+
+/// v12 中无对应字段的 v11 消息字段，以平台前缀保留于 extra
+const SENDER: &str = "qq.sender";
+const RAW_MESSAGE: &str = "qq.raw_message";
+const FONT: &str = "qq.font";
+const ANONYMOUS: &str = "qq.anonymous";
+
+fn message_to_v12(message: v11::MessageEvent, selft: Selft, time: f64) -> Event {
+    fn content(
+        selft: Selft,
+        message_id: i64,
+        user_id: i64,
+        message: Vec<V11MsgSegment>,
+    ) -> Message {
+        let message: Segments = message.into_iter().map(Into::into).collect();
+        Message {
+            selft,
+            message_id: message_id.to_string(),
+            alt_message: crate::segment::alt(&message),
+            message,
+            user_id: user_id.to_string(),
         }
     }
+    fn extra(
+        sender: v11::V11Sender,
+        raw_message: String,
+        font: Option<i32>,
+        anonymous: Option<JsonValue>,
+    ) -> ValueMap {
+        let mut extra = ValueMap::default();
+        extra.insert(SENDER.to_string(), json_to_value(serde_json::json!(sender)));
+        extra.insert(RAW_MESSAGE.to_string(), raw_message.into());
+        if let Some(font) = font {
+            extra.insert(FONT.to_string(), (font as i64).into());
+        }
+        if let Some(anonymous) = anonymous.filter(|a| !a.is_null()) {
+            extra.insert(ANONYMOUS.to_string(), json_to_value(anonymous));
+        }
+        extra
+    }
+    let (mut event, sub_type): (Event, _) = match message {
+        v11::MessageEvent::PrivateMessage {
+            sub_type,
+            message_id,
+            user_id,
+            message,
+            raw_message,
+            sender,
+            font,
+            ..
+        } => (
+            new_event(
+                new_uuid(),
+                time,
+                content(selft, message_id, user_id, message),
+                Private,
+                (),
+                (),
+                (),
+                extra(sender, raw_message, font, None),
+            )
+            .into(),
+            sub_type,
+        ),
+        v11::MessageEvent::GroupMessage {
+            sub_type,
+            message_id,
+            user_id,
+            message,
+            raw_message,
+            sender,
+            group_id,
+            font,
+            anonymous,
+            ..
+        } => (
+            new_event(
+                new_uuid(),
+                time,
+                content(selft, message_id, user_id, message),
+                Group {
+                    group_id: group_id.unwrap_or_default().to_string(),
+                },
+                (),
+                (),
+                (),
+                extra(sender, raw_message, font, anonymous),
+            )
+            .into(),
+            sub_type,
+        ),
+    };
+    if let Ok(JsonValue::String(sub_type)) = serde_json::to_value(sub_type) {
+        event.sub_type = sub_type;
+    }
+    event
+}
+
+fn notice_to_v12(notice: v11::NoticeEvent, selft: Selft, time: f64) -> Event {
+    fn new_notice<D: ToEvent<DetailTypeLevel>>(selft: Selft, time: f64, detail: D) -> Event {
+        new_event(
+            new_uuid(),
+            time,
+            event::Notice { selft },
+            detail,
+            (),
+            (),
+            (),
+            ValueMap::default(),
+        )
+        .into()
+    }
+    let (mut event, sub_type) = match notice {
+        v11::NoticeEvent::GroupIncrease {
+            sub_type,
+            group_id,
+            operator_id,
+            user_id,
+        } => (
+            new_notice(
+                selft,
+                time,
+                event::GroupMemberIncrease {
+                    group_id: group_id.to_string(),
+                    user_id: user_id.to_string(),
+                    operator_id: operator_id.to_string(),
+                },
+            ),
+            if sub_type == "approve" {
+                "join".to_string()
+            } else {
+                sub_type
+            },
+        ),
+        v11::NoticeEvent::GroupDecrease {
+            sub_type,
+            group_id,
+            operator_id,
+            user_id,
+        } => (
+            new_notice(
+                selft,
+                time,
+                event::GroupMemberDecrease {
+                    group_id: group_id.to_string(),
+                    user_id: user_id.to_string(),
+                    operator_id: operator_id.to_string(),
+                },
+            ),
+            sub_type,
+        ),
+        v11::NoticeEvent::GroupRecall {
+            group_id,
+            user_id,
+            operator_id,
+            message_id,
+        } => (
+            new_notice(
+                selft,
+                time,
+                event::GroupMessageDelete {
+                    group_id: group_id.to_string(),
+                    message_id: message_id.to_string(),
+                    user_id: user_id.to_string(),
+                    operator_id: operator_id.to_string(),
+                },
+            ),
+            if user_id == operator_id {
+                "recall".to_string()
+            } else {
+                "delete".to_string()
+            },
+        ),
+        v11::NoticeEvent::FriendAdd { user_id } => (
+            new_notice(
+                selft,
+                time,
+                event::FriendIncrease {
+                    user_id: user_id.to_string(),
+                },
+            ),
+            String::default(),
+        ),
+        v11::NoticeEvent::FriendRecall {
+            user_id,
+            message_id,
+        } => (
+            new_notice(
+                selft,
+                time,
+                event::PrivateMessageDelete {
+                    message_id: message_id.to_string(),
+                    user_id: user_id.to_string(),
+                },
+            ),
+            String::default(),
+        ),
+        v11::NoticeEvent::Other(map) => {
+            return extended_to_v12("notice", "notice_type", selft, time, map)
+        }
+        notice => {
+            return extended_to_v12("notice", "notice_type", selft, time, to_json_map(&notice))
+        }
+    };
+    event.sub_type = sub_type;
+    event
+}
+
+/// 无对应 v12 事件的上报，转为 `qq.` 前缀的扩展事件
+fn extended_to_v12(
+    ty: &str,
+    type_key: &str,
+    selft: Selft,
+    time: f64,
+    mut map: HashMap<String, JsonValue>,
+) -> Event {
+    let detail_type = match map.remove(type_key) {
+        Some(JsonValue::String(detail_type)) => format!("{}.{}", V11_PLATFORM, detail_type),
+        _ => format!("{}.unknown", V11_PLATFORM),
+    };
+    let sub_type = match map.remove("sub_type") {
+        Some(JsonValue::String(sub_type)) => sub_type,
+        _ => String::default(),
+    };
+    let mut extra: ValueMap = map
+        .into_iter()
+        .map(|(k, v)| {
+            let v = v12_field(&k, v);
+            (k, v)
+        })
+        .collect();
+    extra.insert("self".to_string(), selft.into());
+    Event {
+        id: new_uuid(),
+        time,
+        ty: ty.to_string(),
+        detail_type,
+        sub_type,
+        extra,
+    }
+}
+
+fn to_json_map<T: Serialize>(v: &T) -> HashMap<String, JsonValue> {
+    match serde_json::to_value(v) {
+        Ok(JsonValue::Object(map)) => map.into_iter().collect(),
+        _ => HashMap::default(),
+    }
+}
+
+fn json_to_value(v: JsonValue) -> Value {
+    serde_json::from_value(v).unwrap_or(Value::Null)
 }
 
 impl From<event::Event> for ComEvent {
@@ -171,18 +309,1031 @@ impl From<v11::V11Event> for ComEvent {
 }
 
 impl V11MsgSegment {
-    pub fn trans(self, sender_id: &String) -> MsgSegment {
-        match self {
+    #[deprecated(note = "use `MsgSegment::from` instead, sender_id is unused")]
+    pub fn trans(self, _sender_id: &str) -> MsgSegment {
+        self.into()
+    }
+}
+
+impl From<V11MsgSegment> for MsgSegment {
+    fn from(segment: V11MsgSegment) -> Self {
+        match segment {
             V11MsgSegment::Text { text } => Text { text }.into(),
-            V11MsgSegment::Face { id } => todo!(),
-            V11MsgSegment::Image { file, ty, url } => Image { file_id: file }.into(),
+            V11MsgSegment::Image { file, .. } => Image { file_id: file }.into(),
+            V11MsgSegment::At { qq } if qq == "all" => MentionAll {}.into(),
             V11MsgSegment::At { qq } => Mention { user_id: qq }.into(),
-            V11MsgSegment::Poke { ty, id, name } => todo!(),
             V11MsgSegment::Reply { id } => Reply {
                 message_id: id.to_string(),
                 user_id: None,
             }
             .into(),
+            V11MsgSegment::Record { file, .. } => Voice { file_id: file }.into(),
+            V11MsgSegment::Video { file, .. } => Video { file_id: file }.into(),
+            // face, poke, share, music, json, xml, forward, node 等转为 qq. 扩展消息段
+            segment => {
+                let mut map = to_json_map(&segment);
+                let ty = match map.remove("type") {
+                    Some(JsonValue::String(ty)) => ty,
+                    _ => String::default(),
+                };
+                MsgSegment {
+                    ty: format!("{}.{}", V11_PLATFORM, ty),
+                    data: match map.remove("data").map(json_to_value) {
+                        Some(Value::Map(data)) => data,
+                        _ => ValueMap::default(),
+                    },
+                }
+            }
+        }
+    }
+}
+
+impl From<MsgSegment> for V11MsgSegment {
+    /// 无法映射的消息段降级为其 alt 文本
+    fn from(segment: MsgSegment) -> Self {
+        let data = &segment.data;
+        let seg = match segment.ty.as_str() {
+            "text" => data
+                .get_downcast("text")
+                .map(|text| V11MsgSegment::Text { text }),
+            "mention" => data
+                .get_downcast("user_id")
+                .map(|qq| V11MsgSegment::At { qq }),
+            "mention_all" => Ok(V11MsgSegment::At { qq: "all".into() }),
+            "image" => data
+                .get_downcast("file_id")
+                .map(|file| V11MsgSegment::Image {
+                    file,
+                    ty: None,
+                    url: data.get_downcast("url").ok(),
+                }),
+            "reply" => data
+                .get_downcast::<String>("message_id")
+                .and_then(|id| id.parse().map_err(|_| WalleError::Other(id)))
+                .map(|id| V11MsgSegment::Reply { id }),
+            "voice" => data
+                .get_downcast("file_id")
+                .map(|file| V11MsgSegment::Record { file, url: None }),
+            "video" => data
+                .get_downcast("file_id")
+                .map(|file| V11MsgSegment::Video { file, url: None }),
+            ty if strip_platform(ty) != ty => serde_json::from_value(serde_json::json!({
+                "type": strip_platform(ty),
+                "data": data,
+            }))
+            .map_err(|e| WalleError::Other(e.to_string())),
+            ty => Err(WalleError::DeclareNotMatch("v11 segment", ty.to_string())),
+        };
+        seg.unwrap_or_else(|_| V11MsgSegment::Text {
+            text: segment.alt(),
+        })
+    }
+}
+
+impl TryFrom<Event> for V11Event {
+    type Error = WalleError;
+    fn try_from(mut event: Event) -> WalleResult<Self> {
+        let mut extra = HashMap::new();
+        extra.insert("id".to_string(), JsonValue::String(event.id.clone()));
+        let self_id = match event.extra.try_remove_downcast::<Selft>("self")? {
+            Some(selft) => {
+                if selft.platform != V11_PLATFORM {
+                    extra.insert("platform".to_string(), selft.platform.into());
+                }
+                parse_id("self_id", selft.user_id, &mut extra)
+            }
+            None => 0,
+        };
+        let post_type = match event.ty.as_str() {
+            "message" if event.detail_type == MESSAGE_SENT => {
+                event.detail_type = event.extra.remove_downcast("message_type")?;
+                v11::Post::MessageSent(message_to_v11(&mut event, self_id, &mut extra)?)
+            }
+            "message" => v11::Post::Message(message_to_v11(&mut event, self_id, &mut extra)?),
+            "notice" => v11::Post::Notice(typed_post(
+                post_map(
+                    "notice_type",
+                    notice_type_to_v11(&event.detail_type),
+                    notice_sub_type_to_v11(&event.detail_type, &event.sub_type),
+                    std::mem::take(&mut event.extra),
+                ),
+                v11::NoticeEvent::Other,
+            )),
+            "request" => v11::Post::Request(typed_post(
+                post_map(
+                    "request_type",
+                    strip_platform(&event.detail_type),
+                    &event.sub_type,
+                    std::mem::take(&mut event.extra),
+                ),
+                v11::RequestEvent::Other,
+            )),
+            "meta" => v11::Post::Meta(meta_to_v11(&mut event, self_id)?),
+            _ => {
+                return Err(WalleError::DeclareNotMatch(
+                    "message, notice, request or meta",
+                    event.ty,
+                ))
+            }
+        };
+        extra.extend(
+            event
+                .extra
+                .into_iter()
+                .map(|(k, v)| (k, serde_json::to_value(v).unwrap_or_default())),
+        );
+        Ok(V11Event {
+            time: event.time as i64,
+            self_id,
+            post_type,
+            extra,
+        })
+    }
+}
+
+/// v11 的 id 均为数字，无法解析时原值放入 extra
+fn parse_id(key: &str, id: String, extra: &mut HashMap<String, JsonValue>) -> i64 {
+    match id.parse() {
+        Ok(id) => id,
+        Err(_) => {
+            extra.insert(key.to_string(), JsonValue::String(id));
+            0
+        }
+    }
+}
+
+fn message_to_v11(
+    event: &mut Event,
+    self_id: i64,
+    extra: &mut HashMap<String, JsonValue>,
+) -> WalleResult<v11::MessageEvent> {
+    let message_id = parse_id(
+        "message_id",
+        event.extra.remove_downcast("message_id")?,
+        extra,
+    );
+    let user_id = parse_id("user_id", event.extra.remove_downcast("user_id")?, extra);
+    let message: Vec<V11MsgSegment> = event
+        .extra
+        .remove_downcast::<Segments>("message")?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let raw_message = match event.extra.try_remove_downcast(RAW_MESSAGE)? {
+        Some(raw_message) => raw_message,
+        None => crate::cq::render(&message),
+    };
+    event.extra.remove("alt_message");
+    let sender = match event.extra.remove(SENDER) {
+        Some(sender) => serde_json::to_value(sender)
+            .and_then(serde_json::from_value)
+            .map_err(|e| WalleError::Other(e.to_string()))?,
+        None => v11::V11Sender {
+            user_id,
+            nickname: String::default(),
+            extra: HashMap::default(),
+        },
+    };
+    let font = event
+        .extra
+        .try_remove_downcast::<i64>(FONT)?
+        .map(|font| font as i32);
+    let sub_type = if event.sub_type.is_empty() {
+        None
+    } else {
+        match serde_json::from_value(JsonValue::String(event.sub_type.clone())) {
+            Ok(sub_type) => Some(sub_type),
+            Err(_) => {
+                extra.insert("sub_type".to_string(), event.sub_type.clone().into());
+                None
+            }
+        }
+    };
+    match event.detail_type.as_str() {
+        "private" => Ok(v11::MessageEvent::PrivateMessage {
+            sub_type: sub_type.unwrap_or(V11MsgSubType::Friend),
+            message_id,
+            user_id,
+            message,
+            raw_message,
+            sender,
+            target_id: None,
+            temp_source: None,
+            peer_id: self_id,
+            font,
+        }),
+        "group" => {
+            let group_id = parse_id("group_id", event.extra.remove_downcast("group_id")?, extra);
+            Ok(v11::MessageEvent::GroupMessage {
+                sub_type: sub_type.unwrap_or(V11MsgSubType::Normal),
+                message_id,
+                user_id,
+                message,
+                raw_message,
+                sender,
+                group_id: Some(group_id),
+                target_id: None,
+                temp_source: None,
+                peer_id: group_id,
+                font,
+                anonymous: event
+                    .extra
+                    .remove(ANONYMOUS)
+                    .map(serde_json::to_value)
+                    .transpose()
+                    .map_err(|e| WalleError::Other(e.to_string()))?,
+            })
         }
+        detail_type => Err(WalleError::DeclareNotMatch(
+            "private or group",
+            detail_type.to_string(),
+        )),
     }
 }
+
+fn meta_to_v11(event: &mut Event, self_id: i64) -> WalleResult<v11::MetaEvent> {
+    match event.detail_type.as_str() {
+        "heartbeat" => Ok(v11::MetaEvent::HeartBeatEvent {
+            interval: event.extra.remove_downcast("interval")?,
+            sub_type: event.sub_type.clone(),
+            status: status_to_v11(event.extra.try_remove_downcast("status")?, self_id),
+        }),
+        "connect" => Ok(v11::MetaEvent::LifecycleEvent {
+            sub_type: "connect".to_string(),
+            status: None,
+        }),
+        // 无对应 v11 元事件，其余字段随 extra 保留
+        detail_type => Ok(v11::MetaEvent::Other(HashMap::from([(
+            "meta_event_type".to_string(),
+            detail_type.into(),
+        )]))),
+    }
+}
+
+fn status_to_v11(status: Option<Status>, self_id: i64) -> v11::Status {
+    match status {
+        Some(status) => v11::Status {
+            good: status.good,
+            online: status.bots.iter().any(|bot| bot.online),
+            qq_status: None,
+            bot_self: status
+                .bots
+                .into_iter()
+                .find(|bot| bot.selft.user_id == self_id.to_string())
+                .map(|bot| v11::BotSelf {
+                    platform: bot.selft.platform,
+                    user_id: self_id,
+                }),
+        },
+        None => v11::Status {
+            good: true,
+            online: true,
+            qq_status: None,
+            bot_self: None,
+        },
+    }
+}
+
+fn notice_type_to_v11(detail_type: &str) -> &str {
+    match detail_type {
+        "group_member_increase" => "group_increase",
+        "group_member_decrease" => "group_decrease",
+        "group_message_delete" => "group_recall",
+        "friend_increase" => "friend_add",
+        "private_message_delete" => "friend_recall",
+        detail_type => strip_platform(detail_type),
+    }
+}
+
+fn strip_platform(detail_type: &str) -> &str {
+    detail_type
+        .strip_prefix(V11_PLATFORM)
+        .and_then(|s| s.strip_prefix('.'))
+        .unwrap_or(detail_type)
+}
+
+fn notice_sub_type_to_v11<'a>(detail_type: &str, sub_type: &'a str) -> &'a str {
+    match (detail_type, sub_type) {
+        ("group_member_increase", "join") => "approve",
+        (_, sub_type) => sub_type,
+    }
+}
+
+/// 字段不符合已知上报类型时保留为 `Other`
+fn typed_post<T: serde::de::DeserializeOwned>(
+    map: HashMap<String, JsonValue>,
+    other: fn(HashMap<String, JsonValue>) -> T,
+) -> T {
+    serde_json::from_value(JsonValue::Object(map.clone().into_iter().collect()))
+        .unwrap_or_else(|_| other(map))
+}
+
+/// notice 与 request 在 v11 中为扁平结构，`*_id` 字段转为数字
+fn post_map(
+    type_key: &str,
+    ty: &str,
+    sub_type: &str,
+    extra: ValueMap,
+) -> HashMap<String, JsonValue> {
+    let mut map: HashMap<String, JsonValue> = extra
+        .into_iter()
+        .map(|(k, v)| {
+            let v = v11_field(&k, v);
+            (k, v)
+        })
+        .collect();
+    map.insert(type_key.to_string(), ty.into());
+    if !sub_type.is_empty() {
+        map.insert("sub_type".to_string(), sub_type.into());
+    }
+    map
+}
+
+/// v11 的 `*_id` 字段为数字
+fn v11_field(key: &str, v: Value) -> JsonValue {
+    match v {
+        Value::Str(s) if key.ends_with("_id") && s.is_empty() => 0.into(),
+        Value::Str(s) if key.ends_with("_id") => match s.parse::<i64>() {
+            Ok(id) => id.into(),
+            Err(_) => s.into(),
+        },
+        v => serde_json::to_value(v).unwrap_or_default(),
+    }
+}
+
+/// v12 的 `*_id` 字段为字符串，递归处理 map 与 list
+fn v12_field(key: &str, v: JsonValue) -> Value {
+    match v {
+        JsonValue::Number(id) if key.ends_with("_id") => Value::Str(id.to_string()),
+        JsonValue::Object(map) => Value::Map(
+            map.into_iter()
+                .map(|(k, v)| {
+                    let v = v12_field(&k, v);
+                    (k, v)
+                })
+                .collect(),
+        ),
+        JsonValue::Array(list) => {
+            Value::List(list.into_iter().map(|v| v12_field(key, v)).collect())
+        }
+        v => json_to_value(v),
+    }
+}
+
+/// 连接所使用的 OneBot 协议版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    V12,
+    V11,
+}
+
+/// 单个连接的协议状态
+///
+/// 收到 v11 事件后切换为 v11 模式，此后动作与响应均经过转换
+#[derive(Debug)]
+pub struct ConnState {
+    pub protocol: Protocol,
+    /// v11 机器人平台，默认为 qq
+    pub platform: String,
+    /// 实现名，未知时为空
+    pub implt: String,
+    /// v11 实现版本，未知时为空
+    pub version: String,
+    /// v11 机器人 self_id
+    pub self_id: Option<i64>,
+    /// 发送动作使用的编码，未指定时跟随实现端最近所发消息
+    pub content_type: Option<ContentType>,
+    mirrored: ContentType,
+    // v11 响应不携带动作名，按 echo 暂存
+    actions: HashMap<EchoS, String>,
+}
+
+impl Default for ConnState {
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::default(),
+            platform: V11_PLATFORM.to_string(),
+            implt: String::default(),
+            version: String::default(),
+            self_id: None,
+            content_type: None,
+            mirrored: ContentType::default(),
+            actions: HashMap::default(),
+        }
+    }
+}
+
+impl ConnState {
+    /// 由 v11 反向 ws 握手头 `X-Self-ID` 与 `User-Agent` 构造
+    pub fn from_handshake(self_id: Option<&str>, user_agent: Option<&str>) -> Self {
+        let mut state = Self::default();
+        if let Some(self_id) = self_id.and_then(|id| id.parse().ok()) {
+            state.protocol = Protocol::V11;
+            state.self_id = Some(self_id);
+        }
+        if let Some((implt, version)) = user_agent
+            .and_then(|ua| ua.split_whitespace().next())
+            .and_then(|ua| ua.split_once('/'))
+        {
+            state.implt = implt.to_string();
+            state.version = version.to_string();
+        }
+        state
+    }
+    /// 记录实现端所发消息的编码
+    pub fn received(&mut self, content_type: ContentType) {
+        self.mirrored = content_type;
+    }
+    /// 发送动作应使用的编码，v11 仅支持 JSON
+    pub fn send_content_type(&self) -> ContentType {
+        match self.protocol {
+            Protocol::V11 => ContentType::Json,
+            Protocol::V12 => self.content_type.unwrap_or(self.mirrored),
+        }
+    }
+    pub fn selft(&self) -> Option<Selft> {
+        self.self_id.map(|user_id| Selft {
+            platform: self.platform.clone(),
+            user_id: user_id.to_string(),
+        })
+    }
+    pub fn get_version(&self) -> Version {
+        Version {
+            implt: self.implt.clone(),
+            version: self.version.clone(),
+            onebot_version: "11".to_string(),
+        }
+    }
+    /// 记录 v11 连接状态并转换为 v12 事件
+    pub fn to_v12(&mut self, event: ComEvent) -> Event {
+        let event = match event {
+            ComEvent::V12Event(event) => return event,
+            ComEvent::V11Event(event) => event,
+        };
+        self.protocol = Protocol::V11;
+        self.self_id = Some(event.self_id);
+        let status = match &event.post_type {
+            v11::Post::Meta(v11::MetaEvent::HeartBeatEvent { status, .. }) => Some(status),
+            v11::Post::Meta(v11::MetaEvent::LifecycleEvent { status, .. }) => status.as_ref(),
+            _ => None,
+        };
+        if let Some(bot_self) = status.and_then(|status| status.bot_self.as_ref()) {
+            self.platform = bot_self.platform.clone();
+        }
+        let selft = Selft {
+            platform: self.platform.clone(),
+            user_id: event.self_id.to_string(),
+        };
+        let time = event.time as f64;
+        let mut v12: Event = match event.post_type {
+            v11::Post::Meta(v11::MetaEvent::HeartBeatEvent { interval, .. }) => new_event(
+                new_uuid(),
+                time,
+                Meta,
+                Heartbeat { interval },
+                (),
+                (),
+                (),
+                ValueMap::default(),
+            )
+            .into(),
+            v11::Post::Meta(v11::MetaEvent::LifecycleEvent { .. }) => new_event(
+                new_uuid(),
+                time,
+                Meta,
+                Connect {
+                    version: self.get_version(),
+                },
+                (),
+                (),
+                (),
+                ValueMap::default(),
+            )
+            .into(),
+            v11::Post::Meta(v11::MetaEvent::Other(mut map)) => {
+                match map.remove("meta_event_type") {
+                    Some(JsonValue::String(detail_type))
+                        if V12_META.contains(&detail_type.as_str()) =>
+                    {
+                        Event {
+                            id: new_uuid(),
+                            time,
+                            ty: "meta".to_string(),
+                            detail_type,
+                            sub_type: String::default(),
+                            extra: map
+                                .into_iter()
+                                .map(|(k, v)| (k, json_to_value(v)))
+                                .collect(),
+                        }
+                    }
+                    detail_type => {
+                        if let Some(detail_type) = detail_type {
+                            map.insert("meta_event_type".to_string(), detail_type);
+                        }
+                        extended_to_v12("meta", "meta_event_type", selft, time, map)
+                    }
+                }
+            }
+            v11::Post::Message(message) => message_to_v12(message, selft, time),
+            v11::Post::MessageSent(message) => {
+                let mut event = message_to_v12(message, selft, time);
+                let message_type =
+                    std::mem::replace(&mut event.detail_type, MESSAGE_SENT.to_string());
+                event
+                    .extra
+                    .insert("message_type".to_string(), message_type.into());
+                event
+            }
+            v11::Post::Notice(notice) => notice_to_v12(notice, selft, time),
+            v11::Post::Request(request) => extended_to_v12(
+                "request",
+                "request_type",
+                selft,
+                time,
+                to_json_map(&request),
+            ),
+        };
+        for (k, v) in event.extra {
+            match v {
+                JsonValue::String(id) if k == "id" => v12.id = id,
+                v => {
+                    v12.extra.entry(k).or_insert_with(|| json_to_value(v));
+                }
+            }
+        }
+        v12
+    }
+    pub fn action_to_v11<A: Serialize>(
+        &mut self,
+        action: Echo<A>,
+    ) -> WalleResult<Echo<v11::Request>> {
+        let (action, echo) = action.unpack();
+        let action: Action = serde_json::from_value(
+            serde_json::to_value(action).map_err(|e| WalleError::Other(e.to_string()))?,
+        )
+        .map_err(|e| WalleError::Other(e.to_string()))?;
+        self.actions.insert(echo.clone(), action.action.clone());
+        Ok(echo.pack(action.into()))
+    }
+    /// 仅保留仍在等待响应的 v11 动作
+    pub fn retain_actions(&mut self, mut f: impl FnMut(&EchoS) -> bool) {
+        self.actions.retain(|echo, _| f(echo));
+    }
+    pub fn resp_to_v12(&mut self, resp: Echo<v11::Response>) -> (Resp, EchoS) {
+        let (resp, echo) = resp.unpack();
+        let action = self.actions.remove(&echo).unwrap_or_default();
+        (resp_to_v12(&action, resp), echo)
+    }
+}
+
+impl From<Action> for v11::Request {
+    fn from(action: Action) -> Self {
+        let name = match action.action.as_str() {
+            "send_message" => "send_msg",
+            "delete_message" => "delete_msg",
+            "get_self_info" => "get_login_info",
+            "get_user_info" => "get_stranger_info",
+            "leave_group" => "set_group_leave",
+            "get_version" => "get_version_info",
+            name => strip_platform(name),
+        };
+        let params = action
+            .params
+            .into_iter()
+            .map(|(k, v)| match (k.as_str(), v) {
+                ("detail_type", v) => ("message_type".to_string(), v11_field(&k, v)),
+                ("message", Value::List(segments)) => (
+                    k,
+                    segments
+                        .into_iter()
+                        .filter_map(|seg| MsgSegment::try_from(seg).ok())
+                        .map(|seg| serde_json::to_value(V11MsgSegment::from(seg)))
+                        .collect::<Result<_, _>>()
+                        .unwrap_or_default(),
+                ),
+                (_, v) => {
+                    let v = v11_field(&k, v);
+                    (k, v)
+                }
+            })
+            .collect::<serde_json::Map<_, _>>();
+        Self {
+            action: name.to_string(),
+            params: JsonValue::Object(params),
+        }
+    }
+}
+
+/// `action` 为发出请求时 v12 的动作名，用于补全 v12 响应字段
+pub fn resp_to_v12(action: &str, resp: v11::Response) -> Resp {
+    let (status, retcode) = match (resp.status.as_str(), resp.retcode) {
+        ("ok" | "async", _) => ("ok", 0),
+        (_, 1404) => ("failed", 10002),
+        (_, 100) => ("failed", 10003),
+        _ => ("failed", 20002),
+    };
+    let mut data = v12_field("", resp.data);
+    match (action, &mut data) {
+        ("send_message", Value::Map(map)) => {
+            map.entry("time".to_string())
+                .or_insert_with(|| Value::F64(crate::util::timestamp_nano_f64()));
+        }
+        ("get_self_info" | "get_user_info" | "get_group_member_info", Value::Map(map)) => {
+            user_info_to_v12(map)
+        }
+        ("get_friend_list" | "get_group_member_list", Value::List(list)) => {
+            for user in list {
+                if let Value::Map(map) = user {
+                    user_info_to_v12(map)
+                }
+            }
+        }
+        _ => {}
+    }
+    Resp {
+        status: status.to_string(),
+        retcode,
+        data,
+        message: resp.wording.or(resp.msg).unwrap_or_default(),
+    }
+}
+
+fn user_info_to_v12(map: &mut ValueMap) {
+    let nickname = map
+        .get("nickname")
+        .cloned()
+        .unwrap_or(Value::Str(String::default()));
+    let card = map
+        .get("card")
+        .cloned()
+        .unwrap_or(Value::Str(String::default()));
+    let remark = map
+        .get("remark")
+        .cloned()
+        .unwrap_or(Value::Str(String::default()));
+    map.entry("user_name".to_string()).or_insert(nickname);
+    map.entry("user_displayname".to_string()).or_insert(card);
+    map.entry("user_remark".to_string()).or_insert(remark);
+}
+
+#[test]
+fn message_to_v11_test() {
+    use crate::value_map;
+    let event: Event = new_event(
+        "id".to_string(),
+        1632847927.0,
+        Message {
+            selft: Selft {
+                platform: V11_PLATFORM.to_string(),
+                user_id: "10000".to_string(),
+            },
+            message_id: "123".to_string(),
+            message: vec![
+                Text {
+                    text: "hello".to_string(),
+                }
+                .into(),
+                Mention {
+                    user_id: "20000".to_string(),
+                }
+                .into(),
+                MsgSegment {
+                    ty: "location".to_string(),
+                    data: value_map! { "title": "home" },
+                },
+            ],
+            alt_message: "hello".to_string(),
+            user_id: "20000".to_string(),
+        },
+        Group {
+            group_id: "30000".to_string(),
+        },
+        (),
+        (),
+        (),
+        value_map! { "qq.font": 1 },
+    )
+    .into();
+    let v11 = ComEvent::from(event).to_v11().unwrap();
+    assert_eq!(
+        serde_json::to_value(v11).unwrap(),
+        serde_json::json!({
+            "time": 1632847927,
+            "self_id": 10000,
+            "post_type": "message",
+            "message_type": "group",
+            "sub_type": "normal",
+            "message_id": 123,
+            "user_id": 20000,
+            "message": [
+                { "type": "text", "data": { "text": "hello" } },
+                { "type": "at", "data": { "qq": "20000" } },
+                { "type": "text", "data": { "text": "[location,\"title\":\"home\"]" } },
+            ],
+            "raw_message": "hello[CQ:at,qq=20000]&#91;location,\"title\":\"home\"&#93;",
+            "sender": { "user_id": 20000, "nickname": "" },
+            "group_id": 30000,
+            "target_id": null,
+            "temp_source": null,
+            "peer_id": 30000,
+            "font": 1,
+            "extra": { "id": "id" },
+        })
+    );
+}
+
+#[test]
+fn notice_to_v11_test() {
+    use crate::value_map;
+    let event: Event = new_event(
+        "id".to_string(),
+        1632847927.0,
+        event::Notice {
+            selft: Selft {
+                platform: "tg".to_string(),
+                user_id: "bot".to_string(),
+            },
+        },
+        event::GroupMemberIncrease {
+            group_id: "30000".to_string(),
+            user_id: "20000".to_string(),
+            operator_id: "".to_string(),
+        },
+        (),
+        (),
+        (),
+        value_map!(),
+    )
+    .into();
+    let mut event = event;
+    event.sub_type = "join".to_string();
+    let v11 = V11Event::try_from(event).unwrap();
+    assert_eq!(
+        serde_json::to_value(v11).unwrap(),
+        serde_json::json!({
+            "time": 1632847927,
+            "self_id": 0,
+            "post_type": "notice",
+            "notice_type": "group_increase",
+            "sub_type": "approve",
+            "group_id": 30000,
+            "user_id": 20000,
+            "operator_id": 0,
+            "extra": { "id": "id", "platform": "tg", "self_id": "bot" },
+        })
+    );
+}
+
+#[test]
+fn meta_to_v11_test() {
+    use crate::value_map;
+    let event = Event {
+        id: "id".to_string(),
+        time: 1632847927.0,
+        ty: "meta".to_string(),
+        detail_type: "status_update".to_string(),
+        sub_type: "".to_string(),
+        extra: value_map! {"status": {"good": true, "bots": []}},
+    };
+    let v11 = serde_json::to_value(V11Event::try_from(event).unwrap()).unwrap();
+    assert_eq!(
+        v11,
+        serde_json::json!({
+            "time": 1632847927,
+            "self_id": 0,
+            "post_type": "meta_event",
+            "meta_event_type": "status_update",
+            "extra": { "id": "id", "status": {"good": true, "bots": []} },
+        })
+    );
+    let event = serde_json::from_value::<ComEvent>(v11).unwrap().to_v12();
+    assert_eq!(event.id, "id");
+    assert_eq!(event.detail_type, "status_update");
+    assert_eq!(
+        event.extra.get("status"),
+        Some(&crate::value!({"good": true, "bots": []}))
+    );
+}
+
+#[test]
+fn message_to_v12_test() {
+    let raw = serde_json::json!({
+        "time": 1632847927,
+        "self_id": 10000,
+        "post_type": "message",
+        "message_type": "group",
+        "sub_type": "anonymous",
+        "message_id": 123,
+        "user_id": 80000000,
+        "message": [{ "type": "text", "data": { "text": "hello" } }],
+        "raw_message": "&#91;hello&#93;",
+        "sender": { "user_id": 80000000, "nickname": "", "card": "匿名", "role": "member" },
+        "group_id": 30000,
+        "target_id": null,
+        "temp_source": null,
+        "peer_id": 30000,
+        "font": 1,
+        "anonymous": { "id": 1, "name": "匿名", "flag": "flag" },
+    });
+    let event: ComEvent = serde_json::from_value(raw.clone()).unwrap();
+    let event = event.to_v12();
+    assert_eq!(event.detail_type, "group");
+    assert_eq!(event.sub_type, "anonymous");
+    assert_eq!(
+        event.extra.get_downcast::<String>(RAW_MESSAGE).unwrap(),
+        "&#91;hello&#93;"
+    );
+    assert_eq!(event.extra.get_downcast::<i64>(FONT).unwrap(), 1);
+    let sender = serde_json::to_value(event.extra.get(SENDER)).unwrap();
+    assert_eq!(sender["card"], "匿名");
+    let mut v11 = serde_json::to_value(V11Event::try_from(event).unwrap()).unwrap();
+    v11.as_object_mut().unwrap().remove("extra");
+    assert_eq!(v11, raw);
+}
+
+#[test]
+fn notice_to_v12_test() {
+    let event: ComEvent = serde_json::from_str(
+        r#"{"time":1632847927,"self_id":10000,"post_type":"notice","notice_type":"group_increase","sub_type":"approve","group_id":30000,"operator_id":0,"user_id":20000}"#,
+    )
+    .unwrap();
+    let event = event.to_v12();
+    assert_eq!(event.ty, "notice");
+    assert_eq!(event.detail_type, "group_member_increase");
+    assert_eq!(event.sub_type, "join");
+    let notice: event::GroupMemberIncreaseEvent = event.clone().try_into().unwrap();
+    assert_eq!(notice.detail_type.user_id, "20000");
+    assert_eq!(
+        V11Event::try_from(event).unwrap().post_type,
+        v11::Post::Notice(v11::NoticeEvent::GroupIncrease {
+            sub_type: "approve".to_string(),
+            group_id: 30000,
+            operator_id: 0,
+            user_id: 20000,
+        })
+    );
+
+    let event: ComEvent = serde_json::from_str(
+        r#"{"time":1632847927,"self_id":10000,"post_type":"notice","notice_type":"notify","sub_type":"poke","group_id":30000,"user_id":20000,"target_id":10000}"#,
+    )
+    .unwrap();
+    let event = event.to_v12();
+    assert_eq!(event.detail_type, "qq.notify");
+    assert_eq!(event.sub_type, "poke");
+    assert_eq!(
+        event.extra.get("target_id"),
+        Some(&Value::Str("10000".to_string()))
+    );
+}
+
+#[test]
+fn request_to_v12_test() {
+    let event: ComEvent = serde_json::from_str(
+        r#"{"time":1632847927,"self_id":10000,"post_type":"request","request_type":"friend","user_id":20000,"comment":"hi","flag":"flag"}"#,
+    )
+    .unwrap();
+    let event = event.to_v12();
+    assert_eq!(event.ty, "request");
+    assert_eq!(event.detail_type, "qq.friend");
+    assert_eq!(event.self_id(), Some("10000".to_string()));
+    assert_eq!(
+        V11Event::try_from(event).unwrap().post_type,
+        v11::Post::Request(v11::RequestEvent::Friend {
+            user_id: 20000,
+            comment: "hi".to_string(),
+            flag: "flag".to_string(),
+        })
+    );
+}
+
+#[test]
+fn action_to_v11_test() {
+    use crate::value_map;
+    let mut conn = ConnState::default();
+    let action = Action {
+        action: "send_message".to_string(),
+        params: value_map! {
+            "detail_type": "group",
+            "group_id": "30000",
+            "message": [{ "type": "text", "data": { "text": "hello" } }]
+        },
+        selft: None,
+    };
+    let action = conn.action_to_v11(EchoS::new("test").pack(action)).unwrap();
+    assert_eq!(
+        serde_json::to_value(&action.inner).unwrap(),
+        serde_json::json!({
+            "action": "send_msg",
+            "params": {
+                "message_type": "group",
+                "group_id": 30000,
+                "message": [{ "type": "text", "data": { "text": "hello" } }]
+            }
+        })
+    );
+
+    let resp: Echo<v11::Response> = serde_json::from_value(serde_json::json!({
+        "status": "ok",
+        "retcode": 0,
+        "data": { "message_id": 123 },
+        "echo": action.echo,
+    }))
+    .unwrap();
+    let (resp, _) = conn.resp_to_v12(resp);
+    let resp: crate::structs::SendMessageResp = resp.as_result_downcast().unwrap();
+    assert_eq!(resp.message_id, "123");
+
+    // 超时的动作不再保留
+    let echo = EchoS::new("timeout");
+    let action = Action {
+        action: "get_self_info".to_string(),
+        params: value_map! {},
+        selft: None,
+    };
+    conn.action_to_v11(echo.clone().pack(action)).unwrap();
+    assert_eq!(conn.actions.len(), 1);
+    conn.retain_actions(|e| *e != echo);
+    assert!(conn.actions.is_empty());
+
+    let resp = resp_to_v12(
+        "get_self_info",
+        serde_json::from_str(
+            r#"{"status":"failed","retcode":1404,"msg":"API_NOT_FOUND","wording":"API不存在"}"#,
+        )
+        .unwrap(),
+    );
+    assert_eq!(resp.retcode, 10002);
+    assert_eq!(resp.message, "API不存在");
+}
+
+#[test]
+fn conn_state_test() {
+    let mut conn = ConnState::from_handshake(Some("10000"), Some("CQHttp/4.15.0 (Linux)"));
+    assert_eq!(conn.protocol, Protocol::V11);
+    assert_eq!(
+        conn.selft(),
+        Some(Selft {
+            platform: V11_PLATFORM.to_string(),
+            user_id: "10000".to_string(),
+        })
+    );
+    let event: ComEvent = serde_json::from_str(
+        r#"{"time":1632847927,"self_id":10001,"post_type":"meta_event","meta_event_type":"lifecycle","sub_type":"connect"}"#,
+    )
+    .unwrap();
+    let event: event::ConnectEvent = conn.to_v12(event).try_into().unwrap();
+    assert_eq!(event.detail_type.version.implt, "CQHttp");
+    assert_eq!(event.detail_type.version.version, "4.15.0");
+    assert_eq!(conn.self_id, Some(10001));
+}
+
+#[test]
+fn segment_test() {
+    let segments: Vec<V11MsgSegment> = serde_json::from_str(
+        r#"[
+            {"type":"face","data":{"id":"1"}},
+            {"type":"record","data":{"file":"a.amr","magic":"0"}},
+            {"type":"poke","data":{"qq":"10000"}},
+            {"type":"forward","data":{"id":"abc"}},
+            {"type":"dice","data":{}}
+        ]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        segments[2],
+        V11MsgSegment::Unknown {
+            ty: "poke".to_string(),
+            data: serde_json::json!({ "qq": "10000" }),
+        }
+    );
+    let v12: Segments = segments.clone().into_iter().map(Into::into).collect();
+    assert_eq!(
+        v12.iter().map(|seg| seg.ty.as_str()).collect::<Vec<_>>(),
+        vec!["qq.face", "voice", "qq.poke", "qq.forward", "qq.dice"]
+    );
+    let v11: Vec<V11MsgSegment> = v12.into_iter().map(Into::into).collect();
+    assert_eq!(
+        v11[1],
+        V11MsgSegment::Record {
+            file: "a.amr".to_string(),
+            url: None
+        }
+    );
+    assert_eq!(v11[0], segments[0]);
+    assert_eq!(v11[2..], segments[2..]);
+}
+
+#[test]
+fn conn_content_type() {
+    let mut conn = ConnState::default();
+    assert_eq!(conn.send_content_type(), ContentType::Json);
+    conn.received(ContentType::MsgPack);
+    assert_eq!(conn.send_content_type(), ContentType::MsgPack);
+    conn.content_type = Some(ContentType::Json);
+    assert_eq!(conn.send_content_type(), ContentType::Json);
+    conn.content_type = Some(ContentType::MsgPack);
+    conn.protocol = Protocol::V11;
+    assert_eq!(conn.send_content_type(), ContentType::Json);
+}
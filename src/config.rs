@@ -1,17 +1,25 @@
 //! OBC 配置项
 
-use std::{collections::HashMap, fmt::Debug};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    time::Duration,
+};
 
 use serde::{Deserialize, Serialize};
 
+use crate::util::ContentType;
+
 /// OneBot 实现端设置项
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct ImplConfig {
     pub http: Vec<HttpServer>,
     pub http_webhook: Vec<HttpClient>,
     pub websocket: Vec<WebSocketServer>,
     pub websocket_rev: Vec<WebSocketClient>,
     pub heartbeat: Heartbeat,
+    #[serde(default)]
+    pub action_timeout: ActionTimeout,
 }
 
 impl Default for ImplConfig {
@@ -22,14 +30,15 @@ impl Default for ImplConfig {
             http_webhook: vec![],
             websocket: vec![],
             websocket_rev: vec![WebSocketClient::default()],
+            action_timeout: ActionTimeout::default(),
         }
     }
 }
 
 /// OneBot 心跳设置
 ///
-/// 间隔为 0 则默认为 4
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// 间隔单位为秒，为 0 则默认为 4
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
 pub struct Heartbeat {
     pub enabled: bool,
     pub interval: u32,
@@ -45,13 +54,15 @@ impl Default for Heartbeat {
 }
 
 /// OneBot 应用端设置项
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct AppConfig {
     pub block_meta_event: Option<bool>,
     pub http_webhook: Vec<HttpServer>,
     pub websocket: Vec<WebSocketClient>,
     pub websocket_rev: Vec<WebSocketServer>,
     pub http: HashMap<String, HttpClient>,
+    #[serde(default)]
+    pub action_timeout: ActionTimeout,
 }
 
 impl Default for AppConfig {
@@ -62,6 +73,7 @@ impl Default for AppConfig {
             http_webhook: vec![],
             websocket: vec![],
             websocket_rev: vec![WebSocketServer::default()],
+            action_timeout: ActionTimeout::default(),
         }
     }
 }
@@ -74,20 +86,22 @@ impl AppConfig {
             http_webhook: vec![],
             websocket: vec![],
             websocket_rev: vec![],
+            action_timeout: ActionTimeout::default(),
         }
     }
 }
 
 /// OneBot Impl Http 通讯设置
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub struct HttpServer {
     pub host: std::net::IpAddr,
     pub port: u16,
     pub access_token: Option<String>,
-    #[cfg(feature = "impl")]
-    pub event_enable: bool,
-    #[cfg(feature = "impl")]
-    pub event_buffer_size: usize,
+    /// 编码格式，未指定时跟随对端
+    #[serde(default)]
+    pub content_type: Option<ContentType>,
+    #[serde(default)]
+    pub action_timeout: ActionTimeout,
 }
 
 impl Default for HttpServer {
@@ -96,23 +110,28 @@ impl Default for HttpServer {
             host: std::net::IpAddr::from([127, 0, 0, 1]),
             port: 6700,
             access_token: None,
-            #[cfg(feature = "impl")]
-            event_enable: true,
-            #[cfg(feature = "impl")]
-            event_buffer_size: 16,
+            content_type: None,
+            action_timeout: ActionTimeout::default(),
         }
     }
 }
 
 /// OneBot Impl Http Webhook 通讯设置
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub struct HttpClient {
     #[serde(rename = "impl")]
     pub implt: Option<String>,
     pub platform: Option<String>,
     pub url: String,
     pub access_token: Option<String>,
+    /// 编码格式，未指定时跟随对端
+    #[serde(default)]
+    pub content_type: Option<ContentType>,
     pub timeout: u64,
+    #[serde(default)]
+    pub action_timeout: ActionTimeout,
+    #[serde(default)]
+    pub event_buffer: EventBuffer,
 }
 
 impl Default for HttpClient {
@@ -122,17 +141,27 @@ impl Default for HttpClient {
             platform: None,
             url: "http://127.0.0.1:6700".to_owned(),
             access_token: None,
+            content_type: None,
             timeout: 4,
+            action_timeout: ActionTimeout::default(),
+            event_buffer: EventBuffer::default(),
         }
     }
 }
 
 /// OneBot WebSocket 服务器设置
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub struct WebSocketServer {
     pub host: std::net::IpAddr,
     pub port: u16,
     pub access_token: Option<String>,
+    /// 编码格式，未指定时跟随对端
+    #[serde(default)]
+    pub content_type: Option<ContentType>,
+    #[serde(default)]
+    pub action_timeout: ActionTimeout,
+    #[serde(default)]
+    pub event_buffer: EventBuffer,
 }
 
 impl Default for WebSocketServer {
@@ -141,16 +170,26 @@ impl Default for WebSocketServer {
             host: std::net::IpAddr::from([127, 0, 0, 1]),
             port: 8844,
             access_token: None,
+            content_type: None,
+            action_timeout: ActionTimeout::default(),
+            event_buffer: EventBuffer::default(),
         }
     }
 }
 
 /// OneBot Impl 反向 WebSocket 通讯设置
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub struct WebSocketClient {
     pub url: String,
     pub access_token: Option<String>,
+    /// 编码格式，未指定时跟随对端
+    #[serde(default)]
+    pub content_type: Option<ContentType>,
     pub reconnect_interval: u32,
+    #[serde(default)]
+    pub action_timeout: ActionTimeout,
+    #[serde(default)]
+    pub event_buffer: EventBuffer,
 }
 
 impl Default for WebSocketClient {
@@ -158,11 +197,105 @@ impl Default for WebSocketClient {
         Self {
             url: "ws://127.0.0.1:8844".to_owned(),
             access_token: None,
+            content_type: None,
             reconnect_interval: 4,
+            action_timeout: ActionTimeout::default(),
+            event_buffer: EventBuffer::default(),
+        }
+    }
+}
+
+/// Action 响应超时设置，单位为秒
+///
+/// 端点上的设置优先于全局设置，按 Action 名称的设置优先于 default
+///
+/// 按 Action 名称的设置仅支持以 `Action` 为 Action 类型的 OBC，其他类型在启动时报错
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct ActionTimeout {
+    pub default: Option<u64>,
+    pub actions: BTreeMap<String, u64>,
+}
+
+impl ActionTimeout {
+    /// 均未设置时的超时
+    pub const DEFAULT: u64 = 10;
+    /// 以 self 为准，未设置的项由 fallback 补全
+    pub fn or(&self, fallback: &Self) -> Self {
+        let mut actions = fallback.actions.clone();
+        actions.extend(self.actions.clone());
+        Self {
+            default: self.default.or(fallback.default),
+            actions,
+        }
+    }
+    /// 获取 action 的超时，均未设置时为 otherwise 秒
+    pub fn get(&self, action: Option<&str>, otherwise: u64) -> Duration {
+        let secs = action
+            .and_then(|action| self.actions.get(action))
+            .copied()
+            .or(self.default)
+            .unwrap_or(otherwise);
+        Duration::from_secs(secs)
+    }
+}
+
+/// 实现端向单个连接推送 Event 的缓冲设置
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct EventBuffer {
+    pub capacity: usize,
+    pub lag: LagPolicy,
+}
+
+impl Default for EventBuffer {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            lag: LagPolicy::default(),
         }
     }
 }
 
+/// 缓冲区满时的处理方式
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LagPolicy {
+    /// 断开该连接
+    Disconnect,
+    /// 丢弃该 Event 并记录
+    #[default]
+    Skip,
+    /// 等待该连接消费，阻塞 Event 的产生方
+    Block,
+}
+
+#[test]
+fn action_timeout_test() {
+    let global = ActionTimeout {
+        default: Some(20),
+        actions: [("upload_file".to_string(), 120)].into(),
+    };
+    let endpoint = ActionTimeout {
+        default: None,
+        actions: [("get_file".to_string(), 60)].into(),
+    };
+    let merged = endpoint.or(&global);
+    assert_eq!(
+        merged.get(Some("upload_file"), 10),
+        Duration::from_secs(120)
+    );
+    assert_eq!(merged.get(Some("get_file"), 10), Duration::from_secs(60));
+    assert_eq!(
+        merged.get(Some("send_message"), 10),
+        Duration::from_secs(20)
+    );
+    assert_eq!(
+        ActionTimeout::default().get(None, ActionTimeout::DEFAULT),
+        Duration::from_secs(10)
+    );
+}
+
 #[test]
 fn toml_test() {
     let config = AppConfig::default();
@@ -0,0 +1,206 @@
+//! CQ 码解析与生成
+//!
+//! https://docs.go-cqhttp.org/cqcode/
+
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value as JsonValue;
+
+use crate::{segment::Segments, v11::V11MsgSegment};
+
+/// 转义 CQ 码中的纯文本，`in_param` 为 true 时同时转义 `,`
+pub fn escape(s: &str, in_param: bool) -> String {
+    let s = s
+        .replace('&', "&amp;")
+        .replace('[', "&#91;")
+        .replace(']', "&#93;");
+    if in_param {
+        s.replace(',', "&#44;")
+    } else {
+        s
+    }
+}
+
+pub fn unescape(s: &str) -> String {
+    s.replace("&#44;", ",")
+        .replace("&#91;", "[")
+        .replace("&#93;", "]")
+        .replace("&amp;", "&")
+}
+
+/// 解析 CQ 码字符串
+pub fn parse(s: &str) -> Vec<V11MsgSegment> {
+    let mut segments = vec![];
+    let mut rest = s;
+    while let Some(start) = rest.find("[CQ:") {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        push_text(&mut segments, &rest[..start]);
+        segments.push(parse_code(&rest[start + 4..start + len]));
+        rest = &rest[start + len + 1..];
+    }
+    push_text(&mut segments, rest);
+    segments
+}
+
+fn push_text(segments: &mut Vec<V11MsgSegment>, s: &str) {
+    if !s.is_empty() {
+        segments.push(V11MsgSegment::Text { text: unescape(s) });
+    }
+}
+
+fn parse_code(code: &str) -> V11MsgSegment {
+    let mut parts = code.split(',');
+    let ty = parts.next().unwrap_or_default();
+    let data: serde_json::Map<String, JsonValue> = parts
+        .filter_map(|part| part.split_once('='))
+        .map(|(k, v)| (k.to_string(), JsonValue::String(unescape(v))))
+        .collect();
+    serde_json::from_value(serde_json::json!({ "type": ty, "data": data })).unwrap_or_else(|_| {
+        V11MsgSegment::Text {
+            text: format!("[CQ:{}]", unescape(code)),
+        }
+    })
+}
+
+/// 生成 CQ 码字符串
+pub fn render(segments: &[V11MsgSegment]) -> String {
+    segments.iter().map(render_segment).collect()
+}
+
+fn render_segment(segment: &V11MsgSegment) -> String {
+    if let V11MsgSegment::Text { text } = segment {
+        return escape(text, false);
+    }
+    let JsonValue::Object(mut map) = serde_json::to_value(segment).unwrap_or_default() else {
+        return String::default();
+    };
+    let mut code = format!(
+        "[CQ:{}",
+        map.remove("type")
+            .as_ref()
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default()
+    );
+    if let Some(JsonValue::Object(data)) = map.remove("data") {
+        for (k, v) in data {
+            let v = match v {
+                JsonValue::Null => continue,
+                JsonValue::String(s) => s,
+                v => v.to_string(),
+            };
+            code.push(',');
+            code.push_str(&k);
+            code.push('=');
+            code.push_str(&escape(&v, true));
+        }
+    }
+    code.push(']');
+    code
+}
+
+/// 将 CQ 码字符串解析为 v12 消息
+pub fn to_segments(s: &str) -> Segments {
+    parse(s).into_iter().map(Into::into).collect()
+}
+
+/// 将 v12 消息渲染为 CQ 码字符串
+pub fn from_segments(segments: &Segments) -> String {
+    render(
+        &segments
+            .iter()
+            .cloned()
+            .map(V11MsgSegment::from)
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// v11 `message` 字段可能为 CQ 码字符串或消息段数组
+pub(crate) fn deserialize_message<'de, D>(deserializer: D) -> Result<Vec<V11MsgSegment>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Message {
+        Str(String),
+        Segments(Vec<V11MsgSegment>),
+    }
+    Ok(match Message::deserialize(deserializer)? {
+        Message::Str(s) => parse(&s),
+        Message::Segments(segments) => segments,
+    })
+}
+
+/// CQ 码参数均为字符串，数字字段需兼容两种形式
+pub(crate) fn deserialize_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: std::str::FromStr + TryFrom<i64>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Int(i64),
+        Str(String),
+    }
+    match Number::deserialize(deserializer)? {
+        Number::Int(i) => T::try_from(i).map_err(|_| de::Error::custom("number out of range")),
+        Number::Str(s) => s
+            .parse()
+            .map_err(|_| de::Error::custom(format!("invalid number: {}", s))),
+    }
+}
+
+#[test]
+fn cq_test() {
+    let s = "hello&amp;&#91;world&#93;[CQ:at,qq=123][CQ:reply,id=-1][CQ:face,id=1]";
+    let segments = parse(s);
+    assert_eq!(
+        segments,
+        vec![
+            V11MsgSegment::Text {
+                text: "hello&[world]".to_string()
+            },
+            V11MsgSegment::At {
+                qq: "123".to_string()
+            },
+            V11MsgSegment::Reply { id: -1 },
+            V11MsgSegment::Face {
+                id: "1".to_string()
+            },
+        ]
+    );
+    assert_eq!(render(&segments), s);
+    assert_eq!(
+        parse("[CQ:image,file=a&#44;b.png]"),
+        vec![V11MsgSegment::Image {
+            file: "a,b.png".to_string(),
+            ty: None,
+            url: None
+        }]
+    );
+    assert_eq!(
+        render(&parse("[CQ:image,file=a&#44;b.png]")),
+        "[CQ:image,file=a&#44;b.png]"
+    );
+    assert_eq!(
+        parse("[CQ:at,qq=1"),
+        vec![V11MsgSegment::Text {
+            text: "[CQ:at,qq=1".to_string()
+        }]
+    );
+}
+
+#[test]
+fn string_message_test() {
+    let event: crate::v11::V11Event = serde_json::from_str(
+        r#"{"time":1632847927,"self_id":10000,"post_type":"message","message_type":"private","sub_type":"friend","message_id":1,"user_id":20000,"message":"hi[CQ:at,qq=all]","raw_message":"hi[CQ:at,qq=all]","sender":{"user_id":20000,"nickname":"n"}}"#,
+    )
+    .unwrap();
+    let event = crate::com::ComEvent::from(event).to_v12();
+    let message: crate::event::MessageEvent = event.try_into().unwrap();
+    assert_eq!(message.ty.message.len(), 2);
+    assert_eq!(message.ty.message[1].ty, "mention_all");
+    assert_eq!(message.ty.alt_message, "hi[mention_all]");
+}
@@ -58,6 +58,18 @@ pub trait EventHandler<E = Event, A = Action, R = Resp>: Sync {
     {
         async { Ok(resp) }
     }
+    /// 运行中以新配置重载，仅启动或停止发生变化的部分，返回新启动的任务
+    fn reload<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _config: Self::Config,
+    ) -> impl Future<Output = WalleResult<Vec<tokio::task::JoinHandle<()>>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        async { Err(crate::WalleError::ReloadNotSupported) }
+    }
     fn shutdown(&self) -> impl Future<Output = ()> {
         async {}
     }
@@ -84,6 +96,17 @@ pub trait EventHandler<E = Event, A = Action, R = Resp>: Sync {
 }
 
 use crate::ah::JoinedHandler;
+use crate::error::WalleError;
+
+/// JoinedEventHandler 分发 Event 的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DispatchMode {
+    /// 依次调用，前者出错不影响后者
+    #[default]
+    Sequential,
+    /// 并发调用并等待全部完成
+    Concurrent,
+}
 
 pub trait EHExt<E, A, R> {
     fn join<EH1>(self, event_handler: EH1) -> JoinedHandler<Self, EH1>
@@ -92,6 +115,20 @@ pub trait EHExt<E, A, R> {
     {
         JoinedHandler(self, event_handler)
     }
+    /// 组合两个 EventHandler，以 mode 分发 Event
+    fn join_with<EH1>(self, event_handler: EH1, mode: DispatchMode) -> JoinedEventHandler<Self, EH1>
+    where
+        Self: Sized,
+    {
+        JoinedEventHandler::new(self, event_handler, mode)
+    }
+    /// 在独立任务中处理 Event，不等待其完成
+    fn detach(self) -> Detached<Self>
+    where
+        Self: Sized,
+    {
+        Detached(Arc::new(self))
+    }
 }
 
 impl<T: EventHandler<E, A, R>, E, A, R> EHExt<E, A, R> for T {}
@@ -115,7 +152,20 @@ where
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
         let mut joins = self.0.start(ob, config.0).await?;
-        joins.extend(self.1.start(ob, config.1).await?.into_iter());
+        joins.extend(self.1.start(ob, config.1).await?);
+        Ok(joins)
+    }
+    async fn reload<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let mut joins = self.0.reload(ob, config.0).await?;
+        joins.extend(self.1.reload(ob, config.1).await?);
         Ok(joins)
     }
     async fn call<AH, EH>(&self, event: E, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
@@ -123,8 +173,9 @@ where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        self.0.call(event.clone(), ob).await?;
-        self.1.call(event, ob).await
+        let r0 = self.0.call(event.clone(), ob).await;
+        let r1 = self.1.call(event, ob).await;
+        WalleError::collect([r0, r1])
     }
     async fn before_call_action<AH, EH>(
         &self,
@@ -171,3 +222,200 @@ where
         self.1.on_onebot_disconnect(ob).await
     }
 }
+
+/// 组合两个 EventHandler，以 DispatchMode 分发 Event
+pub struct JoinedEventHandler<H0, H1> {
+    handlers: JoinedHandler<H0, H1>,
+    mode: DispatchMode,
+}
+
+impl<H0, H1> JoinedEventHandler<H0, H1> {
+    pub fn new(h0: H0, h1: H1, mode: DispatchMode) -> Self {
+        Self {
+            handlers: JoinedHandler(h0, h1),
+            mode,
+        }
+    }
+    pub fn first(&self) -> &H0 {
+        &self.handlers.0
+    }
+    pub fn second(&self) -> &H1 {
+        &self.handlers.1
+    }
+    pub fn mode(&self) -> DispatchMode {
+        self.mode
+    }
+    pub fn into_inner(self) -> (H0, H1) {
+        (self.handlers.0, self.handlers.1)
+    }
+}
+
+impl<EH0, EH1, E, A, R> EventHandler<E, A, R> for JoinedEventHandler<EH0, EH1>
+where
+    EH0: EventHandler<E, A, R> + Send + Sync + 'static,
+    EH0::Config: Send + Sync + 'static,
+    EH1: EventHandler<E, A, R> + Send + Sync + 'static,
+    EH1::Config: Send + Sync + 'static,
+    E: Clone + Send + Sync + 'static,
+{
+    type Config = (EH0::Config, EH1::Config);
+    async fn start<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.start(ob, config).await
+    }
+    async fn reload<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.reload(ob, config).await
+    }
+    async fn call<AH, EH>(&self, event: E, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        match self.mode {
+            DispatchMode::Sequential => self.handlers.call(event, ob).await,
+            DispatchMode::Concurrent => {
+                let JoinedHandler(h0, h1) = &self.handlers;
+                let (r0, r1) = tokio::join!(h0.call(event.clone(), ob), h1.call(event, ob));
+                WalleError::collect([r0, r1])
+            }
+        }
+    }
+    async fn before_call_action<AH, EH>(
+        &self,
+        action: A,
+        ob: &Arc<OneBot<AH, EH>>,
+    ) -> WalleResult<A>
+    where
+        A: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.before_call_action(action, ob).await
+    }
+    async fn after_call_action<AH, EH>(&self, resp: R, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
+    where
+        R: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.after_call_action(resp, ob).await
+    }
+    async fn shutdown(&self) {
+        self.handlers.shutdown().await
+    }
+    async fn on_onebot_connect<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.on_onebot_connect(ob).await
+    }
+    async fn on_onebot_disconnect<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.handlers.on_onebot_disconnect(ob).await
+    }
+}
+
+/// fire-and-forget 的 EventHandler，错误仅记录日志
+///
+/// 任务登记于 OneBot，优雅关闭时等待其结束或在超时后中止
+pub struct Detached<EH>(pub Arc<EH>);
+
+impl<EH0, E, A, R> EventHandler<E, A, R> for Detached<EH0>
+where
+    EH0: EventHandler<E, A, R> + Send + Sync + 'static,
+    E: Send + 'static,
+{
+    type Config = EH0::Config;
+    async fn start<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.0.start(ob, config).await
+    }
+    async fn reload<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: Self::Config,
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.0.reload(ob, config).await
+    }
+    async fn call<AH, EH>(&self, event: E, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let handler = self.0.clone();
+        let ob2 = ob.clone();
+        ob.spawn("detached event handler", async move {
+            if let Err(e) = handler.call(event, &ob2).await {
+                tracing::warn!(target: crate::WALLE_CORE, "detached handler error: {}", e);
+            }
+        });
+        Ok(())
+    }
+    async fn before_call_action<AH, EH>(
+        &self,
+        action: A,
+        ob: &Arc<OneBot<AH, EH>>,
+    ) -> WalleResult<A>
+    where
+        A: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.0.before_call_action(action, ob).await
+    }
+    async fn after_call_action<AH, EH>(&self, resp: R, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
+    where
+        R: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.0.after_call_action(resp, ob).await
+    }
+    async fn shutdown(&self) {
+        self.0.shutdown().await
+    }
+    async fn on_onebot_connect<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.0.on_onebot_connect(ob).await
+    }
+    async fn on_onebot_disconnect<AH, EH>(&self, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        self.0.on_onebot_disconnect(ob).await
+    }
+}
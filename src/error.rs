@@ -28,6 +28,8 @@ pub enum WalleError {
     AlreadyStarted,
     #[error("OneBot is not started")]
     NotStarted,
+    #[error("Reload is not supported")]
+    ReloadNotSupported,
 
     // Extended
     #[error("ExtendedMap missed key: {0}")]
@@ -43,6 +45,32 @@ pub enum WalleError {
 
     #[error("{0}")]
     Other(String),
+
+    // 多个 handler 分支的错误
+    #[error("{}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; "))]
+    Multi(Vec<WalleError>),
+}
+
+impl WalleError {
+    /// 聚合多个分支的结果，仅有一个错误时原样返回
+    pub fn collect<I>(results: I) -> WalleResult<()>
+    where
+        I: IntoIterator<Item = WalleResult<()>>,
+    {
+        let mut errors = vec![];
+        for r in results {
+            match r {
+                Ok(()) => {}
+                Err(WalleError::Multi(es)) => errors.extend(es),
+                Err(e) => errors.push(e),
+            }
+        }
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(WalleError::Multi(errors)),
+        }
+    }
 }
 
 impl serde::de::Error for WalleError {
@@ -70,10 +98,7 @@ pub trait ResultExt<T, E> {
 impl<T, E: std::fmt::Debug> ResultExt<T, E> for Result<T, E> {
     #[inline(always)]
     fn ignore(self) -> Option<T> {
-        match self {
-            Ok(v) => Some(v),
-            Err(_) => None,
-        }
+        self.ok()
     }
 
     #[inline(always)]
@@ -22,6 +22,8 @@ pub struct Event {
 }
 
 pub trait ToEvent<T>: PushToValueMap {
+    /// 扩展事件前缀，如 `qq`
+    const PREFIX: Option<&'static str> = None;
     fn ty(&self) -> &'static str;
 }
 
@@ -141,6 +143,7 @@ where
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn new_event<T, D, S, P, I>(
     id: String,
     time: f64,
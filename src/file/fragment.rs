@@ -0,0 +1,325 @@
+//! 应用端分片上传与下载
+
+use std::collections::BTreeMap;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::action::{Action, GetFileFragmented, UploadFileFragmented};
+use crate::error::{WalleError, WalleResult};
+use crate::resp::Resp;
+use crate::structs::{FileFragment, FileFragmentedHead, FileId, Selft};
+use crate::util::{OneBotBytes, Value};
+use crate::{ActionHandler, EventHandler, OneBot, WALLE_CORE};
+
+/// 分片上传与下载，通过 OneBot.handle_action 发送 upload_file_fragmented 与 get_file_fragmented
+#[derive(Debug, Clone)]
+pub struct Fragmented {
+    /// 分片大小，默认 1 MiB
+    pub chunk_size: u64,
+    /// 同时进行的分片请求数，默认 4
+    pub parallel: usize,
+    /// `upload_path` 与 `download_path` 发送 Action 失败后自最后确认位置续传的次数，默认 3
+    ///
+    /// 对端返回错误响应时不重试，下载完成后 sha256 不符时自 0 重新下载
+    pub retries: usize,
+    /// 发送 Action 时指定的 self
+    pub selft: Option<Selft>,
+}
+
+impl Default for Fragmented {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1024 * 1024,
+            parallel: 4,
+            retries: 3,
+            selft: None,
+        }
+    }
+}
+
+/// 分片上传进度
+#[derive(Debug, Clone)]
+pub struct UploadProgress {
+    pub file_id: String,
+    pub total_size: u64,
+    /// 已确认的连续字节数，续传时 reader 应位于此处
+    pub offset: u64,
+    hasher: Sha256,
+}
+
+/// 分片下载进度
+#[derive(Debug, Clone)]
+pub struct DownloadProgress {
+    pub file_id: String,
+    pub head: FileFragmentedHead,
+    /// 已写入的连续字节数，续传时 writer 应位于此处
+    pub offset: u64,
+    hasher: Sha256,
+}
+
+impl Fragmented {
+    async fn call<E, T, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        mut action: Action,
+    ) -> WalleResult<T>
+    where
+        T: TryFrom<Value, Error = WalleError>,
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+    {
+        action.selft = self.selft.clone();
+        ob.handle_action(action).await?.as_result_downcast()
+    }
+
+    pub async fn prepare_upload<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        name: String,
+        total_size: u64,
+    ) -> WalleResult<UploadProgress>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+    {
+        let prepare = UploadFileFragmented::Prepare {
+            name,
+            total_size: total_size as i64,
+        };
+        let FileId { file_id } = self.call::<E, _, _, _>(ob, prepare.into()).await?;
+        Ok(UploadProgress {
+            file_id,
+            total_size,
+            offset: 0,
+            hasher: Sha256::new(),
+        })
+    }
+
+    /// 自 progress.offset 起读取 reader 并上传，完成后携带 sha256 结束上传并返回 file_id
+    ///
+    /// 失败时 progress 保留已确认的位置，可将 reader 定位至 progress.offset 后再次调用续传
+    pub async fn upload<E, AH, EH, R>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        progress: &mut UploadProgress,
+        mut reader: R,
+    ) -> WalleResult<String>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+        R: AsyncRead + Unpin,
+    {
+        let mut sent = progress.offset;
+        // 已确认但尚未连续的分片，待连续后计入 sha256
+        let mut acked = BTreeMap::new();
+        let mut pending = FuturesUnordered::new();
+        loop {
+            while pending.len() < self.parallel.max(1) && sent < progress.total_size {
+                let offset = sent;
+                let mut data =
+                    vec![0; self.chunk_size.max(1).min(progress.total_size - sent) as usize];
+                reader.read_exact(&mut data).await?;
+                sent += data.len() as u64;
+                let transfer = UploadFileFragmented::Transfer {
+                    file_id: progress.file_id.clone(),
+                    offset: offset as i64,
+                    data: OneBotBytes(data.clone()),
+                };
+                pending.push(async move {
+                    self.call::<E, (), _, _>(ob, transfer.into())
+                        .await
+                        .map(|_| (offset, data))
+                });
+            }
+            let Some(r) = pending.next().await else {
+                break;
+            };
+            let (offset, data) = r?;
+            acked.insert(offset, data);
+            while let Some(data) = acked.remove(&progress.offset) {
+                progress.hasher.update(&data);
+                progress.offset += data.len() as u64;
+            }
+        }
+        let finish = UploadFileFragmented::Finish {
+            file_id: progress.file_id.clone(),
+            sha256: Some(hex::encode(progress.hasher.clone().finalize())),
+        };
+        let FileId { file_id } = self.call::<E, _, _, _>(ob, finish.into()).await?;
+        Ok(file_id)
+    }
+
+    /// 上传本地文件，失败后自最后确认的位置续传
+    pub async fn upload_path<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        path: impl AsRef<Path>,
+    ) -> WalleResult<String>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+    {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let total_size = tokio::fs::metadata(path).await?.len();
+        let mut progress = self.prepare_upload::<E, _, _>(ob, name, total_size).await?;
+        let mut retries = self.retries;
+        loop {
+            let mut file = tokio::fs::File::open(path).await?;
+            file.seek(SeekFrom::Start(progress.offset)).await?;
+            match self.upload::<E, _, _, _>(ob, &mut progress, file).await {
+                Err(e) if retries > 0 && !matches!(e, WalleError::RespError(_)) => {
+                    retries -= 1;
+                    tracing::warn!(
+                        target: WALLE_CORE,
+                        "upload {} failed: {}, resume from {}",
+                        path.display(),
+                        e,
+                        progress.offset
+                    );
+                }
+                r => return r,
+            }
+        }
+    }
+
+    pub async fn prepare_download<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        file_id: String,
+    ) -> WalleResult<DownloadProgress>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+    {
+        let prepare = GetFileFragmented::Prepare {
+            file_id: file_id.clone(),
+        };
+        let head: FileFragmentedHead = self.call::<E, _, _, _>(ob, prepare.into()).await?;
+        if head.total_size < 0 {
+            return Err(WalleError::Other(format!(
+                "illegal total_size {}",
+                head.total_size
+            )));
+        }
+        Ok(DownloadProgress {
+            file_id,
+            head,
+            offset: 0,
+            hasher: Sha256::new(),
+        })
+    }
+
+    /// 自 progress.offset 起下载并按序写入 writer，完成后校验 sha256
+    ///
+    /// 失败时 progress 保留已写入的位置，可将 writer 定位至 progress.offset 后再次调用续传
+    pub async fn download<E, AH, EH, W>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        progress: &mut DownloadProgress,
+        mut writer: W,
+    ) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+        W: AsyncWrite + Unpin,
+    {
+        let total_size = progress.head.total_size as u64;
+        let mut requested = progress.offset;
+        // 已收到但尚未连续的分片
+        let mut received = BTreeMap::new();
+        let mut pending = FuturesUnordered::new();
+        loop {
+            while pending.len() < self.parallel.max(1) && requested < total_size {
+                let offset = requested;
+                let size = self.chunk_size.max(1).min(total_size - requested);
+                requested += size;
+                let transfer = GetFileFragmented::Transfer {
+                    file_id: progress.file_id.clone(),
+                    offset: offset as i64,
+                    size: size as i64,
+                };
+                pending.push(async move {
+                    let FileFragment { data } =
+                        self.call::<E, _, _, _>(ob, transfer.into()).await?;
+                    if data.0.len() as u64 != size {
+                        return Err(WalleError::Other(format!(
+                            "fragment at {} expect {} bytes, got {}",
+                            offset,
+                            size,
+                            data.0.len()
+                        )));
+                    }
+                    Ok((offset, data.0))
+                });
+            }
+            let Some(r) = pending.next().await else {
+                break;
+            };
+            let (offset, data) = r?;
+            received.insert(offset, data);
+            while let Some(data) = received.remove(&progress.offset) {
+                writer.write_all(&data).await?;
+                progress.hasher.update(&data);
+                progress.offset += data.len() as u64;
+            }
+        }
+        writer.flush().await?;
+        let sha256 = hex::encode(progress.hasher.clone().finalize());
+        if !sha256.eq_ignore_ascii_case(&progress.head.sha256) {
+            return Err(WalleError::Other(format!(
+                "sha256 mismatch, expect {} got {}",
+                progress.head.sha256, sha256
+            )));
+        }
+        Ok(())
+    }
+
+    /// 下载文件至本地路径，失败后自最后写入的位置续传
+    pub async fn download_path<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        file_id: String,
+        path: impl AsRef<Path>,
+    ) -> WalleResult<FileFragmentedHead>
+    where
+        AH: ActionHandler<E, Action, Resp> + Send + Sync + 'static,
+        EH: EventHandler<E, Action, Resp> + Send + Sync + 'static,
+    {
+        let path = path.as_ref();
+        let mut progress = self.prepare_download::<E, _, _>(ob, file_id).await?;
+        tokio::fs::File::create(path).await?;
+        let mut retries = self.retries;
+        loop {
+            let mut file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+            file.seek(SeekFrom::Start(progress.offset)).await?;
+            match self.download::<E, _, _, _>(ob, &mut progress, file).await {
+                Err(e) if retries > 0 && !matches!(e, WalleError::RespError(_)) => {
+                    retries -= 1;
+                    // 已全部写入仍失败即 sha256 不符，已写入的数据不可信
+                    if progress.offset == progress.head.total_size as u64 {
+                        progress.offset = 0;
+                        progress.hasher = Sha256::new();
+                        tokio::fs::File::create(path).await?;
+                    }
+                    tracing::warn!(
+                        target: WALLE_CORE,
+                        "download {} failed: {}, resume from {}",
+                        progress.file_id,
+                        e,
+                        progress.offset
+                    );
+                }
+                r => return r.map(|_| progress.head),
+            }
+        }
+    }
+}
@@ -0,0 +1,847 @@
+//! 文件存储，用于实现 upload_file、get_file 与分片传输相关动作
+//!
+//! `FileStore` 为存储后端，`Files` 在其上维护文件索引与分片上传状态，
+//! 实现端可通过 `Files::handle` 一次调用应答全部文件动作，
+//! 应用端可通过 `Fragmented` 分片上传与下载文件
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::action::{
+    Action, GetFile, GetFileFragmented, TryFromAction, UploadFile, UploadFileFragmented,
+};
+use crate::error::WalleResult;
+use crate::resp::{resp_error, Resp, RespError};
+use crate::structs::{File, FileFragment, FileFragmentedHead, FileId};
+use crate::util::{new_uuid, BytesSource, OneBotBytes, Value};
+
+mod fragment;
+pub use fragment::*;
+
+/// 计算 sha256 时每次读取的大小
+const HASH_CHUNK: u64 = 1024 * 1024;
+
+/// 文件存储后端，以 file_id 为键读写文件内容
+pub trait FileStore: Send + Sync {
+    /// 创建指定大小的空文件
+    fn create(&self, file_id: &str, size: u64) -> impl Future<Output = WalleResult<()>> + Send;
+    /// 自 offset 处写入数据
+    fn write(
+        &self,
+        file_id: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> impl Future<Output = WalleResult<()>> + Send;
+    /// 自 offset 处读取至多 size 字节
+    fn read(
+        &self,
+        file_id: &str,
+        offset: u64,
+        size: u64,
+    ) -> impl Future<Output = WalleResult<Vec<u8>>> + Send;
+    /// 删除文件，文件不存在时忽略
+    fn remove(&self, file_id: &str) -> impl Future<Output = WalleResult<()>> + Send;
+    /// 文件的本地路径，用于应答 type 为 path 的 get_file
+    fn path(&self, _file_id: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// 本地文件系统存储，文件保存于 root 目录下
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+    fn file(&self, file_id: &str) -> PathBuf {
+        self.root.join(file_id)
+    }
+}
+
+impl FileStore for LocalStore {
+    async fn create(&self, file_id: &str, size: u64) -> WalleResult<()> {
+        tokio::fs::create_dir_all(&self.root).await?;
+        let file = tokio::fs::File::create(self.file(file_id)).await?;
+        file.set_len(size).await?;
+        Ok(())
+    }
+    async fn write(&self, file_id: &str, offset: u64, data: &[u8]) -> WalleResult<()> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(self.file(file_id))
+            .await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+        Ok(())
+    }
+    async fn read(&self, file_id: &str, offset: u64, size: u64) -> WalleResult<Vec<u8>> {
+        let mut file = tokio::fs::File::open(self.file(file_id)).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut buf = vec![];
+        file.take(size).read_to_end(&mut buf).await?;
+        Ok(buf)
+    }
+    async fn remove(&self, file_id: &str) -> WalleResult<()> {
+        match tokio::fs::remove_file(self.file(file_id)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+    fn path(&self, file_id: &str) -> Option<PathBuf> {
+        Some(self.file(file_id))
+    }
+}
+
+/// 内存存储，用于测试
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    files: DashMap<String, Vec<u8>>,
+}
+
+impl FileStore for MemoryStore {
+    async fn create(&self, file_id: &str, size: u64) -> WalleResult<()> {
+        self.files
+            .insert(file_id.to_string(), vec![0; size as usize]);
+        Ok(())
+    }
+    async fn write(&self, file_id: &str, offset: u64, data: &[u8]) -> WalleResult<()> {
+        let mut file = self
+            .files
+            .get_mut(file_id)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+        let (start, end) = (offset as usize, offset as usize + data.len());
+        if file.len() < end {
+            file.resize(end, 0);
+        }
+        file[start..end].copy_from_slice(data);
+        Ok(())
+    }
+    async fn read(&self, file_id: &str, offset: u64, size: u64) -> WalleResult<Vec<u8>> {
+        let file = self
+            .files
+            .get(file_id)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+        let start = (offset as usize).min(file.len());
+        let end = (start + size as usize).min(file.len());
+        Ok(file[start..end].to_vec())
+    }
+    async fn remove(&self, file_id: &str) -> WalleResult<()> {
+        self.files.remove(file_id);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Meta {
+    name: String,
+    size: u64,
+    sha256: String,
+}
+
+/// Files 的限制项
+#[derive(Debug, Clone)]
+pub struct FileLimits {
+    /// 单个文件的最大字节数，默认 100 MiB
+    pub max_size: u64,
+    /// 以 url 上传时下载的超时，默认 30 秒
+    pub fetch_timeout: Duration,
+    /// 分片上传超过该时长未收到分片即被清理，默认 10 分钟
+    pub upload_ttl: Duration,
+}
+
+impl Default for FileLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 100 * 1024 * 1024,
+            fetch_timeout: Duration::from_secs(30),
+            upload_ttl: Duration::from_secs(600),
+        }
+    }
+}
+
+impl FileLimits {
+    fn check_size(&self, size: u64) -> Result<(), RespError> {
+        if size > self.max_size {
+            return Err(resp_error::bad_param(format!(
+                "file size {} exceeds max_size {}",
+                size, self.max_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// 进行中的分片上传
+#[derive(Debug)]
+struct Upload {
+    name: String,
+    total_size: u64,
+    // 已接收的区间 start -> end，相邻或重叠的区间会被合并
+    received: BTreeMap<u64, u64>,
+    // 最近一次收到分片的时间
+    active: Instant,
+}
+
+impl Upload {
+    fn receive(&mut self, mut start: u64, mut end: u64) {
+        let merged: Vec<u64> = self
+            .received
+            .range(..=end)
+            .filter(|(_, e)| **e >= start)
+            .map(|(s, _)| *s)
+            .collect();
+        for s in merged {
+            let e = self.received.remove(&s).unwrap_or(s);
+            start = start.min(s);
+            end = end.max(e);
+        }
+        if start < end {
+            self.received.insert(start, end);
+        }
+        self.active = Instant::now();
+    }
+    /// 自 0 起连续接收到的字节数
+    fn received(&self) -> u64 {
+        self.received.get(&0).copied().unwrap_or(0)
+    }
+}
+
+/// 基于 FileStore 的文件动作实现
+///
+/// 文件索引（名称、大小、sha256）仅保存于进程内存中，进程重启后
+/// 此前上传的文件无法再以 file_id 获取，即使 FileStore 中仍保留其数据
+pub struct Files<S> {
+    store: S,
+    limits: FileLimits,
+    files: DashMap<String, Meta>,
+    uploads: DashMap<String, Upload>,
+}
+
+impl<S: FileStore> Files<S> {
+    pub fn new(store: S) -> Self {
+        Self::with_limits(store, FileLimits::default())
+    }
+    pub fn with_limits(store: S, limits: FileLimits) -> Self {
+        Self {
+            store,
+            limits,
+            files: DashMap::default(),
+            uploads: DashMap::default(),
+        }
+    }
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+    pub fn limits(&self) -> &FileLimits {
+        &self.limits
+    }
+
+    /// 清理超过 upload_ttl 未收到分片的上传，返回清理的数量
+    pub async fn reap_uploads(&self) -> usize {
+        let ttl = self.limits.upload_ttl;
+        let stale: Vec<String> = self
+            .uploads
+            .iter()
+            .filter(|u| u.active.elapsed() > ttl)
+            .map(|u| u.key().clone())
+            .collect();
+        let mut reaped = 0;
+        for file_id in stale {
+            // 检查与移除之间可能收到了新的分片
+            if self
+                .uploads
+                .remove_if(&file_id, |_, u| u.active.elapsed() > ttl)
+                .is_some()
+            {
+                self.store.remove(&file_id).await.ok();
+                reaped += 1;
+            }
+        }
+        reaped
+    }
+
+    /// 处理文件相关动作，非文件动作原样返回
+    pub async fn handle(&self, mut action: Action) -> Result<Resp, Action> {
+        let resp = match action.action.as_str() {
+            "upload_file" => match UploadFile::try_from_action_mut(&mut action) {
+                Ok(upload) => self.upload_file(upload).await.map(Resp::from),
+                Err(e) => Err(resp_error::bad_param(e)),
+            },
+            "upload_file_fragmented" => {
+                match UploadFileFragmented::try_from_action_mut(&mut action) {
+                    Ok(upload) => self.upload_file_fragmented(upload).await.map(Resp::from),
+                    Err(e) => Err(resp_error::bad_param(e)),
+                }
+            }
+            "get_file" => match GetFile::try_from_action_mut(&mut action) {
+                Ok(get) => self.get_file(get).await.map(Resp::from),
+                Err(e) => Err(resp_error::bad_param(e)),
+            },
+            "get_file_fragmented" => match GetFileFragmented::try_from_action_mut(&mut action) {
+                Ok(get) => self.get_file_fragmented(get).await.map(Resp::from),
+                Err(e) => Err(resp_error::bad_param(e)),
+            },
+            _ => return Err(action),
+        };
+        Ok(resp.unwrap_or_else(Resp::from))
+    }
+
+    /// 保存完整文件，返回 file_id
+    pub async fn put(
+        &self,
+        name: String,
+        data: &[u8],
+        sha256: Option<&str>,
+    ) -> Result<String, RespError> {
+        self.limits.check_size(data.len() as u64)?;
+        let hash = hex::encode(Sha256::digest(data));
+        check_sha256(&hash, sha256)?;
+        let file_id = new_uuid();
+        let size = data.len() as u64;
+        self.store
+            .create(&file_id, size)
+            .await
+            .map_err(resp_error::filesystem_error)?;
+        if let Err(e) = self.store.write(&file_id, 0, data).await {
+            self.store.remove(&file_id).await.ok();
+            return Err(resp_error::filesystem_error(e));
+        }
+        self.files.insert(
+            file_id.clone(),
+            Meta {
+                name,
+                size,
+                sha256: hash,
+            },
+        );
+        Ok(file_id)
+    }
+
+    /// 读取任一来源的文件内容，受 FileLimits 限制
+    pub async fn load(
+        &self,
+        source: BytesSource,
+        headers: HashMap<String, String>,
+    ) -> Result<Vec<u8>, RespError> {
+        match source {
+            BytesSource::Url(url) => fetch(&url, headers, &self.limits).await,
+            BytesSource::Path(path) => {
+                let size = tokio::fs::metadata(&path)
+                    .await
+                    .map_err(resp_error::filesystem_error)?
+                    .len();
+                self.limits.check_size(size)?;
+                tokio::fs::read(path)
+                    .await
+                    .map_err(resp_error::filesystem_error)
+            }
+            BytesSource::Bytes(data) => Ok(data.0),
+        }
+    }
+
+    pub async fn upload_file(&self, upload: UploadFile) -> Result<FileId, RespError> {
+        let source = match upload.ty.as_str() {
+            "url" => BytesSource::Url(
+                upload
+                    .url
+                    .ok_or_else(|| resp_error::bad_param("missing url"))?,
+            ),
+            "path" => BytesSource::Path(
+                upload
+                    .path
+                    .ok_or_else(|| resp_error::bad_param("missing path"))?
+                    .into(),
+            ),
+            "data" => BytesSource::Bytes(
+                upload
+                    .data
+                    .ok_or_else(|| resp_error::bad_param("missing data"))?,
+            ),
+            ty => return Err(resp_error::unsupported_param(format!("type {}", ty))),
+        };
+        let data = self
+            .load(source, upload.headers.unwrap_or_default())
+            .await?;
+        let file_id = self
+            .put(upload.name, &data, upload.sha256.as_deref())
+            .await?;
+        Ok(FileId { file_id })
+    }
+
+    pub async fn upload_file_fragmented(
+        &self,
+        upload: UploadFileFragmented,
+    ) -> Result<Value, RespError> {
+        match upload {
+            UploadFileFragmented::Prepare { name, total_size } => {
+                let total_size = u64::try_from(total_size)
+                    .map_err(|_| resp_error::bad_param("negative total_size"))?;
+                self.limits.check_size(total_size)?;
+                self.reap_uploads().await;
+                let file_id = new_uuid();
+                self.store
+                    .create(&file_id, total_size)
+                    .await
+                    .map_err(resp_error::filesystem_error)?;
+                self.uploads.insert(
+                    file_id.clone(),
+                    Upload {
+                        name,
+                        total_size,
+                        received: BTreeMap::default(),
+                        active: Instant::now(),
+                    },
+                );
+                Ok(FileId { file_id }.into())
+            }
+            UploadFileFragmented::Transfer {
+                file_id,
+                offset,
+                data,
+            } => {
+                let offset =
+                    u64::try_from(offset).map_err(|_| resp_error::bad_param("negative offset"))?;
+                let end = offset + data.0.len() as u64;
+                let total_size = self
+                    .uploads
+                    .get(&file_id)
+                    .map(|u| u.total_size)
+                    .ok_or_else(|| resp_error::bad_param("upload not prepared"))?;
+                if end > total_size {
+                    return Err(resp_error::bad_param(format!(
+                        "fragment {}..{} out of total_size {}",
+                        offset, end, total_size
+                    )));
+                }
+                self.store
+                    .write(&file_id, offset, &data.0)
+                    .await
+                    .map_err(resp_error::filesystem_error)?;
+                // 写入完成后才记录区间，finish 不会将进行中的分片视为已接收
+                self.uploads
+                    .get_mut(&file_id)
+                    .ok_or_else(|| resp_error::bad_param("upload not prepared"))?
+                    .receive(offset, end);
+                Ok(Value::Null)
+            }
+            UploadFileFragmented::Finish { file_id, sha256 } => {
+                let (_, upload) = self
+                    .uploads
+                    .remove(&file_id)
+                    .ok_or_else(|| resp_error::bad_param("upload not prepared"))?;
+                if upload.received() < upload.total_size {
+                    let received = upload.received();
+                    self.uploads.insert(file_id, upload);
+                    return Err(resp_error::bad_param(format!(
+                        "upload incomplete, missing from offset {}",
+                        received
+                    )));
+                }
+                let hash = self.sha256(&file_id, upload.total_size).await;
+                let checked = hash.and_then(|hash| {
+                    check_sha256(&hash, sha256.as_deref())?;
+                    Ok(hash)
+                });
+                match checked {
+                    Ok(hash) => {
+                        self.files.insert(
+                            file_id.clone(),
+                            Meta {
+                                name: upload.name,
+                                size: upload.total_size,
+                                sha256: hash,
+                            },
+                        );
+                        Ok(FileId { file_id }.into())
+                    }
+                    Err(e) => {
+                        self.store.remove(&file_id).await.ok();
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn get_file(&self, get: GetFile) -> Result<File, RespError> {
+        let meta = self.meta(&get.file_id)?;
+        let mut file = File {
+            name: meta.name,
+            url: None,
+            headers: None,
+            path: None,
+            data: None,
+            sha256: Some(meta.sha256),
+        };
+        match get.ty.as_str() {
+            "data" => {
+                file.data = Some(
+                    self.store
+                        .read(&get.file_id, 0, meta.size)
+                        .await
+                        .map_err(resp_error::filesystem_error)?,
+                )
+            }
+            "path" => {
+                let path = self
+                    .store
+                    .path(&get.file_id)
+                    .ok_or_else(|| resp_error::unsupported_param("type path"))?;
+                file.path = Some(path.to_string_lossy().into_owned());
+            }
+            // FileStore 无法提供可供对端访问的 url
+            "url" => return Err(resp_error::unsupported_param("type url")),
+            ty => return Err(resp_error::unsupported_param(format!("type {}", ty))),
+        }
+        Ok(file)
+    }
+
+    pub async fn get_file_fragmented(&self, get: GetFileFragmented) -> Result<Value, RespError> {
+        match get {
+            GetFileFragmented::Prepare { file_id } => {
+                let meta = self.meta(&file_id)?;
+                Ok(FileFragmentedHead {
+                    name: meta.name,
+                    total_size: meta.size as i64,
+                    sha256: meta.sha256,
+                }
+                .into())
+            }
+            GetFileFragmented::Transfer {
+                file_id,
+                offset,
+                size,
+            } => {
+                let meta = self.meta(&file_id)?;
+                let (offset, size) = match (u64::try_from(offset), u64::try_from(size)) {
+                    (Ok(offset), Ok(size)) if offset <= meta.size => (offset, size),
+                    _ => {
+                        return Err(resp_error::bad_param(format!(
+                            "fragment offset {} size {} out of total_size {}",
+                            offset, size, meta.size
+                        )))
+                    }
+                };
+                let data = self
+                    .store
+                    .read(&file_id, offset, size)
+                    .await
+                    .map_err(resp_error::filesystem_error)?;
+                Ok(FileFragment {
+                    data: OneBotBytes(data),
+                }
+                .into())
+            }
+        }
+    }
+
+    fn meta(&self, file_id: &str) -> Result<Meta, RespError> {
+        self.files
+            .get(file_id)
+            .map(|m| m.clone())
+            .ok_or_else(|| resp_error::bad_param(format!("file {} not found", file_id)))
+    }
+
+    async fn sha256(&self, file_id: &str, size: u64) -> Result<String, RespError> {
+        let mut hasher = Sha256::new();
+        let mut offset = 0;
+        while offset < size {
+            let chunk = self
+                .store
+                .read(file_id, offset, HASH_CHUNK.min(size - offset))
+                .await
+                .map_err(resp_error::filesystem_error)?;
+            if chunk.is_empty() {
+                return Err(resp_error::filesystem_error("unexpected end of file"));
+            }
+            offset += chunk.len() as u64;
+            hasher.update(&chunk);
+        }
+        Ok(hex::encode(hasher.finalize()))
+    }
+}
+
+fn check_sha256(hash: &str, expect: Option<&str>) -> Result<(), RespError> {
+    match expect {
+        Some(expect) if !expect.eq_ignore_ascii_case(hash) => Err(resp_error::bad_param(format!(
+            "sha256 mismatch, expect {} got {}",
+            expect, hash
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "http")]
+async fn fetch(
+    url: &str,
+    headers: HashMap<String, String>,
+    limits: &FileLimits,
+) -> Result<Vec<u8>, RespError> {
+    use hyper::body::HttpBody;
+
+    let uri: hyper::Uri = url.parse().map_err(resp_error::bad_param)?;
+    // hyper::Client 未启用 TLS
+    if uri.scheme_str() != Some("http") {
+        return Err(resp_error::unsupported_param(format!(
+            "url scheme {}",
+            uri.scheme_str().unwrap_or_default()
+        )));
+    }
+    let mut req = hyper::Request::get(uri);
+    for (k, v) in headers {
+        req = req.header(k, v);
+    }
+    let req = req
+        .body(hyper::Body::empty())
+        .map_err(resp_error::bad_param)?;
+    let download = async {
+        let resp = hyper::Client::new()
+            .request(req)
+            .await
+            .map_err(resp_error::network_error)?;
+        if !resp.status().is_success() {
+            return Err(resp_error::network_error(resp.status()));
+        }
+        let mut body = resp.into_body();
+        if let Some(size) = body.size_hint().exact() {
+            limits.check_size(size)?;
+        }
+        let mut data = vec![];
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(resp_error::network_error)?;
+            limits.check_size((data.len() + chunk.len()) as u64)?;
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data)
+    };
+    tokio::time::timeout(limits.fetch_timeout, download)
+        .await
+        .map_err(|_| resp_error::network_error("fetch timeout"))?
+}
+
+#[cfg(not(feature = "http"))]
+async fn fetch(
+    _url: &str,
+    _headers: HashMap<String, String>,
+    _limits: &FileLimits,
+) -> Result<Vec<u8>, RespError> {
+    Err(resp_error::unsupported_param(
+        "type url requires http feature",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value_map;
+
+    fn action(name: &str, params: crate::util::ValueMap) -> Action {
+        Action {
+            action: name.to_string(),
+            selft: None,
+            params,
+        }
+    }
+
+    async fn fragmented<S: FileStore>(files: &Files<S>) {
+        let data: Vec<u8> = (0..=255).collect();
+        let sha256 = hex::encode(Sha256::digest(&data));
+        let resp = files
+            .handle(action(
+                "upload_file_fragmented",
+                value_map! {"stage": "prepare", "name": "a.bin", "total_size": 256},
+            ))
+            .await
+            .unwrap();
+        let file_id: String = FileId::try_from(resp.data).unwrap().file_id;
+        let transfer = |offset: usize, len: usize| {
+            files.handle(action(
+                "upload_file_fragmented",
+                value_map! {
+                    "stage": "transfer",
+                    "file_id": file_id.clone(),
+                    "offset": offset as i64,
+                    "data": data[offset..offset + len].to_vec()
+                },
+            ))
+        };
+        let finish = || {
+            files.handle(action(
+                "upload_file_fragmented",
+                value_map! {"stage": "finish", "file_id": file_id.clone(), "sha256": sha256.clone()},
+            ))
+        };
+        // 乱序且重复的分片
+        assert_eq!(transfer(128, 128).await.unwrap().retcode, 0);
+        assert_eq!(transfer(64, 64).await.unwrap().retcode, 0);
+        assert_eq!(transfer(64, 64).await.unwrap().retcode, 0);
+        assert_eq!(finish().await.unwrap().retcode, 10003);
+        assert_eq!(transfer(0, 64).await.unwrap().retcode, 0);
+        let out_of_range = files
+            .handle(action(
+                "upload_file_fragmented",
+                value_map! {"stage": "transfer", "file_id": file_id.clone(), "offset": 255, "data": vec![0u8, 0]},
+            ))
+            .await
+            .unwrap();
+        assert_eq!(out_of_range.retcode, 10003);
+        assert_eq!(finish().await.unwrap().retcode, 0);
+
+        let head = files
+            .handle(action(
+                "get_file_fragmented",
+                value_map! {"stage": "prepare", "file_id": file_id.clone()},
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            FileFragmentedHead::try_from(head.data).unwrap(),
+            FileFragmentedHead {
+                name: "a.bin".to_string(),
+                total_size: 256,
+                sha256,
+            }
+        );
+        let fragment = files
+            .handle(action(
+                "get_file_fragmented",
+                value_map! {"stage": "transfer", "file_id": file_id.clone(), "offset": 200, "size": 100},
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            FileFragment::try_from(fragment.data).unwrap().data.0,
+            data[200..]
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_store() {
+        let files = Files::new(MemoryStore::default());
+        let resp = files
+            .handle(action(
+                "upload_file",
+                value_map! {"type": "data", "name": "a.txt", "data": b"walle".to_vec()},
+            ))
+            .await
+            .unwrap();
+        let file_id = FileId::try_from(resp.data).unwrap().file_id;
+        let url = files
+            .handle(action(
+                "get_file",
+                value_map! {"file_id": file_id.clone(), "type": "url"},
+            ))
+            .await
+            .unwrap();
+        assert_eq!(url.retcode, 10004);
+        let resp = files
+            .handle(action(
+                "get_file",
+                value_map! {"file_id": file_id, "type": "data"},
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.data.downcast_map().unwrap().get("data"),
+            Some(&Value::Bytes(b"walle".to_vec().into()))
+        );
+        let mismatch = files
+            .handle(action(
+                "upload_file",
+                value_map! {"type": "data", "name": "a.txt", "data": b"walle".to_vec(), "sha256": "00"},
+            ))
+            .await
+            .unwrap();
+        assert_eq!(mismatch.retcode, 10003);
+        assert!(files
+            .handle(action("get_status", value_map! {}))
+            .await
+            .is_err());
+        fragmented(&files).await;
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let files = Files::with_limits(
+            MemoryStore::default(),
+            FileLimits {
+                max_size: 4,
+                upload_ttl: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+        let upload = |data: Vec<u8>| {
+            files.handle(action(
+                "upload_file",
+                value_map! {"type": "data", "name": "a.bin", "data": data},
+            ))
+        };
+        assert_eq!(upload(vec![0; 4]).await.unwrap().retcode, 0);
+        assert_eq!(upload(vec![0; 5]).await.unwrap().retcode, 10003);
+        let prepare = |total_size: i64| {
+            files.handle(action(
+                "upload_file_fragmented",
+                value_map! {"stage": "prepare", "name": "a.bin", "total_size": total_size},
+            ))
+        };
+        assert_eq!(prepare(1 << 40).await.unwrap().retcode, 10003);
+        let resp = prepare(4).await.unwrap();
+        let file_id = FileId::try_from(resp.data).unwrap().file_id;
+        assert!(files.store().files.contains_key(&file_id));
+        // 再次 prepare 时清理超时的上传
+        assert_eq!(prepare(4).await.unwrap().retcode, 0);
+        assert!(!files.store().files.contains_key(&file_id));
+        let transfer = files
+            .handle(action(
+                "upload_file_fragmented",
+                value_map! {"stage": "transfer", "file_id": file_id, "offset": 0, "data": vec![0u8]},
+            ))
+            .await
+            .unwrap();
+        assert_eq!(transfer.retcode, 10003);
+        let https = files
+            .handle(action(
+                "upload_file",
+                value_map! {"type": "url", "name": "a.bin", "url": "https://127.0.0.1/a.bin"},
+            ))
+            .await
+            .unwrap();
+        assert_eq!(https.retcode, 10004);
+    }
+
+    #[tokio::test]
+    async fn load_source() {
+        let files = Files::new(MemoryStore::default());
+        let path = std::env::temp_dir().join(format!("walle-source-{}", new_uuid()));
+        std::fs::write(&path, b"walle").unwrap();
+        for s in [
+            format!("\"file://{}\"", path.to_string_lossy()),
+            "\"base64://d2FsbGU=\"".to_string(),
+        ] {
+            let source: BytesSource = serde_json::from_str(&s).unwrap();
+            let data = files.load(source, HashMap::default()).await.unwrap();
+            assert_eq!(data, b"walle");
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn local_store() {
+        let root = std::env::temp_dir().join(format!("walle-file-{}", new_uuid()));
+        let files = Files::new(LocalStore::new(&root));
+        fragmented(&files).await;
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -12,17 +12,37 @@ pub mod alt;
 pub mod config;
 pub mod error;
 pub mod event;
+#[cfg(feature = "file-store")]
+pub mod file;
+pub mod middleware;
 pub mod resp;
 pub mod segment;
+#[cfg(feature = "tower")]
+pub mod service;
 pub mod structs;
 pub mod util;
 
+#[cfg(feature = "v11")]
+pub mod com;
+#[cfg(feature = "v11")]
+pub mod cq;
+#[cfg(feature = "v11")]
+pub mod v11;
+
 mod ah;
-mod com;
-mod v11;
-pub use ah::{ActionHandler, GetSelfs, GetStatus, GetVersion};
+pub use ah::{
+    AHExt, ActionHandler, GetSelfs, GetStatus, GetVersion, JoinedActionHandler, JoinedHandler,
+    SelftRouter,
+};
 mod eh;
-pub use eh::EventHandler;
+pub use eh::{Detached, DispatchMode, EHExt, EventHandler, JoinedEventHandler};
+mod set;
+pub use set::{
+    ActionHandlerSet, ActionSetContext, ActionSetOneBot, EventHandlerSet, EventSetContext,
+    EventSetOneBot, HandlerConfigs, Paired,
+};
+mod task;
+pub use task::ShutdownReport;
 use tokio::task::JoinHandle;
 
 #[cfg(any(feature = "impl-obc", feature = "app-obc"))]
@@ -55,9 +75,16 @@ pub struct OneBot<AH, EH> {
     signal: StdMutex<Option<tokio::sync::broadcast::Sender<()>>>,
     ah_tasks: Mutex<Vec<JoinHandle<()>>>,
     eh_tasks: Mutex<Vec<JoinHandle<()>>>,
+    // 连接、Action 等进行中的任务
+    tasks: Arc<task::Tasks>,
+    // Middleware<A, R> 与 Middleware<E, ()> 栈
+    action_layers: Layers,
+    event_layers: Layers,
 }
 
+use middleware::{Layers, Middleware, Next};
 use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
 use tokio::sync::Mutex;
 
 pub use crate::error::{WalleError, WalleResult};
@@ -70,8 +97,36 @@ impl<AH, EH> OneBot<AH, EH> {
             signal: StdMutex::new(None),
             ah_tasks: Mutex::default(),
             eh_tasks: Mutex::default(),
+            tasks: Arc::default(),
+            action_layers: Layers::default(),
+            event_layers: Layers::default(),
         }
     }
+    /// 添加 handle_action 中间件，先添加者位于外层
+    ///
+    /// 须在首次 handle_action 前添加，且所有 Action 中间件的类型须一致
+    pub fn layer_action<A, R>(&self, middleware: impl Middleware<A, R> + 'static) -> WalleResult<()>
+    where
+        A: 'static,
+        R: 'static,
+    {
+        self.action_layers.push::<A, R>(Arc::new(middleware))
+    }
+    /// 添加 handle_event 中间件，先添加者位于外层
+    ///
+    /// 须在首次 handle_event 前添加，且所有 Event 中间件的类型须一致
+    pub fn layer_event<E>(&self, middleware: impl Middleware<E, ()> + 'static) -> WalleResult<()>
+    where
+        E: 'static,
+    {
+        self.event_layers.push::<E, ()>(Arc::new(middleware))
+    }
+    pub fn action_handler(&self) -> &AH {
+        &self.action_handler
+    }
+    pub fn event_handler(&self) -> &EH {
+        &self.event_handler
+    }
     pub async fn start<E, A, R>(
         self: &Arc<Self>,
         ah_config: AH::Config,
@@ -97,11 +152,53 @@ impl<AH, EH> OneBot<AH, EH> {
         }
         Ok(())
     }
+    /// 运行中以新配置重载 ActionHandler，未变化的连接保持不变
+    pub async fn reload_action_handler<E, A, R>(
+        self: &Arc<Self>,
+        ah_config: AH::Config,
+    ) -> WalleResult<()>
+    where
+        E: Send + Sync + 'static,
+        A: Send + Sync + 'static,
+        R: Send + Sync + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        if !self.is_started() {
+            return Err(WalleError::NotStarted);
+        }
+        let joins = self.action_handler.reload(self, ah_config).await?;
+        let mut tasks = self.ah_tasks.lock().await;
+        tasks.retain(|task| !task.is_finished());
+        tasks.extend(joins);
+        Ok(())
+    }
+    /// 运行中以新配置重载 EventHandler，未变化的连接保持不变
+    pub async fn reload_event_handler<E, A, R>(
+        self: &Arc<Self>,
+        eh_config: EH::Config,
+    ) -> WalleResult<()>
+    where
+        E: Send + Sync + 'static,
+        A: Send + Sync + 'static,
+        R: Send + Sync + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        if !self.is_started() {
+            return Err(WalleError::NotStarted);
+        }
+        let joins = self.event_handler.reload(self, eh_config).await?;
+        let mut tasks = self.eh_tasks.lock().await;
+        tasks.retain(|task| !task.is_finished());
+        tasks.extend(joins);
+        Ok(())
+    }
     pub async fn wait_all(&self) {
         let mut tasks: Vec<JoinHandle<()>> = std::mem::take(self.ah_tasks.lock().await.as_mut());
-        tasks.extend(
-            std::mem::take::<Vec<JoinHandle<()>>>(self.eh_tasks.lock().await.as_mut()).into_iter(),
-        );
+        tasks.extend(std::mem::take::<Vec<JoinHandle<()>>>(
+            self.eh_tasks.lock().await.as_mut(),
+        ));
         for task in tasks {
             task.await.ok();
         }
@@ -150,9 +247,111 @@ impl<AH, EH> OneBot<AH, EH> {
             self.event_handler.shutdown().await;
             self.action_handler.shutdown().await;
         }
-        Ok(self.wait_all().await)
+        self.wait_all().await;
+        Ok(())
+    }
+    /// 登记进行中的任务，优雅关闭时将等待其结束或在超时后中止
+    pub fn spawn<F>(&self, name: impl Into<String>, fut: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(name.into(), fut)
+    }
+    /// 进行中的任务数
+    pub fn running_tasks(&self) -> usize {
+        self.tasks.len()
+    }
+    /// 优雅关闭：停止接受新连接，等待进行中的 Action 与 Event 处理完成，
+    /// 超过 timeout 后中止剩余任务并在结果中列出
+    pub async fn shutdown_graceful<E, A, R>(
+        &self,
+        ah_first: bool,
+        timeout: Duration,
+    ) -> WalleResult<ShutdownReport>
+    where
+        E: Send + Sync + 'static,
+        A: Send + Sync + 'static,
+        R: Send + Sync + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let tx = self
+            .signal
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or(WalleError::NotStarted)?;
+        tx.send(()).ok();
+        if ah_first {
+            self.action_handler.shutdown().await;
+            self.event_handler.shutdown().await;
+        } else {
+            self.event_handler.shutdown().await;
+            self.action_handler.shutdown().await;
+        }
+        let mut handles = vec![];
+        for (name, tasks) in [
+            ("action handler", &self.ah_tasks),
+            ("event handler", &self.eh_tasks),
+        ] {
+            let tasks = std::mem::take(&mut *tasks.lock().await);
+            handles.extend(tasks.into_iter().map(|task| (name, task)));
+        }
+        let mut report = ShutdownReport {
+            finished: handles.len() + self.tasks.len(),
+            aborted: vec![],
+        };
+        let handles = async {
+            let mut aborted = vec![];
+            for (name, mut task) in handles {
+                if tokio::time::timeout_at(deadline, &mut task).await.is_err() {
+                    task.abort();
+                    aborted.push(format!("{} task", name));
+                }
+            }
+            aborted
+        };
+        let tasks = self
+            .tasks
+            .drain(deadline.saturating_duration_since(tokio::time::Instant::now()));
+        let (aborted, mut tasks_aborted) = tokio::join!(handles, tasks);
+        report.aborted = aborted;
+        report.aborted.append(&mut tasks_aborted);
+        report.finished = report.finished.saturating_sub(report.aborted.len());
+        for name in &report.aborted {
+            tracing::warn!(target: WALLE_CORE, "task aborted on shutdown: {}", name);
+        }
+        Ok(report)
     }
     pub async fn handle_event<E, A, R>(self: &Arc<Self>, event: E) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+        E: Send + 'static,
+    {
+        let layers = self.event_layers.get::<E, ()>()?;
+        if layers.is_empty() {
+            return self.call_event(event).await;
+        }
+        let inner = |event| Box::pin(self.call_event(event)) as _;
+        Next::new(layers, &inner).run(event).await
+    }
+    pub async fn handle_action<E, A, R>(self: &Arc<Self>, action: A) -> WalleResult<R>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+        A: Send + 'static,
+        R: Send + 'static,
+    {
+        let layers = self.action_layers.get::<A, R>()?;
+        if layers.is_empty() {
+            return self.call_action(action).await;
+        }
+        let inner = |action| Box::pin(self.call_action(action)) as _;
+        Next::new(layers, &inner).run(action).await
+    }
+    async fn call_event<E, A, R>(self: &Arc<Self>, event: E) -> WalleResult<()>
     where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
@@ -166,7 +365,7 @@ impl<AH, EH> OneBot<AH, EH> {
             .await?;
         self.action_handler.after_call_event(self).await
     }
-    pub async fn handle_action<E, A, R>(self: &Arc<Self>, action: A) -> WalleResult<R>
+    async fn call_action<E, A, R>(self: &Arc<Self>, action: A) -> WalleResult<R>
     where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
@@ -195,6 +394,9 @@ where
     async fn is_good(&self) -> bool {
         self.action_handler.is_good().await
     }
+    async fn get_bots(&self) -> Vec<structs::Bot> {
+        self.action_handler.get_bots().await
+    }
     async fn get_status(&self) -> structs::Status {
         self.action_handler.get_status().await
     }
@@ -211,4 +413,10 @@ where
     async fn get_selfs(&self) -> Vec<structs::Selft> {
         self.action_handler.get_selfs().await
     }
+    async fn has_self(&self, selft: &structs::Selft) -> bool {
+        self.action_handler.has_self(selft).await
+    }
+    fn selfs_epoch(&self) -> u64 {
+        self.action_handler.selfs_epoch()
+    }
 }
@@ -0,0 +1,124 @@
+//! OneBot.handle_action 与 OneBot.handle_event 的中间件
+//!
+//! 中间件按添加顺序由外向内包裹处理过程，可在调用 `next.run` 前后读取或改写请求与结果，
+//! 也可不调用 `next` 直接返回，用于日志、鉴权、限流、统计等
+
+use std::any::{type_name, Any};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use futures_util::future::BoxFuture;
+
+use crate::error::{WalleError, WalleResult};
+
+/// 中间件，Action 中间件为 `Middleware<A, R>`，Event 中间件为 `Middleware<E, ()>`
+///
+/// 闭包可通过 `from_fn` 转换为中间件
+pub trait Middleware<I, O>: Send + Sync {
+    fn call<'a>(&'a self, item: I, next: Next<'a, I, O>) -> BoxFuture<'a, WalleResult<O>>;
+}
+
+/// 由闭包构造的中间件
+pub struct FromFn<F>(F);
+
+/// 以 `Fn(I, Next<I, O>) -> BoxFuture<WalleResult<O>>` 闭包构造中间件
+pub fn from_fn<I, O, F>(f: F) -> FromFn<F>
+where
+    F: for<'a> Fn(I, Next<'a, I, O>) -> BoxFuture<'a, WalleResult<O>> + Send + Sync,
+{
+    FromFn(f)
+}
+
+impl<I, O, F> Middleware<I, O> for FromFn<F>
+where
+    F: for<'a> Fn(I, Next<'a, I, O>) -> BoxFuture<'a, WalleResult<O>> + Send + Sync,
+{
+    fn call<'a>(&'a self, item: I, next: Next<'a, I, O>) -> BoxFuture<'a, WalleResult<O>> {
+        (self.0)(item, next)
+    }
+}
+
+type Inner<'a, I, O> = dyn Fn(I) -> BoxFuture<'a, WalleResult<O>> + Send + Sync + 'a;
+
+/// 中间件链中余下的部分
+pub struct Next<'a, I, O> {
+    layers: &'a [Arc<dyn Middleware<I, O>>],
+    inner: &'a Inner<'a, I, O>,
+}
+
+impl<'a, I, O> Next<'a, I, O> {
+    pub(crate) fn new(layers: &'a [Arc<dyn Middleware<I, O>>], inner: &'a Inner<'a, I, O>) -> Self {
+        Self { layers, inner }
+    }
+    /// 调用下一层中间件，无剩余中间件时调用 Handler
+    pub fn run(self, item: I) -> BoxFuture<'a, WalleResult<O>> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => layer.call(
+                item,
+                Next {
+                    layers,
+                    inner: self.inner,
+                },
+            ),
+            None => (self.inner)(item),
+        }
+    }
+}
+
+type Stack<I, O> = Vec<Arc<dyn Middleware<I, O>>>;
+
+/// 一种 I/O 类型的中间件栈，首次处理请求时固定，此后读取无需加锁
+pub(crate) struct Layers {
+    pending: Mutex<Option<Box<dyn Any + Send + Sync>>>,
+    frozen: OnceLock<Box<dyn Any + Send + Sync>>,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self {
+            pending: Mutex::new(None),
+            frozen: OnceLock::new(),
+        }
+    }
+}
+
+fn mismatch<I, O>() -> WalleError {
+    WalleError::ValueTypeNotMatch(
+        "middleware of the first registered type".to_string(),
+        format!("Middleware<{}, {}>", type_name::<I>(), type_name::<O>()),
+    )
+}
+
+impl Layers {
+    /// 添加中间件，栈已固定或类型与已添加者不同时返回错误
+    pub(crate) fn push<I: 'static, O: 'static>(
+        &self,
+        layer: Arc<dyn Middleware<I, O>>,
+    ) -> WalleResult<()> {
+        let mut pending = self.pending.lock().unwrap();
+        if self.frozen.get().is_some() {
+            return Err(WalleError::Other(
+                "middleware must be added before the first request is handled".to_string(),
+            ));
+        }
+        let stack = pending.get_or_insert_with(|| Box::new(Stack::<I, O>::new()));
+        stack
+            .downcast_mut::<Stack<I, O>>()
+            .ok_or_else(mismatch::<I, O>)?
+            .push(layer);
+        Ok(())
+    }
+    /// 固定并返回中间件栈
+    pub(crate) fn get<I: 'static, O: 'static>(&self) -> WalleResult<&[Arc<dyn Middleware<I, O>>]> {
+        self.frozen
+            .get_or_init(|| {
+                self.pending
+                    .lock()
+                    .unwrap()
+                    .take()
+                    .unwrap_or_else(|| Box::new(Stack::<I, O>::new()))
+            })
+            .downcast_ref::<Stack<I, O>>()
+            .map(Vec::as_slice)
+            .ok_or_else(mismatch::<I, O>)
+    }
+}
@@ -1,191 +1,195 @@
-use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+use std::{convert::Infallible, sync::Arc, time::Duration};
 
 use crate::{
     config::{HttpClient, HttpServer},
     error::{WalleError, WalleResult},
     prelude::Bot,
     structs::Selft,
-    util::{AuthReqHeaderExt, Echo, GetSelf, ProtocolItem},
+    util::{ActionName, AuthReqHeaderExt, ContentType, Echo, GetSelf, ProtocolItem},
     ActionHandler, EventHandler, OneBot,
 };
+use color_eyre::eyre;
 use hyper::{
-    body::Buf,
     client::HttpConnector,
     header::{AUTHORIZATION, CONTENT_TYPE},
     server::conn::Http,
     service::service_fn,
-    Body, Client as HyperClient, Method, Request, Response,
+    Body, Client as HyperClient, HeaderMap, Method, Request, Response,
 };
 use tokio::{net::TcpListener, task::JoinHandle};
 use tracing::{info, warn};
 
-use super::{AppOBC, EchoMap};
+use super::{AppOBC, EchoMap, Signal};
 
 impl<A, R> AppOBC<A, R>
 where
-    A: ProtocolItem,
+    A: ProtocolItem + ActionName,
     R: ProtocolItem,
 {
     pub(crate) async fn webhook<E, AH, EH>(
         &self,
         ob: &Arc<OneBot<AH, EH>>,
-        config: Vec<HttpServer>,
-        tasks: &mut Vec<JoinHandle<()>>,
-    ) -> WalleResult<()>
+        webhook: HttpServer,
+        mut signal: Signal,
+    ) -> WalleResult<JoinHandle<()>>
     where
         E: ProtocolItem + GetSelf + Clone,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        for webhook in config {
-            let bot_map = self.bots.clone();
-            let echo_map = self.echos.clone();
-            let access_token = webhook.access_token.clone();
-            let mut signal_rx = ob.get_signal_rx()?;
+        let bot_map = self.bots.clone();
+        let echo_map = self.echos.clone();
+        let access_token = webhook.access_token.clone();
+        let timeout = webhook.action_timeout.clone();
+        let content_type = webhook.content_type;
+        let timeouts = self.timeouts.endpoint(webhook.action_timeout);
+        let conn_ob = ob.clone();
+        let ob = ob.clone();
+        let addr = std::net::SocketAddr::new(webhook.host, webhook.port);
+        info!(
+            target: crate::WALLE_CORE,
+            "Starting HTTP Webhook server on http://{}", addr
+        );
+        let listener = TcpListener::bind(&addr).await.map_err(WalleError::from)?;
+        let serv = service_fn(move |req: Request<Body>| {
+            let access_token = access_token.clone();
+            let timeout = timeout.clone();
+            // 等待 EventHandler 产生 Action 以随响应返回
+            let wait = timeouts.get::<A>(None, 8);
             let ob = ob.clone();
-            let addr = std::net::SocketAddr::new(webhook.host, webhook.port);
-            info!(
-                target: crate::WALLE_CORE,
-                "Starting HTTP Webhook server on http://{}", addr
-            );
-            let listener = TcpListener::bind(&addr).await.map_err(WalleError::from)?;
-            let serv = service_fn(move |req: Request<Body>| {
-                let access_token = access_token.clone();
-                let ob = ob.clone();
-                let bot_map = bot_map.clone();
-                let echo_map = echo_map.clone();
-                async move {
-                    if let Some(token) = access_token.as_ref() {
-                        if let Some(header_token) = req
-                            .headers()
-                            .get(AUTHORIZATION)
-                            .and_then(|v| v.to_str().ok())
-                        {
-                            if header_token != format!("Bearer {}", token) {
-                                return Ok(Response::builder()
-                                    .status(403)
-                                    .body("Authorization Header is invalid".into())
-                                    .unwrap());
-                            }
-                        } else {
+            let bot_map = bot_map.clone();
+            let echo_map = echo_map.clone();
+            async move {
+                if let Some(token) = access_token.as_ref() {
+                    if let Some(header_token) = req
+                        .headers()
+                        .get(AUTHORIZATION)
+                        .and_then(|v| v.to_str().ok())
+                    {
+                        if header_token != format!("Bearer {}", token) {
                             return Ok(Response::builder()
                                 .status(403)
-                                .body("Missing Authorization Header".into())
+                                .body("Authorization Header is invalid".into())
                                 .unwrap());
                         }
+                    } else {
+                        return Ok(Response::builder()
+                            .status(403)
+                            .body("Missing Authorization Header".into())
+                            .unwrap());
                     }
-                    let implt = req
-                        .headers()
-                        .get("X-Impl")
-                        .and_then(|v| v.to_str().ok())
-                        .map(|s| s.to_owned())
-                        .unwrap_or_default();
-                    let body = String::from_utf8(
-                        hyper::body::to_bytes(req.into_body())
-                            .await
-                            .unwrap()
-                            .to_vec(),
-                    )
-                    .unwrap();
-                    match E::json_decode(&body) {
-                        Ok(event) => {
-                            let (seq, mut action_rx) = bot_map.new_connect();
-                            let selft = event.get_self();
-                            bot_map.connect_update(
-                                &seq,
-                                vec![Bot {
-                                    online: true,
-                                    selft,
-                                }],
-                                &implt,
-                            );
-                            if let Err(e) = ob.handle_event(event).await {
-                                warn!(target: super::OBC, "{}", e);
-                            }
-                            if let Ok(Some(a)) = tokio::time::timeout(
-                                std::time::Duration::from_secs(8),
-                                action_rx.recv(),
-                            )
-                            .await
-                            {
-                                let echo_s = a.get_echo();
-                                echo_map.remove(&echo_s);
-                                bot_map.connect_closs(&seq);
-                                return Ok(Response::new(a.json_encode().into()));
-                            }
+                }
+                let implt = req
+                    .headers()
+                    .get("X-Impl")
+                    .and_then(|v| v.to_str().ok())
+                    .map(|s| s.to_owned())
+                    .unwrap_or_default();
+                // 响应编码跟随请求，除非指定了 content_type
+                let req_type = content_type_of(req.headers()).unwrap_or_default();
+                let resp_type = content_type.unwrap_or(req_type);
+                let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                match decode::<E>(&body, req_type) {
+                    Ok(event) => {
+                        let (seq, mut action_rx) = bot_map.new_connect(timeout);
+                        let selft = event.get_self();
+                        bot_map.connect_update(
+                            &seq,
+                            vec![Bot {
+                                online: true,
+                                selft,
+                            }],
+                            &implt,
+                        );
+                        if let Err(e) = ob.handle_event(event).await {
+                            warn!(target: super::OBC, "{}", e);
+                        }
+                        let action = tokio::time::timeout(wait, action_rx.recv()).await;
+                        bot_map.connect_closs(&seq);
+                        if let Ok(Some(a)) = action {
+                            let echo_s = a.get_echo();
+                            echo_map.remove(&echo_s);
+                            return Ok(Response::builder()
+                                .header(CONTENT_TYPE, resp_type.to_string())
+                                .body(a.to_body(&resp_type))
+                                .unwrap());
                         }
-                        Err(s) => warn!(target: crate::WALLE_CORE, "Webhook json error: {}", s),
                     }
-                    Ok::<Response<Body>, Infallible>(Response::new("".into()))
+                    Err(s) => warn!(target: crate::WALLE_CORE, "Webhook decode error: {}", s),
                 }
-            });
-            tasks.push(tokio::spawn(async move {
-                loop {
-                    let service = serv.clone();
-                    tokio::select! {
-                        _ = signal_rx.recv() => break,
-                        Ok((tcp_stream, _)) = listener.accept() => {
-                            tokio::spawn(async move {
-                                Http::new()
-                                    .serve_connection(tcp_stream, service)
-                                    .await
-                                    .unwrap();
-                            });
-                        }
+                Ok::<Response<Body>, Infallible>(Response::new("".into()))
+            }
+        });
+        Ok(tokio::spawn(async move {
+            loop {
+                let service = serv.clone();
+                tokio::select! {
+                    _ = signal.recv() => break,
+                    Ok((tcp_stream, addr)) = listener.accept() => {
+                        let mut signal = signal.resubscribe();
+                        conn_ob.spawn(format!("http connection {}", addr), async move {
+                            let conn = Http::new().serve_connection(tcp_stream, service);
+                            tokio::pin!(conn);
+                            tokio::select! {
+                                _ = &mut conn => {}
+                                // 处理完进行中的请求后断开
+                                _ = signal.recv() => {
+                                    conn.as_mut().graceful_shutdown();
+                                    conn.await.ok();
+                                }
+                            }
+                        });
                     }
                 }
-            }));
-        }
-        Ok(())
+            }
+        }))
     }
 
     pub(crate) async fn http<E, AH, EH>(
         &self,
         ob: &Arc<OneBot<AH, EH>>,
-        config: HashMap<String, HttpClient>,
-        tasks: &mut Vec<JoinHandle<()>>,
-    ) -> WalleResult<()>
+        bot_id: String,
+        http: HttpClient,
+        mut signal: Signal,
+    ) -> WalleResult<JoinHandle<()>>
     where
         E: ProtocolItem + GetSelf + Clone,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
         let client = Arc::new(HyperClient::new());
-        for (bot_id, http) in config {
-            let (seq, mut rx) = self.bots.new_connect();
-            let implt = http.implt.clone().unwrap_or_default();
-            self.bots.connect_update(
-                &seq,
-                vec![Bot {
-                    online: true,
-                    selft: Selft {
-                        platform: http.platform.clone().unwrap_or_default(),
-                        user_id: bot_id.clone(),
-                    },
-                }],
-                &implt,
-            );
-            let ob = ob.clone();
-            let cli = client.clone();
-            let echo_map = self.echos.clone();
-            let mut signal_rx = ob.get_signal_rx()?;
-            tasks.push(tokio::spawn(async move {
-                loop {
-                    tokio::select! {
-                        _ = signal_rx.recv() => break,
-                        Some(action) = rx.recv() => {
-                            tokio::spawn(http_push(
-                                action,
-                                cli.clone(),
-                                http.clone(),
-                                echo_map.clone(),
-                            ));
-                        }
+        let (seq, mut rx) = self.bots.new_connect(http.action_timeout.clone());
+        let implt = http.implt.clone().unwrap_or_default();
+        self.bots.connect_update(
+            &seq,
+            vec![Bot {
+                online: true,
+                selft: Selft {
+                    platform: http.platform.clone().unwrap_or_default(),
+                    user_id: bot_id,
+                },
+            }],
+            &implt,
+        );
+        let ob = ob.clone();
+        let echo_map = self.echos.clone();
+        let bot_map = self.bots.clone();
+        Ok(tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = signal.recv() => break,
+                    Some(action) = rx.recv() => {
+                        ob.spawn(format!("http push {}", http.url), http_push(
+                            action,
+                            client.clone(),
+                            http.clone(),
+                            echo_map.clone(),
+                        ));
                     }
                 }
-            }));
-        }
-        Ok(())
+            }
+            bot_map.connect_closs(&seq);
+        }))
     }
 }
 
@@ -195,23 +199,33 @@ async fn http_push<A, R>(
     http: HttpClient,
     echo_map: EchoMap<R>,
 ) where
-    A: ProtocolItem,
+    A: ProtocolItem + ActionName,
     R: ProtocolItem,
 {
     let (action, echo_s) = action.unpack();
+    let content_type = http.content_type.unwrap_or_default();
     let req = Request::builder()
         .method(Method::POST)
         .uri(&http.url)
         .header_auth_token(&http.access_token)
-        .header(CONTENT_TYPE, crate::util::ContentType::Json.to_string())
-        .body(action.to_body(&crate::util::ContentType::Json)) //todo
+        .header(CONTENT_TYPE, content_type.to_string())
+        .body(action.to_body(&content_type))
         .unwrap();
     match tokio::time::timeout(Duration::from_secs(http.timeout), client.request(req)).await {
         Ok(Ok(resp)) => {
-            let body = hyper::body::aggregate(resp).await.unwrap(); //todo
-            let r: R = serde_json::from_reader(body.reader()).unwrap();
-            if let Some((_, r_tx)) = echo_map.remove(&echo_s) {
-                r_tx.send(r).ok();
+            // 按响应的 Content-Type 解码
+            let content_type = content_type_of(resp.headers()).unwrap_or(content_type);
+            let r = hyper::body::to_bytes(resp)
+                .await
+                .map_err(eyre::Report::from)
+                .and_then(|body| decode::<R>(&body, content_type));
+            match r {
+                Ok(r) => {
+                    if let Some((_, r_tx)) = echo_map.remove(&echo_s) {
+                        r_tx.send(r).ok();
+                    }
+                }
+                Err(e) => warn!(target: crate::WALLE_CORE, "HTTP push resp error: {}", e),
             }
         }
         Ok(Err(e)) => {
@@ -222,3 +236,17 @@ async fn http_push<A, R>(
         }
     }
 }
+
+fn content_type_of(headers: &HeaderMap) -> Option<ContentType> {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| ContentType::new(v.split(';').next().unwrap_or_default().trim()))
+}
+
+fn decode<T: ProtocolItem>(data: &[u8], content_type: ContentType) -> Result<T, eyre::Report> {
+    match content_type {
+        ContentType::Json => T::json_decode(std::str::from_utf8(data)?),
+        ContentType::MsgPack => T::rmp_decode(data),
+    }
+}
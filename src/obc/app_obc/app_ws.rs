@@ -1,20 +1,23 @@
 use crate::{
-    com::ComEvent,
-    config::{WebSocketClient, WebSocketServer},
+    com::{ComEvent, ConnState, Protocol},
+    config::{ActionTimeout, WebSocketClient, WebSocketServer},
     error::{ResultExt, WalleError, WalleResult},
     event::{Event, MetaDetailEvent, MetaTypes},
-    util::{AuthReqHeaderExt, Echo, GetSelf, ProtocolItem},
+    structs::Bot,
+    util::{ActionName, AuthReqHeaderExt, Echo, EchoS, GetSelf, ProtocolItem},
     ActionHandler, EventHandler, OneBot,
 };
 use crate::{
     obc::{
-        ws_util::{try_connect, upgrade_websocket},
-        AppOBC, BotMap, EchoMap,
+        ws_util::{close_msg, try_connect, upgrade_websocket},
+        AppOBC, BotMap, EchoMap, Signal,
     },
     util::ContentType,
 };
 
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 use color_eyre::eyre;
 use futures_util::{SinkExt, StreamExt};
@@ -30,122 +33,189 @@ use tracing::{info, warn};
 
 impl<A, R> AppOBC<A, R>
 where
-    A: ProtocolItem,
+    A: ProtocolItem + ActionName,
     R: ProtocolItem,
 {
     pub(crate) async fn ws<E, AH, EH>(
         &self,
         ob: &Arc<OneBot<AH, EH>>,
-        config: Vec<WebSocketClient>,
-        tasks: &mut Vec<JoinHandle<()>>,
-    ) -> WalleResult<()>
+        wsc: WebSocketClient,
+        mut signal: Signal,
+    ) -> WalleResult<JoinHandle<()>>
     where
         E: ProtocolItem + GetSelf + Clone,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        for wsc in config {
-            info!(target: super::OBC, "Start try connect to {}", wsc.url);
-            let ob = ob.clone();
-            let echo_map = self.echos.clone();
-            let bot_map = self.bots.clone();
-            let mut signal_rx = ob.get_signal_rx()?;
-            tasks.push(tokio::spawn(async move {
-                while signal_rx.try_recv().is_err() {
-                    let ob = ob.clone();
-                    let echo_map = echo_map.clone();
-                    let bot_map = bot_map.clone();
-                    let req = Request::builder()
-                        .header(
-                            USER_AGENT,
-                            format!("OneBot/12 Walle-App/{}", crate::VERSION),
+        info!(target: super::OBC, "Start try connect to {}", wsc.url);
+        let timeout = wsc.action_timeout.clone();
+        let ob = ob.clone();
+        let echo_map = self.echos.clone();
+        let bot_map = self.bots.clone();
+        Ok(tokio::spawn(async move {
+            while !signal.is_set() {
+                let ob = ob.clone();
+                let echo_map = echo_map.clone();
+                let bot_map = bot_map.clone();
+                let req = Request::builder()
+                    .header(
+                        USER_AGENT,
+                        format!("OneBot/12 Walle-App/{}", crate::VERSION),
+                    )
+                    .header_auth_token(&wsc.access_token);
+                match try_connect(&wsc, req).await {
+                    Some(ws_stream) => {
+                        let mut conn = ConnState::default();
+                        conn.content_type = wsc.content_type;
+                        ws_loop(
+                            ob,
+                            ws_stream,
+                            echo_map,
+                            bot_map,
+                            conn,
+                            timeout.clone(),
+                            signal.resubscribe(),
                         )
-                        .header_auth_token(&wsc.access_token);
-                    match try_connect(&wsc, req).await {
-                        Some(ws_stream) => {
-                            ws_loop(ob, ws_stream, echo_map, bot_map).await;
-                            warn!(target: crate::WALLE_CORE, "Disconnected from {}", wsc.url);
-                        }
-                        None => {
-                            tokio::time::sleep(std::time::Duration::from_secs(
+                        .await;
+                        warn!(target: crate::WALLE_CORE, "Disconnected from {}", wsc.url);
+                    }
+                    None => {
+                        tokio::select! {
+                            _ = tokio::time::sleep(std::time::Duration::from_secs(
                                 wsc.reconnect_interval as u64,
-                            ))
-                            .await;
+                            )) => {}
+                            _ = signal.recv() => break,
                         }
                     }
                 }
-            }));
-        }
-        Ok(())
+            }
+        }))
     }
     pub(crate) async fn wsr<E, AH, EH>(
         &self,
         ob: &Arc<OneBot<AH, EH>>,
-        config: Vec<WebSocketServer>,
-        tasks: &mut Vec<JoinHandle<()>>,
-    ) -> WalleResult<()>
+        wss: WebSocketServer,
+        mut signal: Signal,
+    ) -> WalleResult<JoinHandle<()>>
     where
         E: ProtocolItem + GetSelf + Clone,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        for wss in config {
-            let addr = std::net::SocketAddr::new(wss.host, wss.port);
-            let tcp_listener = TcpListener::bind(&addr).await.map_err(WalleError::IO)?;
-            info!(
-                target: super::OBC,
-                "Websocket server listening on ws://{}", addr
-            );
-            let ob = ob.clone();
-            let mut signal_rx = ob.get_signal_rx()?;
-            let echo_map = self.echos.clone();
-            let bot_map = self.bots.clone();
-            tasks.push(tokio::spawn(async move {
-                loop {
-                    tokio::select! {
-                        _ = signal_rx.recv() => {
-                            info!(target: super::OBC, "Stop listening on ws://{}", addr);
-                            break;
-                        }
-                        Ok((stream, _)) = tcp_listener.accept() => {
-                            if let Some((ws_stream, _implt)) =
-                                upgrade_websocket(&wss.access_token, stream)
-                                    .await
-                            {
-                                let ob = ob.clone();
-                                tokio::spawn(ws_loop(ob.clone(), ws_stream, echo_map.clone(), bot_map.clone()));
-                            }
+        let addr = std::net::SocketAddr::new(wss.host, wss.port);
+        let tcp_listener = TcpListener::bind(&addr).await.map_err(WalleError::IO)?;
+        info!(
+            target: super::OBC,
+            "Websocket server listening on ws://{}", addr
+        );
+        let ob = ob.clone();
+        let echo_map = self.echos.clone();
+        let bot_map = self.bots.clone();
+        Ok(tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = signal.recv() => {
+                        info!(target: super::OBC, "Stop listening on ws://{}", addr);
+                        break;
+                    }
+                    Ok((stream, addr)) = tcp_listener.accept() => {
+                        if let Some((ws_stream, handshake)) =
+                            upgrade_websocket(&wss.access_token, stream)
+                                .await
+                        {
+                            let mut conn = ConnState::from_handshake(
+                                handshake.self_id.as_deref(),
+                                handshake.user_agent.as_deref(),
+                            );
+                            conn.content_type = wss.content_type;
+                            ob.spawn(
+                                format!("websocket connection {}", addr),
+                                ws_loop(
+                                    ob.clone(),
+                                    ws_stream,
+                                    echo_map.clone(),
+                                    bot_map.clone(),
+                                    conn,
+                                    wss.action_timeout.clone(),
+                                    signal.resubscribe(),
+                                ),
+                            );
                         }
                     }
                 }
-            }));
-        }
-        Ok(())
+            }
+        }))
     }
 }
 
+/// 关闭时检查进行中 Action 的间隔
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
 async fn ws_loop<E, A, R, AH, EH>(
     ob: Arc<OneBot<AH, EH>>,
     mut ws_stream: WebSocketStream<TcpStream>,
     echo_map: EchoMap<R>,
     bot_map: Arc<BotMap<A>>,
-    // implt: String,
+    mut conn: ConnState,
+    timeout: ActionTimeout,
+    mut signal: Signal,
 ) where
     E: ProtocolItem + GetSelf + Clone,
-    A: ProtocolItem,
+    A: ProtocolItem + ActionName,
     R: ProtocolItem,
     AH: ActionHandler<E, A, R> + Send + Sync + 'static,
     EH: EventHandler<E, A, R> + Send + Sync + 'static,
 {
-    let (seq, mut action_rx) = bot_map.new_connect();
-    let mut signal_rx = ob.get_signal_rx().unwrap(); //todo
-    let mut implt = None;
+    let (seq, mut action_rx) = bot_map.new_connect(timeout);
+    if let Some(selft) = conn.selft() {
+        bot_map.connect_update(
+            &seq,
+            vec![Bot {
+                selft,
+                online: true,
+            }],
+            &conn.implt,
+        );
+    }
+    // 本连接上已发送、尚未响应的 Action，超时或已响应者自 echo_map 移除
+    let mut pending: HashSet<EchoS> = HashSet::default();
+    // 收到关闭信号后等待本连接上进行中的 Action 响应完成再断开
+    let mut shutdown = false;
     loop {
+        if shutdown {
+            pending.retain(|echo| echo_map.contains_key(echo));
+            if pending.is_empty() {
+                break;
+            }
+        }
         tokio::select! {
-            _ = signal_rx.recv() => break,
-            Some(action) = action_rx.recv() => {
-                if ws_stream.send(action.to_ws_msg(&ContentType::Json)).await.is_err() { //todo
-                    break;
+            _ = signal.recv(), if !shutdown => {
+                shutdown = true;
+                // 不再接收新的 Action，已排队者直接以错误结束
+                action_rx.close();
+                while let Ok(action) = action_rx.try_recv() {
+                    echo_map.remove(&action.get_echo());
+                }
+            },
+            // 超时的 Action 不会有响应，定期检查 pending
+            _ = tokio::time::sleep(SHUTDOWN_POLL), if shutdown => {},
+            Some(action) = action_rx.recv(), if !shutdown => {
+                // 超时的 Action 已自 echo_map 移除，不再等待其 v11 响应
+                conn.retain_actions(|echo| echo_map.contains_key(echo));
+                pending.retain(|echo| echo_map.contains_key(echo));
+                pending.insert(action.get_echo());
+                let content_type = conn.send_content_type();
+                let msg = match conn.protocol {
+                    Protocol::V12 => Some(action.to_ws_msg(&content_type)),
+                    Protocol::V11 => conn
+                        .action_to_v11(action)
+                        .log(super::OBC)
+                        .map(|action| action.to_ws_msg(&content_type)),
+                };
+                if let Some(msg) = msg {
+                    if ws_stream.send(msg).await.is_err() { //todo
+                        break;
+                    }
                 }
             },
             Some(msg) = ws_stream.next() => {
@@ -157,7 +227,7 @@ async fn ws_loop<E, A, R, AH, EH>(
                         &echo_map,
                         &bot_map,
                         &seq,
-                        &mut implt,
+                        &mut conn,
                     ).await {
                         break;
                     },
@@ -168,7 +238,12 @@ async fn ws_loop<E, A, R, AH, EH>(
             }
         }
     }
-    ws_stream.send(WsMsg::Close(None)).await.ok();
+    let reason = if shutdown {
+        "OneBot shutting down"
+    } else {
+        "connection closed"
+    };
+    ws_stream.send(close_msg(reason, shutdown)).await.ok();
     bot_map.connect_closs(&seq);
 }
 
@@ -179,11 +254,11 @@ async fn ws_recv<E, A, R, AH, EH>(
     echo_map: &EchoMap<R>,
     bot_map: &BotMap<A>,
     seq: &usize,
-    implt: &mut Option<String>,
+    conn: &mut ConnState,
 ) -> bool
 where
     E: ProtocolItem + Clone + GetSelf,
-    A: ProtocolItem,
+    A: ProtocolItem + ActionName,
     R: ProtocolItem,
     AH: ActionHandler<E, A, R> + Send + Sync + 'static,
     EH: EventHandler<E, A, R> + Send + Sync + 'static,
@@ -193,15 +268,67 @@ where
     enum ReceiveItem<E, R> {
         Event(E),
         Resp(Echo<R>),
+        V11Resp(Echo<crate::v11::Response>),
     }
 
+    // v11 连接优先按 v11 响应解析，携带 message 的 v11 响应同样可解析为 v12 响应
+    let v11 = conn.protocol == Protocol::V11;
+    match &msg {
+        WsMsg::Text(_) => conn.received(ContentType::Json),
+        WsMsg::Binary(_) => conn.received(ContentType::MsgPack),
+        _ => {}
+    }
     let handle_ok = |item: Result<ReceiveItem<ComEvent, R>, eyre::Report>| async move {
         match item {
             Ok(ReceiveItem::Event(event)) => {
-                let value: Value = serde_json::to_value(event.to_v12())?;
+                let selft = conn.selft();
+                let v11_status = match &event {
+                    ComEvent::V11Event(crate::v11::V11Event {
+                        post_type:
+                            crate::v11::Post::Meta(crate::v11::MetaEvent::HeartBeatEvent {
+                                status, ..
+                            }),
+                        ..
+                    }) => Some((status.good, status.online)),
+                    _ => None,
+                };
+                let event = conn.to_v12(event);
+                if conn.selft() != selft {
+                    // v11 实现不发送 status_update，由 self_id 变化更新 BotMap
+                    let bots = selft
+                        .map(|selft| Bot {
+                            selft,
+                            online: false,
+                        })
+                        .into_iter()
+                        .chain(conn.selft().map(|selft| Bot {
+                            selft,
+                            online: true,
+                        }))
+                        .collect();
+                    bot_map.connect_update(seq, bots, &conn.implt);
+                }
+                if let (Some((good, online)), Some(selft)) = (v11_status, conn.selft()) {
+                    bot_map.set_good(seq, good);
+                    bot_map.connect_update(seq, vec![Bot { selft, online }], &conn.implt);
+                }
+                if let Ok(meta) = <MetaDetailEvent as TryFrom<Event>>::try_from(event.clone()) {
+                    match meta.detail_type {
+                        MetaTypes::Connect(c) => conn.implt = c.version.implt,
+                        MetaTypes::Heartbeat(h) => bot_map.heartbeat(seq, h.interval),
+                        MetaTypes::StatusUpdate(s) if !conn.implt.is_empty() => {
+                            bot_map.set_good(seq, s.status.good);
+                            bot_map.connect_update(seq, s.status.bots, &conn.implt)
+                        }
+                        _ => {}
+                    }
+                }
+                let value: Value = serde_json::to_value(event)?;
                 let value: E = serde_json::from_value(value)?;
                 let ob = ob.clone();
-                tokio::spawn(async move { ob.handle_event(value).await });
+                ob.clone().spawn("websocket event", async move {
+                    ob.handle_event(value).await.ok();
+                });
             }
             Ok(ReceiveItem::Resp(resp)) => {
                 let (r, echos) = resp.unpack();
@@ -209,40 +336,32 @@ where
                     tx.send(r).ok();
                 }
             }
-            Err(s) => return Err(s),
-        }
-        Ok(())
-    };
-
-    let meta_process = |meta: Result<ComEvent, eyre::Report>| async move {
-        if let Ok(event) = meta.and_then(|e: ComEvent| {
-            let e = e.to_v12();
-            <MetaDetailEvent as TryFrom<Event>>::try_from(e).map_err(|e| e.into())
-        }) {
-            match event.detail_type {
-                MetaTypes::Connect(c) => *implt = Some(c.version.implt),
-                MetaTypes::StatusUpdate(s) => {
-                    if let Some(some_implt) = implt {
-                        bot_map.connect_update(seq, s.status.bots, some_implt)
-                    }
+            Ok(ReceiveItem::V11Resp(resp)) => {
+                let (resp, echos) = conn.resp_to_v12(resp);
+                let r: R = serde_json::from_value(serde_json::to_value(resp)?)?;
+                if let Some((_, tx)) = echo_map.remove(&echos) {
+                    tx.send(r).ok();
                 }
-                _ => {}
             }
+            Err(s) => return Err(s),
         }
+        Ok(())
     };
 
     match msg {
         WsMsg::Text(text) => {
-            meta_process(ProtocolItem::json_decode(&text)).await;
-            handle_ok(ProtocolItem::json_decode(&text))
-                .await
-                .log(super::OBC);
+            let item = match v11.then(|| ProtocolItem::json_decode(&text).ok()).flatten() {
+                Some(resp) => Ok(ReceiveItem::V11Resp(resp)),
+                None => ProtocolItem::json_decode(&text),
+            };
+            handle_ok(item).await.log(super::OBC);
         }
         WsMsg::Binary(b) => {
-            meta_process(ProtocolItem::rmp_decode(&b)).await;
-            handle_ok(ProtocolItem::rmp_decode(&b))
-                .await
-                .log(super::OBC);
+            let item = match v11.then(|| ProtocolItem::rmp_decode(&b).ok()).flatten() {
+                Some(resp) => Ok(ReceiveItem::V11Resp(resp)),
+                None => ProtocolItem::rmp_decode(&b),
+            };
+            handle_ok(item).await.log(super::OBC);
         }
         WsMsg::Ping(b) => {
             if ws_stream.send(WsMsg::Pong(b)).await.is_err() {
@@ -1,12 +1,14 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use super::OBC;
+use super::{Endpoint, Endpoints, Signal, Timeouts, OBC};
 use crate::ah::GetSelfs;
+use crate::config::{ActionTimeout, AppConfig};
 use crate::prelude::{Bot, Version};
 use crate::structs::Selft;
-use crate::util::{Echo, EchoInner, EchoS, GetSelf, ProtocolItem};
+use crate::util::{ActionName, Echo, EchoInner, EchoS, GetSelf, ProtocolItem};
 use crate::{ActionHandler, EventHandler, GetStatus, GetVersion, OneBot};
 use crate::{WalleError, WalleResult};
 
@@ -33,6 +35,8 @@ pub struct AppOBC<A, R> {
     pub(crate) echos: EchoMap<R>,             // echo channel sender 暂存 Map
     pub(crate) seq: AtomicU64,                // 用于生成 echo
     pub(crate) bots: Arc<BotMap<A>>,          // Bot action channel map
+    pub(crate) endpoints: Endpoints,
+    pub(crate) timeouts: Timeouts,
 }
 
 impl<A, R> AppOBC<A, R> {
@@ -51,6 +55,8 @@ impl<A, R> Default for AppOBC<A, R> {
             echos: Arc::new(DashMap::new()),
             seq: AtomicU64::default(),
             bots: Arc::new(Default::default()),
+            endpoints: Endpoints::default(),
+            timeouts: Timeouts::default(),
         }
     }
 }
@@ -68,7 +74,7 @@ impl<A, R> AppOBC<A, R> {
 impl<E, A, R> ActionHandler<E, A, R> for AppOBC<A, R>
 where
     E: ProtocolItem + Clone + GetSelf,
-    A: ProtocolItem + GetSelf,
+    A: ProtocolItem + ActionName + GetSelf,
     R: ProtocolItem,
 {
     type Config = crate::config::AppConfig;
@@ -81,18 +87,32 @@ where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        let mut tasks = vec![];
-        #[cfg(feature = "websocket")]
-        {
-            self.wsr(ob, config.websocket_rev, &mut tasks).await?;
-            self.ws(ob, config.websocket, &mut tasks).await?;
+        if let Some(b) = config.block_meta_event {
+            self.block_meta_event(b);
         }
-        #[cfg(feature = "http")]
-        {
-            self.webhook(ob, config.http_webhook, &mut tasks).await?;
-            self.http(ob, config.http, &mut tasks).await?;
+        let endpoints = config.endpoints();
+        Timeouts::check::<A>(&config.action_timeout, &endpoints)?;
+        self.timeouts.set_global(config.action_timeout.clone());
+        let endpoints = self.endpoints.update(endpoints, true);
+        self.start_endpoints(ob, endpoints).await
+    }
+    async fn reload<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: crate::config::AppConfig,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        if let Some(b) = config.block_meta_event {
+            self.block_meta_event(b);
         }
-        Ok(tasks)
+        let endpoints = config.endpoints();
+        Timeouts::check::<A>(&config.action_timeout, &endpoints)?;
+        self.timeouts.set_global(config.action_timeout.clone());
+        let endpoints = self.endpoints.update(endpoints, false);
+        self.start_endpoints(ob, endpoints).await
     }
     async fn call<AH, EH>(&self, action: A, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
     where
@@ -104,16 +124,18 @@ where
             Some(action_txs) => {
                 let (tx, rx) = oneshot::channel();
                 let seq = self.next_seg();
+                let action_tx = action_txs.first().unwrap(); //todo
+                let timeout = self
+                    .timeouts
+                    .endpoint(self.bots.conn_timeout(action_tx))
+                    .get(Some(&action), ActionTimeout::DEFAULT);
                 self.echos.insert(seq.clone(), tx);
-                action_txs
-                    .first()
-                    .unwrap() //todo
-                    .send(seq.pack(action))
-                    .map_err(|e| {
-                        warn!(target: super::OBC, "send action error: {}", e);
-                        WalleError::Other(e.to_string())
-                    })?;
-                match tokio::time::timeout(std::time::Duration::from_secs(10), rx).await {
+                action_tx.send(seq.clone().pack(action)).map_err(|e| {
+                    warn!(target: super::OBC, "send action error: {}", e);
+                    self.echos.remove(&seq);
+                    WalleError::Other(e.to_string())
+                })?;
+                match tokio::time::timeout(timeout, rx).await {
                     Ok(Ok(res)) => Ok(res),
                     Ok(Err(e)) => {
                         warn!(target: super::OBC, "resp recv error: {:?}", e);
@@ -121,7 +143,8 @@ where
                     }
                     Err(_) => {
                         warn!(target: super::OBC, "resp timeout");
-                        Err(WalleError::Other("resp timeout".to_string()))
+                        self.echos.remove(&seq);
+                        Err(WalleError::ResponseTimeout)
                     }
                 }
             }
@@ -147,6 +170,102 @@ where
         }
         Ok(event)
     }
+    async fn shutdown(&self) {
+        self.endpoints.clear();
+    }
+}
+
+impl AppConfig {
+    pub(crate) fn endpoints(&self) -> Vec<Endpoint> {
+        let mut endpoints = vec![];
+        endpoints.extend(
+            self.websocket_rev
+                .iter()
+                .cloned()
+                .map(Endpoint::WebSocketServer),
+        );
+        endpoints.extend(
+            self.websocket
+                .iter()
+                .cloned()
+                .map(Endpoint::WebSocketClient),
+        );
+        endpoints.extend(self.http_webhook.iter().cloned().map(Endpoint::HttpServer));
+        endpoints.extend(
+            self.http
+                .iter()
+                .map(|(bot_id, c)| Endpoint::HttpClient(Some(bot_id.clone()), c.clone())),
+        );
+        endpoints
+    }
+}
+
+impl<A, R> AppOBC<A, R>
+where
+    A: ProtocolItem + ActionName,
+    R: ProtocolItem,
+{
+    async fn start_endpoints<E, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        endpoints: Vec<(Endpoint, tokio::sync::broadcast::Receiver<()>)>,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        E: ProtocolItem + GetSelf + Clone,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let mut tasks = vec![];
+        for (endpoint, stop) in endpoints {
+            let signal = Signal::new(ob.get_signal_rx()?, stop);
+            let task = match endpoint.clone() {
+                #[cfg(feature = "websocket")]
+                Endpoint::WebSocketServer(c) => self.wsr(ob, c, signal).await,
+                #[cfg(feature = "websocket")]
+                Endpoint::WebSocketClient(c) => self.ws(ob, c, signal).await,
+                #[cfg(feature = "http")]
+                Endpoint::HttpServer(c) => self.webhook(ob, c, signal).await,
+                #[cfg(feature = "http")]
+                Endpoint::HttpClient(Some(bot_id), c) => self.http(ob, bot_id, c, signal).await,
+                #[allow(unreachable_patterns)]
+                _ => {
+                    warn!(target: OBC, "{:?} is not enabled by crate features", endpoint);
+                    continue;
+                }
+            };
+            match task {
+                Ok(task) => tasks.push(task),
+                Err(e) => {
+                    self.endpoints.remove(&endpoint);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(tasks)
+    }
+}
+
+/// 超过心跳间隔的该倍数未收到心跳时，视该连接上的 bot 为离线
+pub(crate) const HEARTBEAT_TIMEOUT_FACTOR: u32 = 2;
+
+#[derive(Debug)]
+pub(crate) struct Conn<A> {
+    pub(crate) tx: mpsc::UnboundedSender<Echo<A>>,
+    pub(crate) selfts: HashSet<Selft>,
+    // 实现端最近一次上报的 good
+    pub(crate) good: bool,
+    // 最近一次心跳的时间与心跳间隔
+    pub(crate) heartbeat: Option<(Instant, Duration)>,
+    // 所属端点的 Action 超时设置
+    pub(crate) timeout: ActionTimeout,
+}
+
+impl<A> Conn<A> {
+    fn alive(&self) -> bool {
+        self.heartbeat
+            .map(|(last, interval)| last.elapsed() <= interval * HEARTBEAT_TIMEOUT_FACTOR)
+            .unwrap_or(true)
+    }
 }
 
 #[derive(Debug)]
@@ -154,8 +273,11 @@ pub(crate) struct BotMap<A> {
     pub(crate) conn_seq: AtomicUsize,
     // value: (implt, action_tx)
     pub(crate) bots: DashMap<Selft, (String, Vec<mpsc::UnboundedSender<Echo<A>>>)>,
-    // value: (action_tx, selfts)
-    pub(crate) conns: DashMap<usize, (mpsc::UnboundedSender<Echo<A>>, HashSet<Selft>)>,
+    pub(crate) conns: DashMap<usize, Conn<A>>,
+    // 曾连接但已离线的 bot，value: implt
+    pub(crate) offline: DashMap<Selft, String>,
+    // bot 上线或下线时递增
+    pub(crate) epoch: AtomicU64,
 }
 
 impl<A> Default for BotMap<A> {
@@ -164,73 +286,129 @@ impl<A> Default for BotMap<A> {
             conn_seq: AtomicUsize::default(),
             bots: DashMap::default(),
             conns: DashMap::default(),
+            offline: DashMap::default(),
+            epoch: AtomicU64::default(),
         }
     }
 }
 
 impl<A> BotMap<A> {
-    fn new_connect(&self) -> (usize, mpsc::UnboundedReceiver<Echo<A>>) {
+    fn new_connect(&self, timeout: ActionTimeout) -> (usize, mpsc::UnboundedReceiver<Echo<A>>) {
         let seq = self.conn_seq.fetch_add(1, Ordering::Relaxed);
         let (tx, rx) = mpsc::unbounded_channel();
-        self.conns.insert(seq, (tx, HashSet::default()));
+        self.conns.insert(
+            seq,
+            Conn {
+                tx,
+                selfts: HashSet::default(),
+                good: true,
+                heartbeat: None,
+                timeout,
+            },
+        );
         (seq, rx)
     }
     fn connect_closs(&self, tx_seq: &usize) {
-        if let Some(selfts) = self.conns.remove(tx_seq) {
-            for selft in selfts.1 .1 {
-                let mut bot = self.bots.get_mut(&selft).unwrap();
-                bot.value_mut()
-                    .1
-                    .retain(|htx| !htx.same_channel(&selfts.1 .0));
-                if bot.value().1.is_empty() {
-                    drop(bot);
-                    self.bots.remove(&selft);
-                }
+        if let Some((_, conn)) = self.conns.remove(tx_seq) {
+            for selft in conn.selfts {
+                self.remove_tx(&selft, &conn.tx);
+            }
+        }
+    }
+    // 移除 bot 的一个连接，无剩余连接时标记为离线
+    fn remove_tx(&self, selft: &Selft, tx: &mpsc::UnboundedSender<Echo<A>>) {
+        if let Some(mut bot) = self.bots.get_mut(selft) {
+            bot.value_mut().1.retain(|htx| !htx.same_channel(tx));
+            if bot.value().1.is_empty() {
+                let implt = bot.value().0.clone();
+                drop(bot);
+                self.bots.remove(selft);
+                self.offline.insert(selft.clone(), implt);
+                self.epoch.fetch_add(1, Ordering::Relaxed);
             }
         }
     }
     fn connect_update(&self, tx_seq: &usize, bots: Vec<Bot>, implt: &str) {
-        let mut get = self.conns.get_mut(tx_seq).unwrap();
-        let tx = get.0.clone();
-        let selfts = &mut get.1;
+        let Some(mut conn) = self.conns.get_mut(tx_seq) else {
+            return;
+        };
+        let tx = conn.tx.clone();
         for bot in bots {
-            match (bot.online, selfts.contains(&bot.selft)) {
+            match (bot.online, conn.selfts.contains(&bot.selft)) {
                 (true, false) => {
-                    selfts.insert(bot.selft.clone());
+                    conn.selfts.insert(bot.selft.clone());
+                    self.offline.remove(&bot.selft);
                     self.bots
                         .entry(bot.selft.clone())
                         .or_insert((implt.to_string(), vec![]))
                         .1
                         .push(tx.clone());
+                    self.epoch.fetch_add(1, Ordering::Relaxed);
                     info!(
                         target: OBC,
                         "New Bot connected: {}-{}", bot.selft.platform, bot.selft.user_id
                     );
                 }
                 (false, true) => {
-                    selfts.remove(&bot.selft);
-                    if let Some(mut bots) = self.bots.get_mut(&bot.selft) {
-                        bots.value_mut().1.retain(|htx| !htx.same_channel(&tx));
-                        if bots.1.is_empty() {
-                            drop(bots);
-                            self.bots.remove(&bot.selft);
-                        }
-                    }
+                    conn.selfts.remove(&bot.selft);
+                    self.remove_tx(&bot.selft, &tx);
                     info!(
                         target: OBC,
                         "Bot disconnected: {}-{}", bot.selft.platform, bot.selft.user_id
                     );
                 }
+                (false, false) if !self.bots.contains_key(&bot.selft) => {
+                    self.offline.insert(bot.selft, implt.to_string());
+                }
                 _ => {}
             }
         }
     }
+    /// 记录心跳，interval 单位为毫秒
+    fn heartbeat(&self, tx_seq: &usize, interval: u32) {
+        if let Some(mut conn) = self.conns.get_mut(tx_seq) {
+            conn.heartbeat = Some((Instant::now(), Duration::from_millis(interval as u64)));
+        }
+    }
+    fn set_good(&self, tx_seq: &usize, good: bool) {
+        if let Some(mut conn) = self.conns.get_mut(tx_seq) {
+            conn.good = good;
+        }
+    }
     fn get_bot(&self, bot: &Selft) -> Option<Vec<mpsc::UnboundedSender<Echo<A>>>> {
         self.bots.get(bot).as_deref().cloned().map(|v| v.1)
     }
+    fn conn_timeout(&self, tx: &mpsc::UnboundedSender<Echo<A>>) -> ActionTimeout {
+        self.conns
+            .iter()
+            .find(|conn| conn.tx.same_channel(tx))
+            .map(|conn| conn.timeout.clone())
+            .unwrap_or_default()
+    }
     fn selfts(&self) -> Vec<Selft> {
         self.bots.iter().map(|i| i.key().clone()).collect()
     }
+    /// 所有已知 bot 及其在线状态，心跳超时的连接上的 bot 视为离线
+    fn bot_status(&self) -> Vec<Bot> {
+        let mut online: HashMap<Selft, bool> = HashMap::default();
+        for conn in self.conns.iter() {
+            let alive = conn.alive();
+            for selft in &conn.selfts {
+                *online.entry(selft.clone()).or_default() |= alive;
+            }
+        }
+        for bot in self.offline.iter() {
+            online.entry(bot.key().clone()).or_default();
+        }
+        online
+            .into_iter()
+            .map(|(selft, online)| Bot { selft, online })
+            .collect()
+    }
+    /// 存在连接且所有连接均未心跳超时、上报状态良好
+    fn is_good(&self) -> bool {
+        !self.conns.is_empty() && self.conns.iter().all(|conn| conn.good && conn.alive())
+    }
 }
 
 impl<A, R> GetSelfs for AppOBC<A, R>
@@ -248,6 +426,12 @@ where
             .map(|v| v.value().0.clone())
             .unwrap_or_default()
     }
+    async fn has_self(&self, selft: &Selft) -> bool {
+        self.bots.bots.contains_key(selft)
+    }
+    fn selfs_epoch(&self) -> u64 {
+        self.bots.epoch.load(Ordering::Relaxed)
+    }
 }
 
 impl<A, R> GetStatus for AppOBC<A, R>
@@ -256,7 +440,10 @@ where
     R: Send + Sync,
 {
     async fn is_good(&self) -> bool {
-        true
+        self.bots.is_good()
+    }
+    async fn get_bots(&self) -> Vec<Bot> {
+        self.bots.bot_status()
     }
 }
 
@@ -273,9 +460,9 @@ impl<A, R> GetVersion for AppOBC<A, R> {
 #[test]
 fn test_bot_map() {
     let map = BotMap::<crate::action::Action>::default();
-    let (seq, _) = map.new_connect();
+    let (seq, _) = map.new_connect(ActionTimeout::default());
     assert_eq!(seq, 0);
-    let (seq, _) = map.new_connect();
+    let (seq, _) = map.new_connect(ActionTimeout::default());
     assert_eq!(seq, 1);
     assert_eq!(
         map.conns
@@ -292,12 +479,116 @@ fn test_bot_map() {
         platform: "".to_owned(),
         user_id: "1".to_owned(),
     };
-    // map.connect_update(&0, HashSet::from([self0.clone()]), "");
-    // assert_eq!(map.bots.get(&self0).unwrap().1.len(), 1);
-    // assert!(map.txs.get(&0).unwrap().1.len() == 1);
-    // map.connect_update(&0, HashSet::from([self1.clone()]), "");
+    let bot = |selft: &Selft, online| Bot {
+        selft: selft.clone(),
+        online,
+    };
+    map.connect_update(&0, vec![bot(&self0, true)], "");
+    assert_eq!(map.bots.get(&self0).unwrap().1.len(), 1);
+    assert!(map.conns.get(&0).unwrap().selfts.len() == 1);
+    map.connect_update(&0, vec![bot(&self0, false), bot(&self1, true)], "");
     assert!(map.bots.get(&self0).is_none());
-    assert!(map.conns.get(&0).unwrap().1.len() == 1);
+    assert!(map.conns.get(&0).unwrap().selfts.len() == 1);
     assert_eq!(map.bots.get(&self1).unwrap().1.len(), 1);
     assert!(map.get_bot(&self1).is_some());
 }
+
+#[test]
+fn test_bot_status() {
+    let map = BotMap::<crate::action::Action>::default();
+    assert!(!map.is_good());
+    let selft = |user_id: &str| Selft {
+        platform: "qq".to_owned(),
+        user_id: user_id.to_owned(),
+    };
+    let online = |map: &BotMap<_>, user_id: &str| {
+        map.bot_status()
+            .into_iter()
+            .find(|bot| bot.selft == selft(user_id))
+            .map(|bot| bot.online)
+    };
+    let (seq0, _) = map.new_connect(ActionTimeout::default());
+    let (seq1, _) = map.new_connect(ActionTimeout::default());
+    map.connect_update(
+        &seq0,
+        vec![
+            Bot {
+                selft: selft("0"),
+                online: true,
+            },
+            Bot {
+                selft: selft("1"),
+                online: false,
+            },
+        ],
+        "",
+    );
+    map.connect_update(
+        &seq1,
+        vec![Bot {
+            selft: selft("2"),
+            online: true,
+        }],
+        "",
+    );
+    assert!(map.is_good());
+    assert_eq!(online(&map, "0"), Some(true));
+    assert_eq!(online(&map, "1"), Some(false));
+
+    map.heartbeat(&seq0, 0);
+    std::thread::sleep(Duration::from_millis(1));
+    assert_eq!(online(&map, "0"), Some(false));
+    assert!(!map.is_good());
+    map.heartbeat(&seq0, 60_000);
+    assert_eq!(online(&map, "0"), Some(true));
+    map.set_good(&seq0, false);
+    assert!(!map.is_good());
+
+    map.connect_closs(&seq1);
+    assert_eq!(online(&map, "2"), Some(false));
+    assert!(map.get_bot(&selft("2")).is_none());
+}
+
+#[tokio::test]
+async fn test_call_timeout() {
+    use crate::action::Action;
+    use crate::event::Event;
+    use crate::resp::Resp;
+    let obc = AppOBC::<Action, Resp>::new();
+    obc.timeouts.set_global(ActionTimeout {
+        default: Some(0),
+        actions: Default::default(),
+    });
+    let (seq, _rx) = obc.bots.new_connect(ActionTimeout::default());
+    let selft = Selft {
+        platform: "qq".to_string(),
+        user_id: "1".to_string(),
+    };
+    obc.bots.connect_update(
+        &seq,
+        vec![Bot {
+            selft: selft.clone(),
+            online: true,
+        }],
+        "test",
+    );
+    let ob = Arc::new_cyclic(|ob| {
+        OneBot::new(
+            obc,
+            crate::EventHandlerSet::<AppOBC<Action, Resp>>::new(
+                ob.clone(),
+                crate::DispatchMode::Sequential,
+            ),
+        )
+    });
+    let action = Action {
+        action: "get_self_info".to_string(),
+        params: Default::default(),
+        selft: Some(selft),
+    };
+    assert!(matches!(
+        ActionHandler::<Event, Action, Resp>::call(ob.action_handler(), action, &ob).await,
+        Err(WalleError::ResponseTimeout)
+    ));
+    assert!(ob.action_handler().echos.is_empty());
+}
@@ -1,20 +1,22 @@
 use crate::{
+    config::ActionTimeout,
     error::{WalleError, WalleResult},
     resp::{resp_error, Resp},
-    util::{AuthReqHeaderExt, Echo, ProtocolItem, ValueMap},
+    util::{ActionName, AuthReqHeaderExt, ContentType, Echo, ProtocolItem, ValueMap},
     value_map, ActionHandler, EventHandler, OneBot,
 };
 use crate::{
     event::Event,
     obc::{
-        ws_util::{try_connect, upgrade_websocket},
-        ImplOBC,
+        with_timeout,
+        ws_util::{close_msg, try_connect, upgrade_websocket},
+        ImplOBC, Signal, Timeouts,
     },
 };
 use futures_util::{SinkExt, StreamExt};
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 use tokio::net::TcpStream;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
 use tokio::task::JoinHandle;
 use tokio_tungstenite::tungstenite::http::{header::USER_AGENT, Request};
 use tokio_tungstenite::tungstenite::Message as WsMsg;
@@ -29,118 +31,125 @@ where
     pub(crate) async fn ws<A, R, AH, EH>(
         &self,
         ob: &Arc<OneBot<AH, EH>>,
-        config: Vec<crate::config::WebSocketServer>,
-        tasks: &mut Vec<JoinHandle<()>>,
-    ) -> WalleResult<()>
+        wss: crate::config::WebSocketServer,
+        mut signal: Signal,
+    ) -> WalleResult<JoinHandle<()>>
     where
-        A: ProtocolItem,
+        A: ProtocolItem + ActionName,
         R: ProtocolItem,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        for wss in config {
-            let addr = std::net::SocketAddr::new(wss.host, wss.port);
-            let tcp_listener = tokio::net::TcpListener::bind(&addr)
-                .await
-                .map_err(WalleError::from)?;
-            info!(
-                target: super::OBC,
-                "Websocket server listening on ws://{}", addr
-            );
-            let access_token = wss.access_token.clone();
-            let mut signal_rx = ob.get_signal_rx()?;
-            let event_rx = self.event_tx.subscribe();
-            let hb_rx = self.hb_tx.subscribe();
-            let ob = ob.clone();
-            tasks.push(tokio::spawn(async move {
-            loop { tokio::select! {
+        let addr = std::net::SocketAddr::new(wss.host, wss.port);
+        let tcp_listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .map_err(WalleError::from)?;
+        info!(
+            target: super::OBC,
+            "Websocket server listening on ws://{}", addr
+        );
+        let access_token = wss.access_token.clone();
+        let timeouts = self.timeouts.endpoint(wss.action_timeout.clone());
+        let subscribers = self.subscribers.clone();
+        let hb_rx = self.hb_tx.subscribe();
+        let ob = ob.clone();
+        Ok(tokio::spawn(async move {
+            loop {
+                tokio::select! {
                     Ok((stream, addr)) = tcp_listener.accept() => {
                         if let Some((ws_stream, _)) = upgrade_websocket(&access_token, stream).await {
                             info!(target: super::OBC, "New websocket connection from {}", addr);
-                            tokio::spawn(ws_loop(
+                            ob.spawn(format!("websocket connection {}", addr), ws_loop(
                                 ob.clone(),
-                                event_rx.resubscribe(),
+                                subscribers.subscribe(&wss.event_buffer),
                                 hb_rx.resubscribe(),
                                 ws_stream,
+                                timeouts.clone(),
+                                wss.content_type,
+                                signal.resubscribe(),
                             ));
                         }
                     }
-                    _ = signal_rx.recv() => break,
-                }}
-            }));
-        }
-        Ok(())
+                    _ = signal.recv() => break,
+                }
+            }
+        }))
     }
     // handle outgoing
     pub(crate) async fn wsr<A, R, AH, EH>(
         &self,
         ob: &Arc<OneBot<AH, EH>>,
-        config: Vec<crate::config::WebSocketClient>,
-        tasks: &mut Vec<JoinHandle<()>>,
-    ) -> WalleResult<()>
+        wsr: crate::config::WebSocketClient,
+        mut signal: Signal,
+    ) -> WalleResult<JoinHandle<()>>
     where
-        A: ProtocolItem,
+        A: ProtocolItem + ActionName,
         R: ProtocolItem,
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        for wsr in config {
-            let event_rx = self.event_tx.subscribe();
-            let hb_rx = self.hb_tx.subscribe();
-            let mut signal_rx = ob.get_signal_rx()?;
-            let ob = ob.clone();
-            let implt = self.implt.clone();
-            tasks.push(tokio::spawn(async move {
-                info!(target: super::OBC, "Start try connect to {}", wsr.url);
-                while signal_rx.try_recv().is_err() {
-                    let req = Request::builder()
-                        .header(
-                            USER_AGENT,
-                            format!("OneBot/{} Walle/{}", 12, crate::VERSION),
+        let subscribers = self.subscribers.clone();
+        let hb_rx = self.hb_tx.subscribe();
+        let ob = ob.clone();
+        let implt = self.implt.clone();
+        let timeouts = self.timeouts.endpoint(wsr.action_timeout.clone());
+        Ok(tokio::spawn(async move {
+            info!(target: super::OBC, "Start try connect to {}", wsr.url);
+            while !signal.is_set() {
+                let req = Request::builder()
+                    .header(
+                        USER_AGENT,
+                        format!("OneBot/{} Walle/{}", 12, crate::VERSION),
+                    )
+                    .header("Sec-WebSocket-Protocol", format!("{}.{}", 12, implt))
+                    .header_auth_token(&wsr.access_token);
+                match try_connect(&wsr, req).await {
+                    Some(ws_stream) => {
+                        ws_loop(
+                            ob.clone(),
+                            subscribers.subscribe(&wsr.event_buffer),
+                            hb_rx.resubscribe(),
+                            ws_stream,
+                            timeouts.clone(),
+                            wsr.content_type,
+                            signal.resubscribe(),
                         )
-                        .header("Sec-WebSocket-Protocol", format!("{}.{}", 12, implt))
-                        .header_auth_token(&wsr.access_token);
-                    match try_connect(&wsr, req).await {
-                        Some(ws_stream) => {
-                            ws_loop(
-                                ob.clone(),
-                                event_rx.resubscribe(),
-                                hb_rx.resubscribe(),
-                                ws_stream,
-                            )
-                            .await;
-                            warn!(target: super::OBC, "Disconnected from {}", wsr.url);
-                        }
-                        None => {
-                            tokio::time::sleep(std::time::Duration::from_secs(
+                        .await;
+                        warn!(target: super::OBC, "Disconnected from {}", wsr.url);
+                    }
+                    None => {
+                        tokio::select! {
+                            _ = tokio::time::sleep(std::time::Duration::from_secs(
                                 wsr.reconnect_interval as u64,
-                            ))
-                            .await;
+                            )) => {}
+                            _ = signal.recv() => break,
                         }
                     }
                 }
-            }));
-        }
-        Ok(())
+            }
+        }))
     }
 }
 
 async fn ws_loop<E, A, R, AH, EH>(
     ob: Arc<OneBot<AH, EH>>,
-    mut event_rx: broadcast::Receiver<E>,
+    mut event_rx: mpsc::Receiver<E>,
     mut hb_rx: broadcast::Receiver<Event>,
     mut ws_stream: WebSocketStream<TcpStream>,
+    timeouts: Timeouts,
+    preferred: Option<ContentType>,
+    mut signal: Signal,
 ) where
     E: ProtocolItem + Clone,
-    A: ProtocolItem,
+    A: ProtocolItem + ActionName,
     R: ProtocolItem,
     AH: ActionHandler<E, A, R> + Send + Sync + 'static,
     EH: EventHandler<E, A, R> + Send + Sync + 'static,
 {
     let (json_resp_tx, mut json_resp_rx) = tokio::sync::mpsc::unbounded_channel();
     let (rmp_resp_tx, mut rmp_resp_rx) = tokio::sync::mpsc::unbounded_channel();
-    let mut signal_rx = ob.get_signal_rx().unwrap(); //todo
-                                                     // https://12.onebot.dev/interface/meta/events/
+    let mut content_type = preferred.unwrap_or_default();
+    // https://12.onebot.dev/interface/meta/events/
     let connect = Event {
         id: "".to_owned(),
         time: crate::util::timestamp_nano_f64(),
@@ -152,7 +161,7 @@ async fn ws_loop<E, A, R, AH, EH>(
         },
     };
     if ws_stream
-        .send(WsMsg::Text(connect.json_encode()))
+        .send(connect.to_ws_msg(&content_type))
         .await
         .is_err()
     {
@@ -170,28 +179,33 @@ async fn ws_loop<E, A, R, AH, EH>(
         },
     };
     if ws_stream
-        .send(WsMsg::Text(status.json_encode()))
+        .send(status.to_ws_msg(&content_type))
         .await
         .is_err()
     {
         return;
     }
+    let mut reason = "connection closed";
+    let mut shutdown = false;
     loop {
         tokio::select! {
-            _ = signal_rx.recv() => break,
+            _ = signal.recv() => {
+                reason = "OneBot shutting down";
+                shutdown = true;
+                break;
+            },
             event = event_rx.recv() => {
                 match event {
-                    Ok(event) => {
-                        // event will always send as json
-                        let event = event.json_encode();
-                        trace!(target: crate::WALLE_CORE, "ws send: {}", event);
-                        if ws_stream.send(WsMsg::Text(event)).await.is_err() {
+                    Some(event) => {
+                        trace!(target: crate::WALLE_CORE, "ws send: {:?}", event);
+                        if ws_stream.send(event.to_ws_msg(&content_type)).await.is_err() {
                             // send failed, break loop and close connection
                             break;
                         }
                     }
-                    Err(_) => {
-                        // channel all sender are dropped or channel is fulled will break loop and close connection
+                    None => {
+                        // 消费过慢且 LagPolicy 为 Disconnect 时 sender 被移除
+                        reason = "event channel closed or lagged";
                         break;
                     }
                 }
@@ -199,13 +213,16 @@ async fn ws_loop<E, A, R, AH, EH>(
             hb = hb_rx.recv() => {
                 match hb {
                     Ok(hb) => {
-                        let hb = hb.json_encode();
-                        trace!(target: crate::WALLE_CORE, "ws send: {}", hb);
-                        if ws_stream.send(WsMsg::Text(hb)).await.is_err() {
+                        trace!(target: crate::WALLE_CORE, "ws send: {:?}", hb);
+                        if ws_stream.send(hb.to_ws_msg(&content_type)).await.is_err() {
                             break;
                         }
                     }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!(target: super::OBC, "{} heartbeat skipped", n);
+                    }
                     Err(_) => {
+                        reason = "heartbeat channel closed";
                         break;
                     }
                 }
@@ -214,13 +231,22 @@ async fn ws_loop<E, A, R, AH, EH>(
                 trace!(target: crate::WALLE_CORE, "ws recv: {:?}", ws_msg);
                 match ws_msg {
                     // handle action request
-                    Ok(ws_msg) => if ws_recv(
+                    Ok(ws_msg) => {
+                        // 未指定编码时跟随应用端
+                        match (&ws_msg, preferred) {
+                            (WsMsg::Text(_), None) => content_type = ContentType::Json,
+                            (WsMsg::Binary(_), None) => content_type = ContentType::MsgPack,
+                            _ => {}
+                        }
+                        if ws_recv(
                             ws_msg,
                             &ob,
                             &mut ws_stream,
                             &json_resp_tx,
-                            &rmp_resp_tx
-                        ).await { break },
+                            &rmp_resp_tx,
+                            &timeouts,
+                        ).await { break }
+                    }
                     Err(_) => break,
                 }
 
@@ -242,7 +268,30 @@ async fn ws_loop<E, A, R, AH, EH>(
             }
         }
     }
-    ws_stream.send(WsMsg::Close(None)).await.ok();
+    if shutdown {
+        // 不再接收新的 Action，发送已产生的 Event 与进行中 Action 的响应后关闭
+        while let Ok(event) = event_rx.try_recv() {
+            if ws_stream
+                .send(event.to_ws_msg(&content_type))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+        drop((json_resp_tx, rmp_resp_tx));
+        loop {
+            let msg = tokio::select! {
+                Some(resp) = json_resp_rx.recv() => WsMsg::Text(resp.json_encode()),
+                Some(resp) = rmp_resp_rx.recv() => WsMsg::Binary(resp.rmp_encode()),
+                else => break,
+            };
+            if ws_stream.send(msg).await.is_err() {
+                return;
+            }
+        }
+    }
+    ws_stream.send(close_msg(reason, shutdown)).await.ok();
 }
 
 pub(crate) async fn ws_recv<E, A, R, AH, EH>(
@@ -251,10 +300,11 @@ pub(crate) async fn ws_recv<E, A, R, AH, EH>(
     ws_stream: &mut WebSocketStream<TcpStream>,
     json_resp_sender: &tokio::sync::mpsc::UnboundedSender<Echo<R>>,
     rmp_resp_sender: &tokio::sync::mpsc::UnboundedSender<Echo<R>>,
+    timeouts: &Timeouts,
 ) -> bool
 where
     E: ProtocolItem,
-    A: ProtocolItem,
+    A: ProtocolItem + ActionName,
     R: ProtocolItem,
     AH: ActionHandler<E, A, R> + Send + Sync + 'static,
     EH: EventHandler<E, A, R> + Send + Sync + 'static,
@@ -275,16 +325,14 @@ where
                 let (action, echos) = action.unpack();
                 let tx = json_resp_sender.clone();
                 let ob = ob.clone();
-                tokio::spawn(async move {
-                    tokio::time::timeout(Duration::from_secs(10), async move {
-                        match ob.handle_action(action).await {
-                            Ok(r) => {
-                                tx.send(echos.pack(r)).ok();
-                            }
-                            Err(e) => warn!(target: super::OBC, "handle action error: {}", e),
+                let timeout = timeouts.get(Some(&action), ActionTimeout::DEFAULT);
+                ob.clone().spawn("websocket action", async move {
+                    match with_timeout(timeout, ob.handle_action(action)).await {
+                        Ok(r) => {
+                            tx.send(echos.pack(r)).ok();
                         }
-                    })
-                    .await
+                        Err(e) => warn!(target: super::OBC, "handle action error: {}", e),
+                    }
                 });
                 //todo
             }
@@ -309,16 +357,14 @@ where
                 let (action, echos) = action.unpack();
                 let tx = rmp_resp_sender.clone();
                 let ob = ob.clone();
-                tokio::spawn(async move {
-                    tokio::time::timeout(Duration::from_secs(10), async move {
-                        match ob.handle_action(action).await {
-                            Ok(r) => {
-                                tx.send(echos.pack(r)).ok();
-                            }
-                            Err(e) => warn!(target: super::OBC, "handle action error: {}", e),
+                let timeout = timeouts.get(Some(&action), ActionTimeout::DEFAULT);
+                ob.clone().spawn("websocket action", async move {
+                    match with_timeout(timeout, ob.handle_action(action)).await {
+                        Ok(r) => {
+                            tx.send(echos.pack(r)).ok();
                         }
-                    })
-                    .await
+                        Err(e) => warn!(target: super::OBC, "handle action error: {}", e),
+                    }
                 });
             }
             Err(msg) => match rmp_serde::from_read(v.as_slice()) {
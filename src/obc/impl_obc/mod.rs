@@ -1,12 +1,16 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
-use super::OBC;
+use super::{Endpoint, Endpoints, Signal, Timeouts, OBC};
+use crate::config::{EventBuffer, ImplConfig, LagPolicy};
 use crate::event::Event;
-use crate::util::ProtocolItem;
+use crate::util::{ActionName, ProtocolItem};
 use crate::{ActionHandler, EventHandler, OneBot};
 use crate::{GetStatus, WalleResult};
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
 use tokio::task::JoinHandle;
+use tracing::warn;
 
 #[cfg(feature = "http")]
 mod impl_http;
@@ -20,14 +24,16 @@ mod impl_ws;
 /// ImplOBC 仅对 Event 泛型要求 Clone trait
 pub struct ImplOBC<E> {
     pub implt: String,
-    pub(crate) event_tx: tokio::sync::broadcast::Sender<E>,
+    pub(crate) subscribers: Arc<Subscribers<E>>,
     pub(crate) hb_tx: tokio::sync::broadcast::Sender<crate::event::Event>,
+    pub(crate) endpoints: Endpoints,
+    pub(crate) timeouts: Timeouts,
 }
 
 impl<E, A, R> EventHandler<E, A, R> for ImplOBC<E>
 where
     E: ProtocolItem + Clone,
-    A: ProtocolItem,
+    A: ProtocolItem + ActionName,
     R: ProtocolItem,
 {
     type Config = crate::config::ImplConfig;
@@ -40,30 +46,113 @@ where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        let mut tasks = vec![];
-        #[cfg(feature = "websocket")]
-        {
-            self.ws(ob, config.websocket, &mut tasks).await?;
-            self.wsr(ob, config.websocket_rev, &mut tasks).await?;
-        }
-        #[cfg(feature = "http")]
-        {
-            self.http(ob, config.http, &mut tasks).await?;
-            self.webhook(ob, config.http_webhook, &mut tasks).await?;
-        }
-        if config.heartbeat.enabled {
-            tasks.push(start_hb(ob, config.heartbeat.interval, self.hb_tx.clone()))
-        }
-        Ok(tasks)
+        let endpoints = config.endpoints();
+        Timeouts::check::<A>(&config.action_timeout, &endpoints)?;
+        self.timeouts.set_global(config.action_timeout.clone());
+        let endpoints = self.endpoints.update(endpoints, true);
+        self.start_endpoints(ob, endpoints).await
+    }
+    async fn reload<AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        config: crate::config::ImplConfig,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let endpoints = config.endpoints();
+        Timeouts::check::<A>(&config.action_timeout, &endpoints)?;
+        self.timeouts.set_global(config.action_timeout.clone());
+        let endpoints = self.endpoints.update(endpoints, false);
+        self.start_endpoints(ob, endpoints).await
     }
     async fn call<AH, EH>(&self, event: E, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
     where
         AH: ActionHandler<E, A, R> + Send + Sync + 'static,
         EH: EventHandler<E, A, R> + Send + Sync + 'static,
     {
-        self.event_tx.send(event).ok();
+        self.subscribers.send(event).await;
         Ok(())
     }
+    async fn shutdown(&self) {
+        self.endpoints.clear();
+    }
+}
+
+impl ImplConfig {
+    pub(crate) fn endpoints(&self) -> Vec<Endpoint> {
+        let mut endpoints = vec![];
+        endpoints.extend(
+            self.websocket
+                .iter()
+                .cloned()
+                .map(Endpoint::WebSocketServer),
+        );
+        endpoints.extend(
+            self.websocket_rev
+                .iter()
+                .cloned()
+                .map(Endpoint::WebSocketClient),
+        );
+        endpoints.extend(self.http.iter().cloned().map(Endpoint::HttpServer));
+        endpoints.extend(
+            self.http_webhook
+                .iter()
+                .cloned()
+                .map(|c| Endpoint::HttpClient(None, c)),
+        );
+        if self.heartbeat.enabled {
+            endpoints.push(Endpoint::Heartbeat(self.heartbeat.clone()));
+        }
+        endpoints
+    }
+}
+
+impl<E> ImplOBC<E>
+where
+    E: ProtocolItem + Clone,
+{
+    async fn start_endpoints<A, R, AH, EH>(
+        &self,
+        ob: &Arc<OneBot<AH, EH>>,
+        endpoints: Vec<(Endpoint, broadcast::Receiver<()>)>,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        A: ProtocolItem + ActionName,
+        R: ProtocolItem,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let mut tasks = vec![];
+        for (endpoint, stop) in endpoints {
+            let signal = Signal::new(ob.get_signal_rx()?, stop);
+            let task = match endpoint.clone() {
+                #[cfg(feature = "websocket")]
+                Endpoint::WebSocketServer(c) => self.ws(ob, c, signal).await,
+                #[cfg(feature = "websocket")]
+                Endpoint::WebSocketClient(c) => self.wsr(ob, c, signal).await,
+                #[cfg(feature = "http")]
+                Endpoint::HttpServer(c) => self.http(ob, c, signal).await,
+                #[cfg(feature = "http")]
+                Endpoint::HttpClient(_, c) => self.webhook(ob, c, signal).await,
+                Endpoint::Heartbeat(c) => Ok(start_hb(ob, c.interval, self.hb_tx.clone(), signal)),
+                #[allow(unreachable_patterns)]
+                _ => {
+                    warn!(target: OBC, "{:?} is not enabled by crate features", endpoint);
+                    continue;
+                }
+            };
+            match task {
+                Ok(task) => tasks.push(task),
+                Err(e) => {
+                    self.endpoints.remove(&endpoint);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(tasks)
+    }
 }
 
 impl<E> ImplOBC<E> {
@@ -71,16 +160,84 @@ impl<E> ImplOBC<E> {
     where
         E: Clone,
     {
-        let (event_tx, _) = tokio::sync::broadcast::channel(1024); //todo
         let (hb_tx, _) = tokio::sync::broadcast::channel(1024);
         Self {
             implt,
-            event_tx,
+            subscribers: Arc::default(),
             hb_tx,
+            endpoints: Endpoints::default(),
+            timeouts: Timeouts::default(),
         }
     }
 }
 
+impl<E> ImplOBC<E> {
+    /// 因连接消费过慢而未能送达的 Event 数
+    pub fn dropped_events(&self) -> u64 {
+        self.subscribers.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// 各连接的 Event 缓冲
+pub(crate) struct Subscribers<E> {
+    seq: AtomicUsize,
+    subs: Mutex<HashMap<usize, (mpsc::Sender<E>, LagPolicy)>>,
+    dropped: AtomicU64,
+}
+
+impl<E> Default for Subscribers<E> {
+    fn default() -> Self {
+        Self {
+            seq: AtomicUsize::default(),
+            subs: Mutex::default(),
+            dropped: AtomicU64::default(),
+        }
+    }
+}
+
+impl<E: Clone> Subscribers<E> {
+    pub(crate) fn subscribe(&self, buffer: &EventBuffer) -> mpsc::Receiver<E> {
+        let (tx, rx) = mpsc::channel(buffer.capacity.max(1));
+        let id = self.seq.fetch_add(1, Ordering::Relaxed);
+        self.subs.lock().unwrap().insert(id, (tx, buffer.lag));
+        rx
+    }
+    /// 按各连接的 LagPolicy 推送 Event，移除已断开或因 Disconnect 策略断开的连接
+    pub(crate) async fn send(&self, event: E) {
+        let subs: Vec<_> = {
+            let subs = self.subs.lock().unwrap();
+            subs.iter()
+                .map(|(id, (tx, lag))| (*id, tx.clone(), *lag))
+                .collect()
+        };
+        let mut closed = vec![];
+        for (id, tx, lag) in subs {
+            let keep = match lag {
+                LagPolicy::Block => tx.send(event.clone()).await.is_ok(),
+                _ => match tx.try_send(event.clone()) {
+                    Ok(()) => true,
+                    Err(mpsc::error::TrySendError::Full(_)) => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        warn!(target: OBC, "event consumer lagged, policy: {:?}", lag);
+                        lag == LagPolicy::Skip
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => false,
+                },
+            };
+            if !keep {
+                closed.push(id);
+            }
+        }
+        if !closed.is_empty() {
+            let mut subs = self.subs.lock().unwrap();
+            for id in closed {
+                subs.remove(&id);
+            }
+        }
+    }
+}
+
+/// interval 单位为秒，心跳事件中的 interval 单位为毫秒
 async fn build_hb<AH, EH>(ob: &OneBot<AH, EH>, interval: u32) -> crate::event::Event
 where
     AH: GetStatus + Send + Sync,
@@ -93,7 +250,7 @@ where
         detail_type: "heartbeat".to_string(),
         sub_type: "".to_string(),
         extra: crate::value_map! {
-            "interval": interval,
+            "interval": interval.saturating_mul(1000),
             "status": status
         },
     }
@@ -103,21 +260,45 @@ fn start_hb<AH, EH>(
     ob: &Arc<OneBot<AH, EH>>,
     interval: u32,
     hb_tx: broadcast::Sender<Event>,
+    mut signal: Signal,
 ) -> JoinHandle<()>
 where
     AH: GetStatus + Send + Sync + 'static,
     EH: Send + Sync + 'static,
 {
     let hb_tx = Arc::new(hb_tx);
-    let mut signal = ob.get_signal_rx().unwrap();
     let ob = ob.clone();
     tokio::spawn(async move {
         loop {
-            if signal.try_recv().is_ok() {
-                break;
-            }
             hb_tx.send(build_hb(&ob, interval).await).ok();
-            tokio::time::sleep(std::time::Duration::from_secs(interval as u64)).await;
+            tokio::select! {
+                _ = tokio::time::sleep(std::time::Duration::from_secs(interval as u64)) => {}
+                _ = signal.recv() => break,
+            }
         }
     })
 }
+
+#[tokio::test]
+async fn test_subscribers() {
+    let subscribers = Subscribers::default();
+    let buffer = |lag| EventBuffer { capacity: 1, lag };
+    let mut skip = subscribers.subscribe(&buffer(LagPolicy::Skip));
+    let mut disconnect = subscribers.subscribe(&buffer(LagPolicy::Disconnect));
+    let mut block = subscribers.subscribe(&buffer(LagPolicy::Block));
+    subscribers.send(1).await;
+    // block 的缓冲已满，等待其消费
+    let send = subscribers.send(2);
+    tokio::pin!(send);
+    assert!(futures_util::poll!(send.as_mut()).is_pending());
+    assert_eq!(block.recv().await, Some(1));
+    send.await;
+    assert_eq!(block.recv().await, Some(2));
+    assert_eq!(subscribers.dropped.load(Ordering::Relaxed), 2);
+    assert_eq!(skip.recv().await, Some(1));
+    assert_eq!(disconnect.recv().await, Some(1));
+    assert_eq!(disconnect.recv().await, None);
+    subscribers.send(3).await;
+    assert_eq!(skip.recv().await, Some(3));
+    assert_eq!(subscribers.subs.lock().unwrap().len(), 2);
+}
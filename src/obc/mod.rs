@@ -14,6 +14,173 @@ pub use app_obc::*;
 #[cfg(feature = "impl-obc")]
 pub use impl_obc::*;
 
+use std::any::type_name;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tracing::info;
+
+use crate::config::{
+    ActionTimeout, Heartbeat, HttpClient, HttpServer, WebSocketClient, WebSocketServer,
+};
+use crate::util::ActionName;
+use crate::{WalleError, WalleResult};
+
+/// 单个端点的配置，重载时据此比较差异
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Endpoint {
+    HttpServer(HttpServer),
+    // 应用端 HTTP 以 bot id 为键
+    HttpClient(Option<String>, HttpClient),
+    WebSocketServer(WebSocketServer),
+    WebSocketClient(WebSocketClient),
+    Heartbeat(Heartbeat),
+}
+
+impl Endpoint {
+    fn action_timeout(&self) -> Option<&ActionTimeout> {
+        match self {
+            Endpoint::HttpServer(c) => Some(&c.action_timeout),
+            Endpoint::HttpClient(_, c) => Some(&c.action_timeout),
+            Endpoint::WebSocketServer(c) => Some(&c.action_timeout),
+            Endpoint::WebSocketClient(c) => Some(&c.action_timeout),
+            Endpoint::Heartbeat(_) => None,
+        }
+    }
+}
+
+/// 运行中的端点及其停止信号
+#[derive(Debug, Default)]
+pub(crate) struct Endpoints(Mutex<HashMap<Endpoint, broadcast::Sender<()>>>);
+
+impl Endpoints {
+    /// 停止不再存在的端点，返回需要新启动的端点
+    ///
+    /// reset 为 true 时视所有端点为未启动，用于 start
+    pub(crate) fn update(
+        &self,
+        endpoints: Vec<Endpoint>,
+        reset: bool,
+    ) -> Vec<(Endpoint, broadcast::Receiver<()>)> {
+        let mut running = self.0.lock().unwrap();
+        if reset {
+            running.clear();
+        }
+        let keep: HashSet<&Endpoint> = endpoints.iter().collect();
+        running.retain(|endpoint, tx| {
+            let keep = keep.contains(endpoint);
+            if !keep {
+                info!(target: OBC, "Stop endpoint {:?}", endpoint);
+                tx.send(()).ok();
+            }
+            keep
+        });
+        let mut started = vec![];
+        for endpoint in endpoints {
+            if !running.contains_key(&endpoint) {
+                let (tx, rx) = broadcast::channel(1);
+                running.insert(endpoint.clone(), tx);
+                started.push((endpoint, rx));
+            }
+        }
+        started
+    }
+    /// 移除启动失败的端点
+    pub(crate) fn remove(&self, endpoint: &Endpoint) {
+        self.0.lock().unwrap().remove(endpoint);
+    }
+    pub(crate) fn clear(&self) {
+        for (_, tx) in self.0.lock().unwrap().drain() {
+            tx.send(()).ok();
+        }
+    }
+}
+
+/// 端点任务的停止信号，OneBot 关闭或端点被移除时触发
+pub(crate) struct Signal {
+    ob: broadcast::Receiver<()>,
+    endpoint: broadcast::Receiver<()>,
+}
+
+impl Signal {
+    pub(crate) fn new(ob: broadcast::Receiver<()>, endpoint: broadcast::Receiver<()>) -> Self {
+        Self { ob, endpoint }
+    }
+    pub(crate) async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.ob.recv() => {}
+            _ = self.endpoint.recv() => {}
+        }
+    }
+    /// 是否已触发，不阻塞
+    pub(crate) fn is_set(&mut self) -> bool {
+        use broadcast::error::TryRecvError::Empty;
+        !matches!(self.ob.try_recv(), Err(Empty)) || !matches!(self.endpoint.try_recv(), Err(Empty))
+    }
+    pub(crate) fn resubscribe(&self) -> Self {
+        Self {
+            ob: self.ob.resubscribe(),
+            endpoint: self.endpoint.resubscribe(),
+        }
+    }
+}
+
+/// 端点的 Action 超时设置，全局设置在重载时更新
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Timeouts {
+    global: Arc<RwLock<ActionTimeout>>,
+    endpoint: ActionTimeout,
+}
+
+impl Timeouts {
+    pub(crate) fn set_global(&self, global: ActionTimeout) {
+        *self.global.write().unwrap() = global;
+    }
+    pub(crate) fn endpoint(&self, endpoint: ActionTimeout) -> Self {
+        Self {
+            global: self.global.clone(),
+            endpoint,
+        }
+    }
+    /// 按 Action 名称的超时仅对能按名称区分的 Action 生效，否则拒绝该设置
+    pub(crate) fn check<A: ActionName>(
+        global: &ActionTimeout,
+        endpoints: &[Endpoint],
+    ) -> WalleResult<()> {
+        if A::NAMED {
+            return Ok(());
+        }
+        let per_action = std::iter::once(global)
+            .chain(endpoints.iter().filter_map(Endpoint::action_timeout))
+            .any(|timeout| !timeout.actions.is_empty());
+        if per_action {
+            return Err(WalleError::Other(format!(
+                "per-action timeout is not supported for {}",
+                type_name::<A>()
+            )));
+        }
+        Ok(())
+    }
+    /// 获取 action 的超时，均未设置时为 otherwise 秒
+    pub(crate) fn get<A: ActionName>(&self, action: Option<&A>, otherwise: u64) -> Duration {
+        self.endpoint
+            .or(&self.global.read().unwrap())
+            .get(action.and_then(ActionName::action_name), otherwise)
+    }
+}
+
+/// 在超时内完成 fut，超时返回 WalleError::ResponseTimeout
+pub(crate) async fn with_timeout<T>(
+    timeout: Duration,
+    fut: impl std::future::Future<Output = crate::WalleResult<T>>,
+) -> crate::WalleResult<T> {
+    tokio::time::timeout(timeout, fut)
+        .await
+        .unwrap_or(Err(WalleError::ResponseTimeout))
+}
+
 #[cfg(feature = "http")]
 use hyper::Uri;
 #[cfg(all(not(feature = "http"), feature = "websocket"))]
@@ -38,3 +205,48 @@ fn test_query() {
         check_query(&"/?access_token=v&a=b".parse::<Uri>().unwrap())
     )
 }
+
+#[test]
+fn test_endpoints_update() {
+    let hb = |interval| {
+        Endpoint::Heartbeat(Heartbeat {
+            enabled: true,
+            interval,
+        })
+    };
+    let endpoints = Endpoints::default();
+    let started = endpoints.update(vec![hb(1), hb(2)], true);
+    assert_eq!(started.len(), 2);
+    let (_, mut stop1) = started.into_iter().next().unwrap();
+    // 保留 hb(2)，移除 hb(1)，新增 hb(3)
+    let started = endpoints.update(vec![hb(2), hb(3)], false);
+    assert_eq!(started.len(), 1);
+    assert_eq!(started[0].0, hb(3));
+    assert!(stop1.try_recv().is_ok());
+    assert_eq!(endpoints.update(vec![hb(2), hb(3)], false).len(), 0);
+    assert_eq!(endpoints.update(vec![hb(2), hb(3)], true).len(), 2);
+}
+
+#[test]
+fn test_timeouts_check() {
+    use crate::action::Action;
+    struct Unnamed;
+    impl ActionName for Unnamed {
+        const NAMED: bool = false;
+        fn action_name(&self) -> Option<&str> {
+            None
+        }
+    }
+    let per_action = ActionTimeout {
+        default: None,
+        actions: [("send_message".to_string(), 1)].into(),
+    };
+    let ws = Endpoint::WebSocketServer(WebSocketServer {
+        action_timeout: per_action.clone(),
+        ..Default::default()
+    });
+    assert!(Timeouts::check::<Action>(&per_action, &[ws.clone()]).is_ok());
+    assert!(Timeouts::check::<Unnamed>(&ActionTimeout::default(), &[]).is_ok());
+    assert!(Timeouts::check::<Unnamed>(&per_action, &[]).is_err());
+    assert!(Timeouts::check::<Unnamed>(&ActionTimeout::default(), &[ws]).is_err());
+}
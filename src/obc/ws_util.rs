@@ -64,10 +64,20 @@ pub(crate) async fn try_connect(
     }
 }
 
+/// 握手请求中的实现端信息
+#[derive(Debug, Default)]
+pub(crate) struct Handshake {
+    /// OneBot 12 `Sec-WebSocket-Protocol` 中的实现名
+    pub implt: String,
+    /// OneBot 11 反向 ws 的 `X-Self-ID`
+    pub self_id: Option<String>,
+    pub user_agent: Option<String>,
+}
+
 pub(crate) async fn upgrade_websocket(
     access_token: &Option<String>,
     stream: TcpStream,
-) -> Option<(WebSocketStream<TcpStream>, String)> {
+) -> Option<(WebSocketStream<TcpStream>, Handshake)> {
     let addr = match stream.peer_addr() {
         Ok(addr) => addr,
         Err(e) => {
@@ -75,8 +85,8 @@ pub(crate) async fn upgrade_websocket(
             return None;
         }
     };
-    let mut implt = String::default();
-    let ref_implt = &mut implt;
+    let mut handshake = Handshake::default();
+    let ref_handshake = &mut handshake;
 
     let callback = |req: &Request, resp: Response| -> Result<Response, HttpResp<Option<String>>> {
         use crate::obc::check_query;
@@ -109,9 +119,17 @@ pub(crate) async fn upgrade_websocket(
             .map(|s| s.split_once('.'))
         {
             if version == "12" {
-                *ref_implt = implt.to_owned();
+                ref_handshake.implt = implt.to_owned();
             }
         }
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+        ref_handshake.self_id = header("X-Self-ID");
+        ref_handshake.user_agent = header("User-Agent");
         info!(
             target: OBC,
             "Websocket connectted with {}",
@@ -123,7 +141,7 @@ pub(crate) async fn upgrade_websocket(
     match accept_hdr_async(stream, callback).await {
         Ok(s) => {
             info!(target: OBC, "New websocket client connected from {}", addr);
-            Some((s, implt))
+            Some((s, handshake))
         }
         Err(e) => {
             info!(target: OBC, "Upgrade websocket from {} failed: {}", addr, e);
@@ -131,3 +149,16 @@ pub(crate) async fn upgrade_websocket(
         }
     }
 }
+
+/// 带关闭原因的 Close 帧，因 OneBot 关闭而断开时使用 Away
+pub(crate) fn close_msg(reason: &str, shutdown: bool) -> tokio_tungstenite::tungstenite::Message {
+    use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+    tokio_tungstenite::tungstenite::Message::Close(Some(CloseFrame {
+        code: if shutdown {
+            CloseCode::Away
+        } else {
+            CloseCode::Normal
+        },
+        reason: reason.to_owned().into(),
+    }))
+}
@@ -145,11 +145,11 @@ impl Resp {
 pub mod resp_error {
     use super::RespError;
     /// RespError 构造函数声明
-    /// ```rust
+    /// ```rust,ignore
     /// error_type!(bad_request, 10001, "无效的动作请求");
     /// ```
     /// generate code:
-    /// ```rust
+    /// ```rust,ignore
     /// pub fn bad_request<T: std::fmt::Display>(msg: T) -> RespError {
     ///     RespError {
     ///         code: 10001,
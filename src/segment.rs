@@ -6,6 +6,8 @@ use crate::{
     value, value_map,
 };
 
+pub mod ext;
+
 pub type Segments = Vec<MsgSegment>;
 
 /// 标准 MsgSegment 模型
@@ -16,6 +18,8 @@ pub struct MsgSegment {
 }
 
 pub trait ToMsgSegment: PushToValueMap {
+    /// 扩展消息段前缀，如 `qq`
+    const PREFIX: Option<&'static str> = None;
     fn ty(&self) -> &'static str;
     fn to_segment(self) -> MsgSegment
     where
@@ -239,7 +243,7 @@ pub trait MessageExt {
 
 pub trait MessageRefExt {
     fn try_as_ref<'a>(&'a self) -> WalleResult<Vec<MsgSegmentRef<'a>>>;
-    fn try_iter_text_mut<'a>(&'a self) -> WalleResult<Vec<&'a str>> {
+    fn try_iter_text_mut(&self) -> WalleResult<Vec<&str>> {
         Ok(self
             .try_as_ref()?
             .into_iter()
@@ -252,7 +256,7 @@ pub trait MessageRefExt {
             })
             .collect())
     }
-    fn try_first_text_ref<'a>(&'a self) -> WalleResult<&'a str> {
+    fn try_first_text_ref(&self) -> WalleResult<&str> {
         let mut segs = self.try_as_ref()?;
         if !segs.is_empty() {
             if let MsgSegmentRef::Text { text, .. } = segs.remove(0) {
@@ -263,9 +267,9 @@ pub trait MessageRefExt {
             "first message segment is not text".to_string(),
         ))
     }
-    fn try_last_text_ref<'a>(&'a self) -> WalleResult<&'a str> {
+    fn try_last_text_ref(&self) -> WalleResult<&str> {
         if let Some(MsgSegmentRef::Text { text, .. }) = self.try_as_ref()?.pop() {
-            return Ok(text);
+            Ok(text)
         } else {
             Err(WalleError::Other(
                 "last message segment is not text".to_string(),
@@ -276,7 +280,7 @@ pub trait MessageRefExt {
 
 pub trait MessageMutExt {
     fn try_as_mut<'a>(&'a mut self) -> WalleResult<Vec<MsgSegmentMut<'a>>>;
-    fn try_iter_text_mut<'a>(&'a mut self) -> WalleResult<Vec<&'a mut String>> {
+    fn try_iter_text_mut(&mut self) -> WalleResult<Vec<&mut String>> {
         Ok(self
             .try_as_mut()?
             .into_iter()
@@ -289,7 +293,7 @@ pub trait MessageMutExt {
             })
             .collect())
     }
-    fn try_first_text_mut<'a>(&'a mut self) -> WalleResult<&'a mut String> {
+    fn try_first_text_mut(&mut self) -> WalleResult<&mut String> {
         let mut segs = self.try_as_mut()?;
         if !segs.is_empty() {
             if let MsgSegmentMut::Text { text } = segs.remove(0) {
@@ -300,9 +304,9 @@ pub trait MessageMutExt {
             "first message segment is not text".to_string(),
         ))
     }
-    fn try_last_text_mut<'a>(&'a mut self) -> WalleResult<&'a mut String> {
+    fn try_last_text_mut(&mut self) -> WalleResult<&mut String> {
         if let Some(MsgSegmentMut::Text { text }) = self.try_as_mut()?.pop() {
-            return Ok(text);
+            Ok(text)
         } else {
             Err(WalleError::Other(
                 "last message segment is not text".to_string(),
@@ -339,7 +343,7 @@ impl MessageRefExt for Segments {
 
 impl MessageMutExt for Segments {
     fn try_as_mut<'a>(&'a mut self) -> WalleResult<Vec<MsgSegmentMut<'a>>> {
-        self.into_iter().map(|seg| seg.try_as_mut()).collect()
+        self.iter_mut().map(|seg| seg.try_as_mut()).collect()
     }
 }
 
@@ -351,7 +355,7 @@ impl MessageRefExt for Vec<Value> {
 
 impl MessageMutExt for Vec<Value> {
     fn try_as_mut<'a>(&'a mut self) -> WalleResult<Vec<MsgSegmentMut<'a>>> {
-        self.into_iter().map(|v| v.try_as_mut()).collect()
+        self.iter_mut().map(|v| v.try_as_mut()).collect()
     }
 }
 
@@ -399,6 +403,34 @@ pub enum MsgSegmentRef<'a> {
         user_id: &'a str,
         extra: &'a ValueMap,
     },
+    Face {
+        id: &'a str,
+        extra: &'a ValueMap,
+    },
+    Forward {
+        id: &'a str,
+        extra: &'a ValueMap,
+    },
+    Node {
+        extra: &'a ValueMap,
+    },
+    Json {
+        data: &'a str,
+        extra: &'a ValueMap,
+    },
+    Xml {
+        data: &'a str,
+        extra: &'a ValueMap,
+    },
+    Poke {
+        ty: &'a i64,
+        id: &'a i64,
+        extra: &'a ValueMap,
+    },
+    Markdown {
+        content: &'a str,
+        extra: &'a ValueMap,
+    },
     Other {
         ty: &'a str,
         extra: &'a ValueMap,
@@ -413,46 +445,72 @@ where
     match ty {
         "text" => Ok(MsgSegmentRef::Text {
             text: data.try_get_as_ref("text")?,
-            extra: &data,
+            extra: data,
         }),
         "mention" => Ok(MsgSegmentRef::Mention {
             user_id: data.try_get_as_ref("user_id")?,
-            extra: &data,
+            extra: data,
         }),
-        "mention_all" => Ok(MsgSegmentRef::MentionAll { extra: &data }),
+        "mention_all" => Ok(MsgSegmentRef::MentionAll { extra: data }),
         "image" => Ok(MsgSegmentRef::Image {
             file_id: data.try_get_as_ref("file_id")?,
-            extra: &data,
+            extra: data,
         }),
         "voice" => Ok(MsgSegmentRef::Voice {
             file_id: data.try_get_as_ref("file_id")?,
-            extra: &data,
+            extra: data,
         }),
         "audio" => Ok(MsgSegmentRef::Audio {
             file_id: data.try_get_as_ref("file_id")?,
-            extra: &data,
+            extra: data,
         }),
         "video" => Ok(MsgSegmentRef::Video {
             file_id: data.try_get_as_ref("file_id")?,
-            extra: &data,
+            extra: data,
         }),
         "file" => Ok(MsgSegmentRef::File {
             file_id: data.try_get_as_ref("file_id")?,
-            extra: &data,
+            extra: data,
         }),
         "location" => Ok(MsgSegmentRef::Location {
             latitude: data.try_get_as_ref("latitude")?,
             longitude: data.try_get_as_ref("longitude")?,
             title: data.try_get_as_ref("title")?,
             content: data.try_get_as_ref("content")?,
-            extra: &data,
+            extra: data,
         }),
         "reply" => Ok(MsgSegmentRef::Reply {
             message_id: data.try_get_as_ref("message_id")?,
             user_id: data.try_get_as_ref("user_id")?,
-            extra: &data,
+            extra: data,
         }),
-        _ => Ok(MsgSegmentRef::Other { ty, extra: &data }),
+        "qq.face" => Ok(MsgSegmentRef::Face {
+            id: data.try_get_as_ref("id")?,
+            extra: data,
+        }),
+        "qq.forward" => Ok(MsgSegmentRef::Forward {
+            id: data.try_get_as_ref("id")?,
+            extra: data,
+        }),
+        "qq.node" => Ok(MsgSegmentRef::Node { extra: data }),
+        "qq.json" => Ok(MsgSegmentRef::Json {
+            data: data.try_get_as_ref("data")?,
+            extra: data,
+        }),
+        "qq.xml" => Ok(MsgSegmentRef::Xml {
+            data: data.try_get_as_ref("data")?,
+            extra: data,
+        }),
+        "qq.poke" => Ok(MsgSegmentRef::Poke {
+            ty: data.try_get_as_ref("type")?,
+            id: data.try_get_as_ref("id")?,
+            extra: data,
+        }),
+        "qq.markdown" => Ok(MsgSegmentRef::Markdown {
+            content: data.try_get_as_ref("content")?,
+            extra: data,
+        }),
+        _ => Ok(MsgSegmentRef::Other { ty, extra: data }),
     }
 }
 
@@ -486,6 +544,13 @@ pub enum MsgSegmentMut<'a> {
     Audio { file_id: &'a mut String },
     Video { file_id: &'a mut String },
     File { file_id: &'a mut String },
+    Face { id: &'a mut String },
+    Forward { id: &'a mut String },
+    Node { extra: &'a mut ValueMap },
+    Json { data: &'a mut String },
+    Xml { data: &'a mut String },
+    Poke { ty: &'a mut i64, id: &'a mut i64 },
+    Markdown { content: &'a mut String },
     Other,
 }
 
@@ -515,6 +580,40 @@ where
         "file" => Ok(MsgSegmentMut::File {
             file_id: data.try_get_as_mut("file_id")?,
         }),
+        "qq.face" => Ok(MsgSegmentMut::Face {
+            id: data.try_get_as_mut("id")?,
+        }),
+        "qq.forward" => Ok(MsgSegmentMut::Forward {
+            id: data.try_get_as_mut("id")?,
+        }),
+        "qq.node" => Ok(MsgSegmentMut::Node { extra: data }),
+        "qq.json" => Ok(MsgSegmentMut::Json {
+            data: data.try_get_as_mut("data")?,
+        }),
+        "qq.xml" => Ok(MsgSegmentMut::Xml {
+            data: data.try_get_as_mut("data")?,
+        }),
+        "qq.poke" => {
+            let (mut ty, mut id) = (None, None);
+            for (k, v) in data.iter_mut() {
+                match k.as_str() {
+                    "type" => ty = Some(v),
+                    "id" => id = Some(v),
+                    _ => {}
+                }
+            }
+            Ok(MsgSegmentMut::Poke {
+                ty: ty
+                    .ok_or_else(|| WalleError::MapMissedKey("type".to_owned()))?
+                    ._try_as_mut()?,
+                id: id
+                    .ok_or_else(|| WalleError::MapMissedKey("id".to_owned()))?
+                    ._try_as_mut()?,
+            })
+        }
+        "qq.markdown" => Ok(MsgSegmentMut::Markdown {
+            content: data.try_get_as_mut("content")?,
+        }),
         _ => Ok(MsgSegmentMut::Other),
     }
 }
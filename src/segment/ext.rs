@@ -0,0 +1,77 @@
+//! 常用平台扩展消息段模型
+//!
+//! 均以 `qq` 为前缀，与 OneBot 11 兼容层的转换结果一致
+
+use super::Segments;
+use walle_macro::{
+    _PushToValueMap as PushToValueMap, _ToMsgSegment as ToMsgSegment,
+    _TryFromMsgSegment as TryFromMsgSegment, _TryFromValue as TryFromValue,
+};
+
+/// QQ 表情
+#[derive(
+    Debug, Clone, PartialEq, Eq, PushToValueMap, ToMsgSegment, TryFromMsgSegment, TryFromValue,
+)]
+#[msg_segment(prefix = "qq")]
+pub struct Face {
+    pub id: String,
+}
+
+/// 合并转发
+#[derive(
+    Debug, Clone, PartialEq, Eq, PushToValueMap, ToMsgSegment, TryFromMsgSegment, TryFromValue,
+)]
+#[msg_segment(prefix = "qq")]
+pub struct Forward {
+    pub id: String,
+}
+
+/// 合并转发节点，引用已有消息时仅有 id
+#[derive(
+    Debug, Clone, PartialEq, PushToValueMap, ToMsgSegment, TryFromMsgSegment, TryFromValue,
+)]
+#[msg_segment(prefix = "qq")]
+pub struct Node {
+    pub id: Option<String>,
+    pub user_id: Option<String>,
+    pub nickname: Option<String>,
+    pub content: Option<Segments>,
+}
+
+/// JSON 卡片消息
+#[derive(
+    Debug, Clone, PartialEq, Eq, PushToValueMap, ToMsgSegment, TryFromMsgSegment, TryFromValue,
+)]
+#[msg_segment(prefix = "qq")]
+pub struct Json {
+    pub data: String,
+}
+
+/// XML 卡片消息
+#[derive(
+    Debug, Clone, PartialEq, Eq, PushToValueMap, ToMsgSegment, TryFromMsgSegment, TryFromValue,
+)]
+#[msg_segment(prefix = "qq")]
+pub struct Xml {
+    pub data: String,
+}
+
+/// 戳一戳
+#[derive(
+    Debug, Clone, PartialEq, Eq, PushToValueMap, ToMsgSegment, TryFromMsgSegment, TryFromValue,
+)]
+#[msg_segment(prefix = "qq")]
+pub struct Poke {
+    pub ty: i64,
+    pub id: i64,
+    pub name: Option<String>,
+}
+
+/// Markdown 消息
+#[derive(
+    Debug, Clone, PartialEq, Eq, PushToValueMap, ToMsgSegment, TryFromMsgSegment, TryFromValue,
+)]
+#[msg_segment(prefix = "qq")]
+pub struct Markdown {
+    pub content: String,
+}
@@ -0,0 +1,145 @@
+//! 与 tower::Service 互相转换
+//!
+//! `ActionService` 将 OneBot 的 handle_action 暴露为 `Service<A, Response = R>`，
+//! `ServiceHandler` 将任意 `Service<A, Response = R>` 包装为 ActionHandler
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_util::future::{poll_fn, BoxFuture};
+use tokio::task::JoinHandle;
+use tower_service::Service;
+
+use crate::error::{WalleError, WalleResult};
+use crate::event::Event;
+use crate::resp::Resp;
+use crate::structs::{Selft, Version};
+use crate::{ActionHandler, EventHandler, GetSelfs, GetStatus, GetVersion, OneBot};
+
+/// 以 `OneBot::handle_action` 处理请求的 Service
+pub struct ActionService<AH, EH, E = Event, R = Resp> {
+    ob: Arc<OneBot<AH, EH>>,
+    _phantom: PhantomData<fn() -> (E, R)>,
+}
+
+impl<AH, EH, E, R> ActionService<AH, EH, E, R> {
+    pub fn new(ob: Arc<OneBot<AH, EH>>) -> Self {
+        Self {
+            ob,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<AH, EH, E, R> Clone for ActionService<AH, EH, E, R> {
+    fn clone(&self) -> Self {
+        Self::new(self.ob.clone())
+    }
+}
+
+impl<AH, EH, E, A, R> Service<A> for ActionService<AH, EH, E, R>
+where
+    AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+    EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    A: Send + 'static,
+    R: Send + 'static,
+{
+    type Response = R;
+    type Error = WalleError;
+    type Future = BoxFuture<'static, WalleResult<R>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<WalleResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn call(&mut self, action: A) -> Self::Future {
+        let ob = self.ob.clone();
+        Box::pin(async move { ob.handle_action(action).await })
+    }
+}
+
+/// 以 Service 处理 Action 的 ActionHandler，每次调用时 clone Service
+pub struct ServiceHandler<S> {
+    pub service: S,
+    pub selfs: Vec<Selft>,
+    pub version: Version,
+}
+
+impl<S> ServiceHandler<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service,
+            selfs: vec![],
+            version: Version {
+                implt: crate::WALLE_CORE.to_string(),
+                version: crate::VERSION.to_string(),
+                onebot_version: "12".to_string(),
+            },
+        }
+    }
+    /// 声明该 Service 服务的 bot
+    pub fn with_selfs(mut self, selfs: Vec<Selft>) -> Self {
+        self.selfs = selfs;
+        self
+    }
+}
+
+fn service_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> WalleError {
+    match e.into().downcast::<WalleError>() {
+        Ok(e) => *e,
+        Err(e) => WalleError::Other(e.to_string()),
+    }
+}
+
+impl<S: Sync> GetSelfs for ServiceHandler<S> {
+    async fn get_selfs(&self) -> Vec<Selft> {
+        self.selfs.clone()
+    }
+    async fn get_impl(&self, _selft: &Selft) -> String {
+        self.version.implt.clone()
+    }
+}
+
+impl<S: Sync> GetStatus for ServiceHandler<S> {
+    async fn is_good(&self) -> bool {
+        true
+    }
+}
+
+impl<S> GetVersion for ServiceHandler<S> {
+    fn get_version(&self) -> Version {
+        self.version.clone()
+    }
+}
+
+impl<S, E, A, R> ActionHandler<E, A, R> for ServiceHandler<S>
+where
+    S: Service<A, Response = R> + Clone + Send + Sync + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S::Future: Send,
+    A: Send + 'static,
+{
+    type Config = ();
+    async fn start<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _config: (),
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        Ok(vec![])
+    }
+    async fn call<AH, EH>(&self, action: A, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let mut service = self.service.clone();
+        poll_fn(|cx| service.poll_ready(cx))
+            .await
+            .map_err(service_error)?;
+        service.call(action).await.map_err(service_error)
+    }
+}
@@ -0,0 +1,847 @@
+//! 任意数量 Handler 的组合
+//!
+//! 通过对象安全的适配器持有 Handler，Set 构造时传入所在 OneBot 的 Weak 引用，
+//! 该 OneBot 的类型由 Context 给出并在编译期检查
+
+use std::any::{type_name, Any};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, Weak};
+
+use futures_util::future::{join_all, BoxFuture, LocalBoxFuture};
+use tokio::task::JoinHandle;
+
+use crate::action::Action;
+use crate::ah::{merge_bots, unknown_self};
+use crate::error::{WalleError, WalleResult};
+use crate::event::Event;
+use crate::resp::{Resp, RespError};
+use crate::structs::{Bot, Selft, Version};
+use crate::util::GetSelf;
+use crate::{
+    ActionHandler, DispatchMode, EventHandler, GetSelfs, GetStatus, GetVersion, OneBot,
+    SelftRouter, VERSION, WALLE_CORE,
+};
+
+/// 按 key 提供各 Handler 的 Config，未提供的 Handler 以 `Config::default()` 启动
+pub type HandlerConfigs = HashMap<String, Box<dyn Any + Send + Sync>>;
+
+type Keyed<T> = Vec<(String, Arc<T>)>;
+
+/// ActionHandlerSet 与 EventHandlerSet 搭配使用时的 Context
+pub struct Paired;
+
+/// EventHandlerSet 所在 OneBot 的 Handler 类型
+///
+/// 已为 [`Paired`] 与默认 E、A、R 的 ActionHandler 实现，
+/// Set 嵌套于 JoinedHandler 等其他 Handler 中时需自行实现
+pub trait EventSetContext<E, A, R> {
+    type AH: Send + Sync;
+    type EH: Send + Sync;
+}
+
+impl<E, A, R> EventSetContext<E, A, R> for Paired {
+    type AH = ActionHandlerSet<Paired, E, A, R>;
+    type EH = EventHandlerSet<Paired, E, A, R>;
+}
+
+impl<T: ActionHandler + Send + Sync> EventSetContext<Event, Action, Resp> for T {
+    type AH = T;
+    type EH = EventHandlerSet<T>;
+}
+
+/// ActionHandlerSet 所在 OneBot 的 Handler 类型
+///
+/// 已为 [`Paired`] 与默认 E、A、R 的 EventHandler 实现，
+/// Set 嵌套于 JoinedHandler 等其他 Handler 中时需自行实现
+pub trait ActionSetContext<E, A, R> {
+    type AH: Send + Sync;
+    type EH: Send + Sync;
+}
+
+impl<E, A, R> ActionSetContext<E, A, R> for Paired {
+    type AH = ActionHandlerSet<Paired, E, A, R>;
+    type EH = EventHandlerSet<Paired, E, A, R>;
+}
+
+impl<T: EventHandler + Send + Sync> ActionSetContext<Event, Action, Resp> for T {
+    type AH = ActionHandlerSet<T>;
+    type EH = T;
+}
+
+/// EventHandlerSet 所在的 OneBot
+pub type EventSetOneBot<C, E = Event, A = Action, R = Resp> =
+    OneBot<<C as EventSetContext<E, A, R>>::AH, <C as EventSetContext<E, A, R>>::EH>;
+/// ActionHandlerSet 所在的 OneBot
+pub type ActionSetOneBot<C, E = Event, A = Action, R = Resp> =
+    OneBot<<C as ActionSetContext<E, A, R>>::AH, <C as ActionSetContext<E, A, R>>::EH>;
+
+fn upgrade<O>(ob: &Weak<O>) -> WalleResult<Arc<O>> {
+    ob.upgrade()
+        .ok_or_else(|| WalleError::Other("OneBot of handler set is dropped".to_string()))
+}
+
+fn downcast_config<C: Default + 'static>(
+    key: &str,
+    config: Option<Box<dyn Any + Send + Sync>>,
+) -> WalleResult<C> {
+    match config {
+        Some(config) => config.downcast().map(|c| *c).map_err(|_| {
+            WalleError::ValueTypeNotMatch(type_name::<C>().to_string(), key.to_string())
+        }),
+        None => Ok(C::default()),
+    }
+}
+
+struct Adapter<H>(H);
+
+type DynEH<C, E, A, R> = dyn DynEventHandler<EventSetOneBot<C, E, A, R>, E, A, R>;
+type DynAH<C, E, A, R> = dyn DynActionHandler<ActionSetOneBot<C, E, A, R>, E, A, R>;
+
+trait DynEventHandler<O, E, A, R>: Send + Sync {
+    fn start<'a>(
+        &'a self,
+        key: &'a str,
+        ob: &'a Arc<O>,
+        config: Option<Box<dyn Any + Send + Sync>>,
+    ) -> LocalBoxFuture<'a, WalleResult<Vec<JoinHandle<()>>>>;
+    fn reload<'a>(
+        &'a self,
+        key: &'a str,
+        ob: &'a Arc<O>,
+        config: Box<dyn Any + Send + Sync>,
+    ) -> LocalBoxFuture<'a, WalleResult<Vec<JoinHandle<()>>>>;
+    fn call<'a>(&'a self, event: E, ob: &'a Arc<O>) -> BoxFuture<'a, WalleResult<()>>;
+    fn before_call_action<'a>(&'a self, action: A, ob: &'a Arc<O>)
+        -> BoxFuture<'a, WalleResult<A>>;
+    fn after_call_action<'a>(&'a self, resp: R, ob: &'a Arc<O>) -> BoxFuture<'a, WalleResult<R>>;
+    fn shutdown(&self) -> LocalBoxFuture<'_, ()>;
+    fn on_onebot_connect<'a>(&'a self, ob: &'a Arc<O>) -> LocalBoxFuture<'a, WalleResult<()>>;
+    fn on_onebot_disconnect<'a>(&'a self, ob: &'a Arc<O>) -> LocalBoxFuture<'a, WalleResult<()>>;
+}
+
+impl<H, AH, EH, E, A, R> DynEventHandler<OneBot<AH, EH>, E, A, R> for Adapter<H>
+where
+    H: EventHandler<E, A, R> + Send + Sync + 'static,
+    H::Config: Default + 'static,
+    AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+    EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    E: Send + 'static,
+    A: Send + 'static,
+    R: Send + 'static,
+{
+    fn start<'a>(
+        &'a self,
+        key: &'a str,
+        ob: &'a Arc<OneBot<AH, EH>>,
+        config: Option<Box<dyn Any + Send + Sync>>,
+    ) -> LocalBoxFuture<'a, WalleResult<Vec<JoinHandle<()>>>> {
+        Box::pin(async move {
+            let config = downcast_config(key, config)?;
+            self.0.start(ob, config).await
+        })
+    }
+    fn reload<'a>(
+        &'a self,
+        key: &'a str,
+        ob: &'a Arc<OneBot<AH, EH>>,
+        config: Box<dyn Any + Send + Sync>,
+    ) -> LocalBoxFuture<'a, WalleResult<Vec<JoinHandle<()>>>> {
+        Box::pin(async move {
+            let config = downcast_config(key, Some(config))?;
+            self.0.reload(ob, config).await
+        })
+    }
+    fn call<'a>(&'a self, event: E, ob: &'a Arc<OneBot<AH, EH>>) -> BoxFuture<'a, WalleResult<()>> {
+        Box::pin(async move { self.0.call(event, ob).await })
+    }
+    fn before_call_action<'a>(
+        &'a self,
+        action: A,
+        ob: &'a Arc<OneBot<AH, EH>>,
+    ) -> BoxFuture<'a, WalleResult<A>> {
+        Box::pin(async move { self.0.before_call_action(action, ob).await })
+    }
+    fn after_call_action<'a>(
+        &'a self,
+        resp: R,
+        ob: &'a Arc<OneBot<AH, EH>>,
+    ) -> BoxFuture<'a, WalleResult<R>> {
+        Box::pin(async move { self.0.after_call_action(resp, ob).await })
+    }
+    fn shutdown(&self) -> LocalBoxFuture<'_, ()> {
+        Box::pin(self.0.shutdown())
+    }
+    fn on_onebot_connect<'a>(
+        &'a self,
+        ob: &'a Arc<OneBot<AH, EH>>,
+    ) -> LocalBoxFuture<'a, WalleResult<()>> {
+        Box::pin(async move { self.0.on_onebot_connect(ob).await })
+    }
+    fn on_onebot_disconnect<'a>(
+        &'a self,
+        ob: &'a Arc<OneBot<AH, EH>>,
+    ) -> LocalBoxFuture<'a, WalleResult<()>> {
+        Box::pin(async move { self.0.on_onebot_disconnect(ob).await })
+    }
+}
+
+/// 持有任意数量 EventHandler，Event 按插入顺序分发给全部 Handler
+///
+/// C 为所在 OneBot 的 Context，与 ActionHandlerSet 搭配时为 [`Paired`]
+pub struct EventHandlerSet<C = Paired, E = Event, A = Action, R = Resp>
+where
+    C: EventSetContext<E, A, R>,
+{
+    ob: Weak<EventSetOneBot<C, E, A, R>>,
+    handlers: RwLock<Keyed<DynEH<C, E, A, R>>>,
+    pub mode: DispatchMode,
+}
+
+impl<C, E, A, R> EventHandlerSet<C, E, A, R>
+where
+    C: EventSetContext<E, A, R>,
+{
+    /// ob 为所在 OneBot 的 Weak 引用，通常由 `Arc::new_cyclic` 提供
+    pub fn new(ob: Weak<EventSetOneBot<C, E, A, R>>, mode: DispatchMode) -> Self {
+        Self {
+            ob,
+            handlers: RwLock::default(),
+            mode,
+        }
+    }
+    /// 插入 Handler，同 key 的 Handler 将被替换
+    pub fn insert<H>(&self, key: impl Into<String>, handler: H)
+    where
+        H: EventHandler<E, A, R> + Send + Sync + 'static,
+        H::Config: Default + 'static,
+        C::AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        C::EH: EventHandler<E, A, R> + Send + Sync + 'static,
+        E: Send + 'static,
+        A: Send + 'static,
+        R: Send + 'static,
+    {
+        let key = key.into();
+        let handler: Arc<DynEH<C, E, A, R>> = Arc::new(Adapter(handler));
+        let mut handlers = self.handlers.write().unwrap();
+        match handlers.iter_mut().find(|(k, _)| k == &key) {
+            Some((_, h)) => *h = handler,
+            None => handlers.push((key, handler)),
+        }
+    }
+    /// 移除并关闭 Handler
+    pub async fn remove(&self, key: &str) -> bool {
+        let removed = {
+            let mut handlers = self.handlers.write().unwrap();
+            let index = handlers.iter().position(|(k, _)| k == key);
+            index.map(|i| handlers.remove(i).1)
+        };
+        match removed {
+            Some(handler) => {
+                handler.shutdown().await;
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn keys(&self) -> Vec<String> {
+        let handlers = self.handlers.read().unwrap();
+        handlers.iter().map(|(k, _)| k.clone()).collect()
+    }
+    /// 启动运行时插入的 Handler
+    pub async fn start_handler(
+        &self,
+        key: &str,
+        config: Option<Box<dyn Any + Send + Sync>>,
+    ) -> WalleResult<Vec<JoinHandle<()>>> {
+        let handler = self
+            .get(key)
+            .ok_or_else(|| WalleError::MapMissedKey(key.to_string()))?;
+        handler.start(key, &upgrade(&self.ob)?, config).await
+    }
+    fn get(&self, key: &str) -> Option<Arc<DynEH<C, E, A, R>>> {
+        let handlers = self.handlers.read().unwrap();
+        handlers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, h)| h.clone())
+    }
+    fn snapshot(&self) -> Keyed<DynEH<C, E, A, R>> {
+        self.handlers.read().unwrap().clone()
+    }
+}
+
+/// 各 Handler 收到的 OneBot 为构造时传入的 ob
+impl<C, E, A, R> EventHandler<E, A, R> for EventHandlerSet<C, E, A, R>
+where
+    C: EventSetContext<E, A, R> + 'static,
+    C::AH: 'static,
+    C::EH: 'static,
+    E: Clone + Send + Sync + 'static,
+    A: Send + 'static,
+    R: Send + 'static,
+{
+    type Config = HandlerConfigs;
+    /// 任一 Handler 启动失败时关闭已启动的 Handler
+    async fn start<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        mut config: Self::Config,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        let mut joins = vec![];
+        let mut started = vec![];
+        for (key, handler) in self.snapshot() {
+            let c = config.remove(&key);
+            match handler.start(&key, ob, c).await {
+                Ok(j) => {
+                    joins.extend(j);
+                    started.push(handler);
+                }
+                Err(e) => {
+                    for handler in started {
+                        handler.shutdown().await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(joins)
+    }
+    /// 仅重载 config 中含有的 Handler
+    async fn reload<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        mut config: Self::Config,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        let mut joins = vec![];
+        for (key, handler) in self.snapshot() {
+            if let Some(c) = config.remove(&key) {
+                joins.extend(handler.reload(&key, ob, c).await?);
+            }
+        }
+        Ok(joins)
+    }
+    async fn call<AH, EH>(&self, event: E, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        let handlers = self.snapshot();
+        match self.mode {
+            DispatchMode::Sequential => {
+                let mut results = vec![];
+                for (_, handler) in &handlers {
+                    results.push(handler.call(event.clone(), ob).await);
+                }
+                WalleError::collect(results)
+            }
+            DispatchMode::Concurrent => WalleError::collect(
+                join_all(handlers.iter().map(|(_, h)| h.call(event.clone(), ob))).await,
+            ),
+        }
+    }
+    async fn before_call_action<AH, EH>(
+        &self,
+        mut action: A,
+        _ob: &Arc<OneBot<AH, EH>>,
+    ) -> WalleResult<A>
+    where
+        A: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        for (_, handler) in self.snapshot() {
+            action = handler.before_call_action(action, ob).await?;
+        }
+        Ok(action)
+    }
+    async fn after_call_action<AH, EH>(
+        &self,
+        mut resp: R,
+        _ob: &Arc<OneBot<AH, EH>>,
+    ) -> WalleResult<R>
+    where
+        R: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        for (_, handler) in self.snapshot() {
+            resp = handler.after_call_action(resp, ob).await?;
+        }
+        Ok(resp)
+    }
+    async fn shutdown(&self) {
+        for (_, handler) in self.snapshot() {
+            handler.shutdown().await;
+        }
+    }
+    async fn on_onebot_connect<AH, EH>(&self, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        let mut results = vec![];
+        for (_, handler) in self.snapshot() {
+            results.push(handler.on_onebot_connect(ob).await);
+        }
+        WalleError::collect(results)
+    }
+    async fn on_onebot_disconnect<AH, EH>(&self, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        let mut results = vec![];
+        for (_, handler) in self.snapshot() {
+            results.push(handler.on_onebot_disconnect(ob).await);
+        }
+        WalleError::collect(results)
+    }
+}
+
+trait DynActionHandler<O, E, A, R>: Send + Sync {
+    fn get_selfs(&self) -> BoxFuture<'_, Vec<Selft>>;
+    fn get_impl<'a>(&'a self, selft: &'a Selft) -> BoxFuture<'a, String>;
+    fn has_self<'a>(&'a self, selft: &'a Selft) -> BoxFuture<'a, bool>;
+    fn selfs_epoch(&self) -> u64;
+    fn is_good(&self) -> BoxFuture<'_, bool>;
+    fn get_bots(&self) -> BoxFuture<'_, Vec<Bot>>;
+    fn get_version(&self) -> Version;
+    fn start<'a>(
+        &'a self,
+        key: &'a str,
+        ob: &'a Arc<O>,
+        config: Option<Box<dyn Any + Send + Sync>>,
+    ) -> BoxFuture<'a, WalleResult<Vec<JoinHandle<()>>>>;
+    fn reload<'a>(
+        &'a self,
+        key: &'a str,
+        ob: &'a Arc<O>,
+        config: Box<dyn Any + Send + Sync>,
+    ) -> BoxFuture<'a, WalleResult<Vec<JoinHandle<()>>>>;
+    fn call<'a>(&'a self, action: A, ob: &'a Arc<O>) -> BoxFuture<'a, WalleResult<R>>;
+    fn before_call_event<'a>(&'a self, event: E, ob: &'a Arc<O>) -> BoxFuture<'a, WalleResult<E>>;
+    fn after_call_event<'a>(&'a self, ob: &'a Arc<O>) -> BoxFuture<'a, WalleResult<()>>;
+    fn shutdown(&self) -> BoxFuture<'_, ()>;
+    fn on_onebot_connect<'a>(&'a self, ob: &'a Arc<O>) -> LocalBoxFuture<'a, WalleResult<()>>;
+    fn on_onebot_disconnect<'a>(&'a self, ob: &'a Arc<O>) -> LocalBoxFuture<'a, WalleResult<()>>;
+}
+
+impl<H, AH, EH, E, A, R> DynActionHandler<OneBot<AH, EH>, E, A, R> for Adapter<H>
+where
+    H: ActionHandler<E, A, R> + Send + Sync + 'static,
+    H::Config: Default + Send + 'static,
+    AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+    EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    E: Send + 'static,
+    A: Send + 'static,
+    R: Send + 'static,
+{
+    fn get_selfs(&self) -> BoxFuture<'_, Vec<Selft>> {
+        Box::pin(self.0.get_selfs())
+    }
+    fn get_impl<'a>(&'a self, selft: &'a Selft) -> BoxFuture<'a, String> {
+        Box::pin(self.0.get_impl(selft))
+    }
+    fn has_self<'a>(&'a self, selft: &'a Selft) -> BoxFuture<'a, bool> {
+        Box::pin(self.0.has_self(selft))
+    }
+    fn selfs_epoch(&self) -> u64 {
+        self.0.selfs_epoch()
+    }
+    fn is_good(&self) -> BoxFuture<'_, bool> {
+        Box::pin(self.0.is_good())
+    }
+    fn get_bots(&self) -> BoxFuture<'_, Vec<Bot>> {
+        Box::pin(self.0.get_bots())
+    }
+    fn get_version(&self) -> Version {
+        self.0.get_version()
+    }
+    fn start<'a>(
+        &'a self,
+        key: &'a str,
+        ob: &'a Arc<OneBot<AH, EH>>,
+        config: Option<Box<dyn Any + Send + Sync>>,
+    ) -> BoxFuture<'a, WalleResult<Vec<JoinHandle<()>>>> {
+        Box::pin(async move {
+            let config = downcast_config(key, config)?;
+            self.0.start(ob, config).await
+        })
+    }
+    fn reload<'a>(
+        &'a self,
+        key: &'a str,
+        ob: &'a Arc<OneBot<AH, EH>>,
+        config: Box<dyn Any + Send + Sync>,
+    ) -> BoxFuture<'a, WalleResult<Vec<JoinHandle<()>>>> {
+        Box::pin(async move {
+            let config = downcast_config(key, Some(config))?;
+            self.0.reload(ob, config).await
+        })
+    }
+    fn call<'a>(&'a self, action: A, ob: &'a Arc<OneBot<AH, EH>>) -> BoxFuture<'a, WalleResult<R>> {
+        Box::pin(async move { self.0.call(action, ob).await })
+    }
+    fn before_call_event<'a>(
+        &'a self,
+        event: E,
+        ob: &'a Arc<OneBot<AH, EH>>,
+    ) -> BoxFuture<'a, WalleResult<E>> {
+        Box::pin(async move { self.0.before_call_event(event, ob).await })
+    }
+    fn after_call_event<'a>(
+        &'a self,
+        ob: &'a Arc<OneBot<AH, EH>>,
+    ) -> BoxFuture<'a, WalleResult<()>> {
+        Box::pin(async move { self.0.after_call_event(ob).await })
+    }
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        Box::pin(self.0.shutdown())
+    }
+    fn on_onebot_connect<'a>(
+        &'a self,
+        ob: &'a Arc<OneBot<AH, EH>>,
+    ) -> LocalBoxFuture<'a, WalleResult<()>> {
+        Box::pin(async move { self.0.on_onebot_connect(ob).await })
+    }
+    fn on_onebot_disconnect<'a>(
+        &'a self,
+        ob: &'a Arc<OneBot<AH, EH>>,
+    ) -> LocalBoxFuture<'a, WalleResult<()>> {
+        Box::pin(async move { self.0.on_onebot_disconnect(ob).await })
+    }
+}
+
+/// 持有任意数量 ActionHandler，Action 按 self 路由至对应 Handler
+///
+/// C 为所在 OneBot 的 Context，与 EventHandlerSet 搭配时为 [`Paired`]
+pub struct ActionHandlerSet<C = Paired, E = Event, A = Action, R = Resp>
+where
+    C: ActionSetContext<E, A, R>,
+{
+    ob: Weak<ActionSetOneBot<C, E, A, R>>,
+    handlers: RwLock<Keyed<DynAH<C, E, A, R>>>,
+    /// 以 Handler 的 key 为目标的路由表
+    pub router: SelftRouter<String>,
+}
+
+impl<C, E, A, R> ActionHandlerSet<C, E, A, R>
+where
+    C: ActionSetContext<E, A, R>,
+{
+    /// ob 为所在 OneBot 的 Weak 引用，通常由 `Arc::new_cyclic` 提供
+    pub fn new(ob: Weak<ActionSetOneBot<C, E, A, R>>) -> Self {
+        Self {
+            ob,
+            handlers: RwLock::default(),
+            router: SelftRouter::default(),
+        }
+    }
+    /// 插入 Handler，同 key 的 Handler 将被替换
+    pub fn insert<H>(&self, key: impl Into<String>, handler: H)
+    where
+        H: ActionHandler<E, A, R> + Send + Sync + 'static,
+        H::Config: Default + Send + 'static,
+        C::AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        C::EH: EventHandler<E, A, R> + Send + Sync + 'static,
+        E: Send + 'static,
+        A: Send + 'static,
+        R: Send + 'static,
+    {
+        let key = key.into();
+        let handler: Arc<DynAH<C, E, A, R>> = Arc::new(Adapter(handler));
+        let mut handlers = self.handlers.write().unwrap();
+        match handlers.iter_mut().find(|(k, _)| k == &key) {
+            Some((_, h)) => {
+                *h = handler;
+                self.router.remove_target(&key);
+            }
+            None => handlers.push((key, handler)),
+        }
+    }
+    /// 移除并关闭 Handler
+    pub async fn remove(&self, key: &str) -> bool {
+        let removed = {
+            let mut handlers = self.handlers.write().unwrap();
+            let index = handlers.iter().position(|(k, _)| k == key);
+            index.map(|i| handlers.remove(i).1)
+        };
+        match removed {
+            Some(handler) => {
+                self.router.remove_target(&key.to_string());
+                handler.shutdown().await;
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn keys(&self) -> Vec<String> {
+        let handlers = self.handlers.read().unwrap();
+        handlers.iter().map(|(k, _)| k.clone()).collect()
+    }
+    /// 启动运行时插入的 Handler
+    pub async fn start_handler(
+        &self,
+        key: &str,
+        config: Option<Box<dyn Any + Send + Sync>>,
+    ) -> WalleResult<Vec<JoinHandle<()>>> {
+        let handler = self
+            .get(key)
+            .ok_or_else(|| WalleError::MapMissedKey(key.to_string()))?;
+        handler.start(key, &upgrade(&self.ob)?, config).await
+    }
+    /// selft 为空的 Action 发往该 key 对应的 Handler
+    pub fn set_default(&self, key: Option<impl Into<String>>) {
+        self.router.set_default(key.map(Into::into));
+    }
+    fn get(&self, key: &str) -> Option<Arc<DynAH<C, E, A, R>>> {
+        let handlers = self.handlers.read().unwrap();
+        handlers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, h)| h.clone())
+    }
+    fn snapshot(&self) -> Keyed<DynAH<C, E, A, R>> {
+        self.handlers.read().unwrap().clone()
+    }
+    fn epoch(&self) -> u64 {
+        let handlers = self.handlers.read().unwrap();
+        handlers
+            .iter()
+            .fold(0, |epoch, (_, h)| epoch.wrapping_add(h.selfs_epoch()))
+    }
+    async fn find(&self, selft: &Selft) -> Option<Arc<DynAH<C, E, A, R>>> {
+        let key = self
+            .router
+            .route(
+                selft,
+                || self.epoch(),
+                || self.keys(),
+                |key| async move {
+                    match self.get(&key) {
+                        Some(handler) => handler.has_self(selft).await,
+                        None => false,
+                    }
+                },
+            )
+            .await?;
+        self.get(&key)
+    }
+}
+
+impl<C, E, A, R> GetSelfs for ActionHandlerSet<C, E, A, R>
+where
+    C: ActionSetContext<E, A, R>,
+    E: Send + Sync,
+    A: Send + Sync,
+    R: Send + Sync,
+{
+    async fn get_selfs(&self) -> Vec<Selft> {
+        let mut selfs = vec![];
+        for (_, handler) in self.snapshot() {
+            selfs.extend(handler.get_selfs().await);
+        }
+        selfs
+    }
+    async fn get_impl(&self, selft: &Selft) -> String {
+        match self.find(selft).await {
+            Some(handler) => handler.get_impl(selft).await,
+            None => String::default(),
+        }
+    }
+    async fn has_self(&self, selft: &Selft) -> bool {
+        self.find(selft).await.is_some()
+    }
+    fn selfs_epoch(&self) -> u64 {
+        self.epoch()
+    }
+}
+
+impl<C, E, A, R> GetStatus for ActionHandlerSet<C, E, A, R>
+where
+    C: ActionSetContext<E, A, R>,
+    E: Send + Sync,
+    A: Send + Sync,
+    R: Send + Sync,
+{
+    async fn is_good(&self) -> bool {
+        for (_, handler) in self.snapshot() {
+            if !handler.is_good().await {
+                return false;
+            }
+        }
+        true
+    }
+    async fn get_bots(&self) -> Vec<Bot> {
+        let mut bots = vec![];
+        for (_, handler) in self.snapshot() {
+            bots = merge_bots(bots, handler.get_bots().await);
+        }
+        bots
+    }
+}
+
+impl<C, E, A, R> GetVersion for ActionHandlerSet<C, E, A, R>
+where
+    C: ActionSetContext<E, A, R>,
+{
+    fn get_version(&self) -> Version {
+        let handlers = self.handlers.read().unwrap();
+        match handlers.first() {
+            Some((_, handler)) => handler.get_version(),
+            None => Version {
+                implt: WALLE_CORE.to_string(),
+                version: VERSION.to_string(),
+                onebot_version: "12".to_string(),
+            },
+        }
+    }
+}
+
+/// 各 Handler 收到的 OneBot 为构造时传入的 ob
+impl<C, E, A, R> ActionHandler<E, A, R> for ActionHandlerSet<C, E, A, R>
+where
+    C: ActionSetContext<E, A, R> + 'static,
+    C::AH: 'static,
+    C::EH: 'static,
+    E: Send + Sync + 'static,
+    A: GetSelf + Send + Sync + 'static,
+    R: From<RespError> + Send + Sync + 'static,
+{
+    type Config = HandlerConfigs;
+    /// 任一 Handler 启动失败时关闭已启动的 Handler
+    async fn start<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        mut config: Self::Config,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        let mut joins = vec![];
+        let mut started = vec![];
+        for (key, handler) in self.snapshot() {
+            let c = config.remove(&key);
+            match handler.start(&key, ob, c).await {
+                Ok(j) => {
+                    joins.extend(j);
+                    started.push(handler);
+                }
+                Err(e) => {
+                    for handler in started {
+                        handler.shutdown().await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(joins)
+    }
+    /// 仅重载 config 中含有的 Handler
+    async fn reload<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        mut config: Self::Config,
+    ) -> WalleResult<Vec<JoinHandle<()>>>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        let mut joins = vec![];
+        for (key, handler) in self.snapshot() {
+            if let Some(c) = config.remove(&key) {
+                joins.extend(handler.reload(&key, ob, c).await?);
+            }
+        }
+        Ok(joins)
+    }
+    async fn call<AH, EH>(&self, action: A, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<R>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        let selft = action.get_self();
+        match self.find(&selft).await {
+            Some(handler) => handler.call(action, ob).await,
+            None => Ok(unknown_self(&selft).into()),
+        }
+    }
+    async fn before_call_event<AH, EH>(
+        &self,
+        mut event: E,
+        _ob: &Arc<OneBot<AH, EH>>,
+    ) -> WalleResult<E>
+    where
+        E: Send + 'static,
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        for (_, handler) in self.snapshot() {
+            event = handler.before_call_event(event, ob).await?;
+        }
+        Ok(event)
+    }
+    async fn after_call_event<AH, EH>(&self, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        let mut results = vec![];
+        for (_, handler) in self.snapshot() {
+            results.push(handler.after_call_event(ob).await);
+        }
+        WalleError::collect(results)
+    }
+    async fn shutdown(&self) {
+        for (_, handler) in self.snapshot() {
+            handler.shutdown().await;
+        }
+    }
+    async fn on_onebot_connect<AH, EH>(&self, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        self.router.clear();
+        let mut results = vec![];
+        for (_, handler) in self.snapshot() {
+            results.push(handler.on_onebot_connect(ob).await);
+        }
+        WalleError::collect(results)
+    }
+    async fn on_onebot_disconnect<AH, EH>(&self, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler<E, A, R> + Send + Sync + 'static,
+        EH: EventHandler<E, A, R> + Send + Sync + 'static,
+    {
+        let ob = &upgrade(&self.ob)?;
+        self.router.clear();
+        let mut results = vec![];
+        for (_, handler) in self.snapshot() {
+            results.push(handler.on_onebot_disconnect(ob).await);
+        }
+        WalleError::collect(results)
+    }
+}
@@ -58,6 +58,20 @@ pub struct File {
     pub sha256: Option<String>,
 }
 
+/// get_file_fragmented prepare 阶段的响应
+#[derive(Debug, Clone, PartialEq, Eq, PushToValueMap, TryFromValue)]
+pub struct FileFragmentedHead {
+    pub name: String,
+    pub total_size: i64,
+    pub sha256: String,
+}
+
+/// get_file_fragmented transfer 阶段的响应
+#[derive(Debug, Clone, PartialEq, Eq, PushToValueMap, TryFromValue)]
+pub struct FileFragment {
+    pub data: crate::util::OneBotBytes,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PushToValueMap, TryFromValue)]
 pub struct GuildInfo {
     pub guild_id: String,
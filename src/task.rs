@@ -0,0 +1,97 @@
+//! 进行中任务的登记，用于优雅关闭
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+
+/// 优雅关闭的结果
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// 正常结束的任务数
+    pub finished: usize,
+    /// 超时后被中止的任务
+    pub aborted: Vec<String>,
+}
+
+impl ShutdownReport {
+    /// 所有任务均在超时前结束
+    pub fn is_clean(&self) -> bool {
+        self.aborted.is_empty()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Tasks {
+    seq: AtomicU64,
+    // 登记于 spawn 之前，AbortHandle 于 spawn 后补上
+    running: Mutex<HashMap<u64, (String, Option<AbortHandle>)>>,
+    notify: Notify,
+}
+
+/// 任务结束、panic 或被中止时移除登记
+struct Guard {
+    tasks: Arc<Tasks>,
+    id: u64,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.tasks.running.lock().unwrap().remove(&self.id);
+        self.tasks.notify.notify_waiters();
+    }
+}
+
+impl Tasks {
+    pub(crate) fn spawn<F>(self: &Arc<Self>, name: String, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let id = self.seq.fetch_add(1, Ordering::Relaxed);
+        self.running.lock().unwrap().insert(id, (name, None));
+        let guard = Guard {
+            tasks: self.clone(),
+            id,
+        };
+        let handle = tokio::spawn(async move {
+            let _guard = guard;
+            fut.await;
+        });
+        // 任务已结束时登记已被移除
+        if let Some(task) = self.running.lock().unwrap().get_mut(&id) {
+            task.1 = Some(handle.abort_handle());
+        }
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.running.lock().unwrap().len()
+    }
+    /// 等待所有任务结束，超时后中止剩余任务并返回其名称
+    pub(crate) async fn drain(&self, timeout: Duration) -> Vec<String> {
+        let wait = async {
+            loop {
+                let notified = self.notify.notified();
+                if self.running.lock().unwrap().is_empty() {
+                    break;
+                }
+                notified.await;
+            }
+        };
+        if tokio::time::timeout(timeout, wait).await.is_ok() {
+            return vec![];
+        }
+        let running = std::mem::take(&mut *self.running.lock().unwrap());
+        running
+            .into_values()
+            .map(|(name, handle)| {
+                if let Some(handle) = handle {
+                    handle.abort();
+                }
+                name
+            })
+            .collect()
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use crate::{
     event::{ToEvent, TypeLevel},
     util::PushToValueMap,
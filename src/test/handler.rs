@@ -0,0 +1,1104 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    action::Action,
+    error::{WalleError, WalleResult},
+    event::Event,
+    middleware::{from_fn, Next},
+    resp::Resp,
+    structs::{Selft, Version},
+    util::Value,
+    AHExt, ActionHandler, ActionHandlerSet, DispatchMode, EHExt, EventHandler, EventHandlerSet,
+    EventSetContext, GetSelfs, GetStatus, GetVersion, HandlerConfigs, JoinedHandler, OneBot,
+    SelftRouter,
+};
+
+/// 以名称应答所有 Action 的 ActionHandler
+#[derive(Default)]
+pub struct NopAH(pub &'static str, pub Vec<Selft>);
+
+impl GetSelfs for NopAH {
+    async fn get_selfs(&self) -> Vec<Selft> {
+        self.1.clone()
+    }
+    async fn get_impl(&self, _selft: &Selft) -> String {
+        self.0.to_string()
+    }
+}
+
+impl GetStatus for NopAH {
+    async fn is_good(&self) -> bool {
+        true
+    }
+}
+
+impl GetVersion for NopAH {
+    fn get_version(&self) -> Version {
+        Version {
+            implt: "nop".to_string(),
+            version: "0".to_string(),
+            onebot_version: "12".to_string(),
+        }
+    }
+}
+
+impl ActionHandler for NopAH {
+    type Config = ();
+    async fn start<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _config: (),
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler + Send + Sync + 'static,
+        EH: EventHandler + Send + Sync + 'static,
+    {
+        Ok(vec![])
+    }
+    async fn call<AH, EH>(&self, _action: Action, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<Resp>
+    where
+        AH: ActionHandler + Send + Sync + 'static,
+        EH: EventHandler + Send + Sync + 'static,
+    {
+        Ok(Resp::ok(self.0, ""))
+    }
+}
+
+/// 计数并按需休眠、等待或报错的 EventHandler
+#[derive(Default)]
+pub struct CountEH {
+    pub count: Arc<AtomicUsize>,
+    pub delay: u64,
+    pub fail: bool,
+    pub stopped: Arc<AtomicUsize>,
+    pub barrier: Option<Arc<tokio::sync::Barrier>>,
+}
+
+impl CountEH {
+    /// 与其他 CountEH 共用计数
+    pub fn shared(count: &Arc<AtomicUsize>) -> Self {
+        Self {
+            count: count.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl EventHandler for CountEH {
+    type Config = ();
+    async fn start<AH, EH>(
+        &self,
+        _ob: &Arc<OneBot<AH, EH>>,
+        _config: (),
+    ) -> WalleResult<Vec<tokio::task::JoinHandle<()>>>
+    where
+        AH: ActionHandler + Send + Sync + 'static,
+        EH: EventHandler + Send + Sync + 'static,
+    {
+        Ok(vec![])
+    }
+    async fn call<AH, EH>(&self, _event: Event, _ob: &Arc<OneBot<AH, EH>>) -> WalleResult<()>
+    where
+        AH: ActionHandler + Send + Sync + 'static,
+        EH: EventHandler + Send + Sync + 'static,
+    {
+        tokio::time::sleep(Duration::from_millis(self.delay)).await;
+        if let Some(barrier) = &self.barrier {
+            barrier.wait().await;
+        }
+        self.count.fetch_add(1, Ordering::SeqCst);
+        if self.fail {
+            Err(WalleError::Other("failed".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+    async fn shutdown(&self) {
+        self.stopped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn event() -> Event {
+    Event {
+        id: "".to_string(),
+        time: 0.0,
+        ty: "meta".to_string(),
+        detail_type: "heartbeat".to_string(),
+        sub_type: "".to_string(),
+        extra: Default::default(),
+    }
+}
+
+#[tokio::test]
+async fn dispatch_mode() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let eh = |delay, fail| CountEH {
+        delay,
+        fail,
+        ..CountEH::shared(&count)
+    };
+
+    let ob = Arc::new(OneBot::new(
+        NopAH::default(),
+        eh(0, true).join(eh(0, true)).join(eh(0, false)),
+    ));
+    match ob.handle_event(event()).await {
+        Err(WalleError::Multi(errors)) => assert_eq!(errors.len(), 2),
+        r => panic!("unexpected {:?}", r),
+    }
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+
+    // 两者均需等到对方开始处理后才能完成，仅并发调用时不会超时
+    for (mode, overlapped) in [
+        (DispatchMode::Sequential, false),
+        (DispatchMode::Concurrent, true),
+    ] {
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        let eh = || CountEH {
+            barrier: Some(barrier.clone()),
+            ..CountEH::shared(&count)
+        };
+        let ob = Arc::new(OneBot::new(NopAH::default(), eh().join_with(eh(), mode)));
+        let handled = tokio::time::timeout(Duration::from_millis(100), ob.handle_event(event()));
+        assert_eq!(handled.await.is_ok(), overlapped);
+    }
+    assert_eq!(count.load(Ordering::SeqCst), 5);
+
+    let ob = Arc::new(OneBot::new(
+        NopAH::default(),
+        eh(0, false).join(eh(50, true).detach()),
+    ));
+    ob.handle_event(event()).await.unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 6);
+    // detach 的任务登记于 OneBot
+    assert_eq!(ob.running_tasks(), 1);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(count.load(Ordering::SeqCst), 7);
+    assert_eq!(ob.running_tasks(), 0);
+
+    // 优雅关闭时超时的 detach 任务被中止
+    let ob = Arc::new(OneBot::new(NopAH::default(), eh(500, false).detach()));
+    ob.start((), (), true).await.unwrap();
+    ob.handle_event(event()).await.unwrap();
+    let report = ob
+        .shutdown_graceful(true, Duration::from_millis(50))
+        .await
+        .unwrap();
+    assert_eq!(report.aborted, vec!["detached event handler".to_string()]);
+    assert_eq!(count.load(Ordering::SeqCst), 7);
+}
+
+fn selft(user_id: &str) -> Selft {
+    Selft {
+        platform: "qq".to_string(),
+        user_id: user_id.to_string(),
+    }
+}
+
+/// 以 implt 应答并持有 user_ids 各 bot 的 NopAH，搭配默认 CountEH
+fn nop_onebot(implt: &'static str, user_ids: &[&str]) -> Arc<OneBot<NopAH, CountEH>> {
+    Arc::new(OneBot::new(
+        NopAH(
+            implt,
+            user_ids.iter().map(|user_id| selft(user_id)).collect(),
+        ),
+        CountEH::default(),
+    ))
+}
+
+#[tokio::test]
+async fn handler_set() {
+    type OB = OneBot<ActionHandlerSet, EventHandlerSet>;
+    let count = Arc::new(AtomicUsize::new(0));
+    let ob: Arc<OB> = Arc::new_cyclic(|ob| {
+        OneBot::new(
+            ActionHandlerSet::new(ob.clone()),
+            EventHandlerSet::new(ob.clone(), DispatchMode::Sequential),
+        )
+    });
+    let ahs = ob.action_handler();
+    ahs.insert("a", NopAH("a", vec![selft("1")]));
+    ahs.insert("b", NopAH("b", vec![selft("2")]));
+    let stopped = Arc::new(AtomicUsize::new(0));
+    for key in ["x", "y", "z"] {
+        ob.event_handler().insert(
+            key,
+            CountEH {
+                stopped: stopped.clone(),
+                ..CountEH::shared(&count)
+            },
+        );
+    }
+    let mut configs = HandlerConfigs::new();
+    configs.insert("y".to_string(), Box::new(1u8));
+    assert!(matches!(
+        ob.start(HandlerConfigs::new(), configs, true).await,
+        Err(WalleError::ValueTypeNotMatch(..))
+    ));
+    // 启动失败时已启动的 x 被关闭
+    assert_eq!(stopped.load(Ordering::SeqCst), 1);
+    ob.shutdown(true).await.unwrap();
+    ob.start(HandlerConfigs::new(), HandlerConfigs::new(), true)
+        .await
+        .unwrap();
+
+    ob.handle_event(event()).await.unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+    assert!(ob.event_handler().remove("y").await);
+    ob.handle_event(event()).await.unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 5);
+
+    let action = |user_id: &str| Action {
+        action: "get_self_info".to_string(),
+        selft: Some(selft(user_id)),
+        params: Default::default(),
+    };
+    assert_eq!(
+        ob.handle_action(action("2")).await.unwrap().data,
+        "b".into()
+    );
+    assert_eq!(ob.handle_action(action("3")).await.unwrap().retcode, 10101);
+    assert_eq!(ob.get_selfs().await.len(), 2);
+    assert!(ob.action_handler().remove("b").await);
+    assert_eq!(ob.handle_action(action("2")).await.unwrap().retcode, 10101);
+
+    // 与普通 ActionHandler 搭配
+    let ob = Arc::new_cyclic(|ob| {
+        OneBot::new(
+            NopAH::default(),
+            EventHandlerSet::<NopAH>::new(ob.clone(), DispatchMode::Sequential),
+        )
+    });
+    ob.event_handler().insert("x", CountEH::shared(&count));
+    ob.handle_event(event()).await.unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 6);
+
+    // 嵌套于 JoinedHandler 中，所在 OneBot 的类型由 Context 给出
+    struct Nested;
+    impl EventSetContext<Event, Action, Resp> for Nested {
+        type AH = NopAH;
+        type EH = JoinedHandler<EventHandlerSet<Nested>, CountEH>;
+    }
+    let ob = Arc::new_cyclic(|ob| {
+        OneBot::new(
+            NopAH::default(),
+            EventHandlerSet::<Nested>::new(ob.clone(), DispatchMode::Sequential)
+                .join(CountEH::shared(&count)),
+        )
+    });
+    ob.event_handler().0.insert("x", CountEH::shared(&count));
+    ob.start((), (HandlerConfigs::new(), ()), true)
+        .await
+        .unwrap();
+    ob.handle_event(event()).await.unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 8);
+}
+
+#[tokio::test]
+async fn selft_router() {
+    let ah = NopAH("a", vec![selft("1")])
+        .join_routed(NopAH("b", vec![selft("2")]))
+        .join_routed(NopAH("c", vec![selft("3")]))
+        .with_default(1);
+    let ob = Arc::new(OneBot::new(ah, CountEH::default()));
+    let action = |selft: Option<Selft>| Action {
+        action: "get_self_info".to_string(),
+        selft,
+        params: Default::default(),
+    };
+    for (user_id, implt) in [("1", "a"), ("2", "b"), ("3", "c")] {
+        let resp = ob
+            .handle_action(action(Some(selft(user_id))))
+            .await
+            .unwrap();
+        assert_eq!(resp.data, implt.into());
+    }
+    assert_eq!(ob.action_handler().router().get(&selft("2")), Some(0));
+    assert_eq!(
+        ob.action_handler().first().router().get(&selft("2")),
+        Some(1)
+    );
+    assert_eq!(
+        ob.handle_action(action(None)).await.unwrap().data,
+        "c".into()
+    );
+    let resp = ob.handle_action(action(Some(selft("9")))).await.unwrap();
+    assert_eq!(resp.retcode, 10101);
+    assert!(resp.message.contains("qq-9"));
+    assert!(!ob.has_self(&selft("9")).await);
+
+    let ob = Arc::new(OneBot::new(
+        NopAH("a", vec![selft("1")]).join(NopAH("b", vec![selft("2")])),
+        CountEH::default(),
+    ));
+    let resp = ob.handle_action(action(Some(selft("2")))).await.unwrap();
+    assert_eq!(resp.data, "b".into());
+}
+
+#[tokio::test]
+async fn selft_router_epoch() {
+    let router = SelftRouter::default();
+    let probed = AtomicUsize::new(0);
+    let has_self = |target: usize| {
+        probed.fetch_add(1, Ordering::SeqCst);
+        async move { target == 1 }
+    };
+    assert_eq!(
+        router.route(&selft("1"), || 0, || [0, 1], &has_self).await,
+        Some(1)
+    );
+    assert_eq!(probed.load(Ordering::SeqCst), 2);
+    // 命中缓存时不再取 targets，也不再探测
+    let targets = || -> [usize; 2] { unreachable!() };
+    assert_eq!(
+        router.route(&selft("1"), || 0, targets, &has_self).await,
+        Some(1)
+    );
+    assert_eq!(probed.load(Ordering::SeqCst), 2);
+    // 设置默认后端后，selft 为空时不计算 epoch
+    router.set_default(Some(0));
+    let epoch = || -> u64 { unreachable!() };
+    assert_eq!(
+        router
+            .route(&Selft::default(), epoch, targets, &has_self)
+            .await,
+        Some(0)
+    );
+    // bot 迁移至后端 0：epoch 未变时仍按缓存路由至 1
+    let moved = |target: usize| {
+        probed.fetch_add(1, Ordering::SeqCst);
+        async move { target == 0 }
+    };
+    assert_eq!(
+        router.route(&selft("1"), || 0, targets, &moved).await,
+        Some(1)
+    );
+    assert_eq!(probed.load(Ordering::SeqCst), 2);
+    // epoch 变化后缓存失效，重新探测并路由至 0
+    assert_eq!(
+        router.route(&selft("1"), || 1, || [0, 1], &moved).await,
+        Some(0)
+    );
+    assert_eq!(probed.load(Ordering::SeqCst), 3);
+    assert_eq!(router.get(&selft("1")), Some(0));
+    // bot 断开后同样重新探测，不再路由
+    let gone = |_: usize| async { false };
+    assert_eq!(router.route(&selft("1"), || 2, || [0, 1], gone).await, None);
+    assert_eq!(router.get(&selft("1")), None);
+}
+
+#[tokio::test]
+async fn middleware() {
+    let ob = nop_onebot("a", &["1"]);
+    let log = Arc::new(std::sync::Mutex::new(vec![]));
+    let log0 = log.clone();
+    ob.layer_action(from_fn(
+        move |action: Action, next: Next<'_, Action, Resp>| {
+            let log = log0.clone();
+            Box::pin(async move {
+                log.lock().unwrap().push(format!("in {}", action.action));
+                let resp = next.run(action).await;
+                log.lock().unwrap().push("out".to_string());
+                resp
+            })
+        },
+    ))
+    .unwrap();
+    ob.layer_action(from_fn(
+        |mut action: Action, next: Next<'_, Action, Resp>| {
+            Box::pin(async move {
+                if action.selft.is_none() {
+                    return Ok(crate::resp::resp_error::who_am_i("").into());
+                }
+                action.action = "rewritten".to_string();
+                next.run(action).await
+            })
+        },
+    ))
+    .unwrap();
+    let action = |selft| Action {
+        action: "get_self_info".to_string(),
+        selft,
+        params: Default::default(),
+    };
+    assert_eq!(
+        ob.handle_action(action(Some(selft("1"))))
+            .await
+            .unwrap()
+            .data,
+        "a".into()
+    );
+    assert_eq!(ob.handle_action(action(None)).await.unwrap().retcode, 10101);
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["in get_self_info", "out", "in get_self_info", "out"]
+    );
+
+    let ob = nop_onebot("", &[]);
+    ob.layer_event(from_fn(|event: Event, next: Next<'_, Event, ()>| {
+        Box::pin(async move {
+            if event.ty == "meta" {
+                Err(WalleError::Other("blocked".to_string()))
+            } else {
+                next.run(event).await
+            }
+        })
+    }))
+    .unwrap();
+    // 类型与已添加者不同时拒绝
+    assert!(ob
+        .layer_event(from_fn(|v: Value, next: Next<'_, Value, ()>| next.run(v)))
+        .is_err());
+    assert!(ob.handle_event(event()).await.is_err());
+    assert_eq!(ob.event_handler().count.load(Ordering::SeqCst), 0);
+    // 已处理过 Event 后拒绝
+    assert!(ob
+        .layer_event(from_fn(|e: Event, next: Next<'_, Event, ()>| next.run(e)))
+        .is_err());
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn tower_service() {
+    use crate::service::{ActionService, ServiceHandler};
+    use tower_service::Service;
+
+    let inner = nop_onebot("a", &["1"]);
+    let mut service = ActionService::new(inner.clone());
+    futures_util::future::poll_fn(|cx| Service::<Action>::poll_ready(&mut service, cx))
+        .await
+        .unwrap();
+    let action = Action {
+        action: "get_self_info".to_string(),
+        selft: Some(selft("1")),
+        params: Default::default(),
+    };
+    assert_eq!(service.call(action.clone()).await.unwrap().data, "a".into());
+
+    let ob = Arc::new(OneBot::new(
+        ServiceHandler::new(service).with_selfs(vec![selft("1")]),
+        CountEH::default(),
+    ));
+    assert_eq!(ob.handle_action(action).await.unwrap().data, "a".into());
+    assert!(ob.has_self(&selft("1")).await);
+}
+
+#[tokio::test]
+async fn graceful_shutdown() {
+    let ob = nop_onebot("", &[]);
+    ob.start((), (), true).await.unwrap();
+    /// 任务结束或被中止时均被 drop
+    struct Dropped(Arc<AtomicUsize>);
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    let done = Arc::new(AtomicUsize::new(0));
+    let dropped = Arc::new(AtomicUsize::new(0));
+    for (name, delay) in [("fast", 10), ("slow", 1000)] {
+        let done = done.clone();
+        let dropped = Dropped(dropped.clone());
+        ob.spawn(name, async move {
+            let _dropped = dropped;
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            done.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+    // panic 的任务同样移除登记
+    ob.spawn("panic", async { panic!("task panicked") });
+    tokio::time::sleep(Duration::from_millis(1)).await;
+    assert_eq!(ob.running_tasks(), 2);
+    let report = ob
+        .shutdown_graceful(true, Duration::from_millis(100))
+        .await
+        .unwrap();
+    assert_eq!(report.finished, 1);
+    assert_eq!(report.aborted, ["slow"]);
+    assert!(!report.is_clean());
+    assert_eq!(ob.running_tasks(), 0);
+    // slow 确已中止而非仍在后台运行
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(dropped.load(Ordering::SeqCst), 2);
+    assert_eq!(done.load(Ordering::SeqCst), 1);
+    assert!(matches!(
+        ob.shutdown_graceful(true, Duration::ZERO).await,
+        Err(WalleError::NotStarted)
+    ));
+}
+
+#[tokio::test]
+async fn reload() {
+    let ob = nop_onebot("", &[]);
+    assert!(matches!(
+        ob.reload_action_handler(()).await,
+        Err(WalleError::NotStarted)
+    ));
+    ob.start((), (), true).await.unwrap();
+    assert!(matches!(
+        ob.reload_event_handler(()).await,
+        Err(WalleError::ReloadNotSupported)
+    ));
+    ob.shutdown(true).await.unwrap();
+    // 关闭后可再次启动
+    ob.start((), (), true).await.unwrap();
+    ob.shutdown(true).await.unwrap();
+}
+
+#[cfg(all(feature = "impl-obc", feature = "websocket"))]
+#[tokio::test]
+async fn reload_endpoints() {
+    use crate::config::{ImplConfig, WebSocketServer};
+    use crate::obc::ImplOBC;
+    use futures_util::StreamExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let port = || {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    };
+    let config = |ports: &[u16]| ImplConfig {
+        websocket: ports
+            .iter()
+            .map(|&port| WebSocketServer {
+                port,
+                ..Default::default()
+            })
+            .collect(),
+        websocket_rev: vec![],
+        ..Default::default()
+    };
+    let connect = |port: u16| async move {
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{}", port))
+            .await
+            .unwrap();
+        // connect 与 status_update
+        for _ in 0..2 {
+            ws.next().await.unwrap().unwrap();
+        }
+        ws
+    };
+    let (a, b) = (port(), port());
+    let implt = Arc::new(OneBot::new(
+        NopAH("a", vec![selft("1")]),
+        ImplOBC::<Event>::new("a".to_string()),
+    ));
+    implt.start((), config(&[a]), false).await.unwrap();
+    let mut ws_a = connect(a).await;
+
+    // 新增 b，a 上的连接保持
+    implt.reload_event_handler(config(&[a, b])).await.unwrap();
+    let mut ws_b = connect(b).await;
+    implt.handle_event(event()).await.unwrap();
+    for ws in [&mut ws_a, &mut ws_b] {
+        assert!(matches!(ws.next().await, Some(Ok(Message::Text(_)))));
+    }
+
+    // 移除 a，a 上的连接关闭且不再接受新连接，b 不受影响
+    implt.reload_event_handler(config(&[b])).await.unwrap();
+    assert!(matches!(
+        ws_a.next().await,
+        Some(Ok(Message::Close(_))) | None
+    ));
+    assert!(
+        tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{}", a))
+            .await
+            .is_err()
+    );
+    implt.handle_event(event()).await.unwrap();
+    assert!(matches!(ws_b.next().await, Some(Ok(Message::Text(_)))));
+    implt.shutdown(true).await.unwrap();
+}
+
+#[cfg(feature = "file-store")]
+#[tokio::test]
+async fn fragmented_file() {
+    use crate::file::{Files, Fragmented, MemoryStore};
+
+    let ob = nop_onebot("", &[]);
+    let files = Arc::new(Files::new(MemoryStore::default()));
+    // 第 fail 个分片请求失败
+    let fail = Arc::new(AtomicUsize::new(3));
+    let (files0, fail0) = (files.clone(), fail.clone());
+    ob.layer_action(from_fn(
+        move |action: Action, next: Next<'_, Action, Resp>| {
+            let (files, fail) = (files0.clone(), fail0.clone());
+            Box::pin(async move {
+                if action.params.get("stage") == Some(&"transfer".into())
+                    && fail.fetch_sub(1, Ordering::SeqCst) == 1
+                {
+                    return Err(WalleError::Other("injected".to_string()));
+                }
+                match files.handle(action).await {
+                    Ok(resp) => Ok(resp),
+                    Err(action) => next.run(action).await,
+                }
+            })
+        },
+    ))
+    .unwrap();
+    let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+    let fragmented = Fragmented {
+        chunk_size: 1000,
+        parallel: 3,
+        ..Default::default()
+    };
+
+    let mut progress = fragmented
+        .prepare_upload(&ob, "a.bin".to_string(), data.len() as u64)
+        .await
+        .unwrap();
+    assert!(fragmented
+        .upload(&ob, &mut progress, &data[..])
+        .await
+        .is_err());
+    let offset = progress.offset as usize;
+    assert!(offset < data.len());
+    let file_id = fragmented
+        .upload(&ob, &mut progress, &data[offset..])
+        .await
+        .unwrap();
+
+    fail.store(2, Ordering::SeqCst);
+    let mut progress = fragmented
+        .prepare_download(&ob, file_id.clone())
+        .await
+        .unwrap();
+    let mut out = vec![];
+    assert!(fragmented
+        .download(&ob, &mut progress, &mut out)
+        .await
+        .is_err());
+    assert_eq!(out.len() as u64, progress.offset);
+    fragmented
+        .download(&ob, &mut progress, &mut out)
+        .await
+        .unwrap();
+    assert_eq!(out, data);
+
+    let dir = std::env::temp_dir().join(format!("walle-fragmented-{}", crate::util::new_uuid()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.bin"), &data).unwrap();
+    fail.store(5, Ordering::SeqCst);
+    let file_id = fragmented
+        .upload_path(&ob, dir.join("a.bin"))
+        .await
+        .unwrap();
+    fail.store(5, Ordering::SeqCst);
+    let head = fragmented
+        .download_path(&ob, file_id, dir.join("b.bin"))
+        .await
+        .unwrap();
+    assert_eq!(head.name, "a.bin");
+    assert_eq!(std::fs::read(dir.join("b.bin")).unwrap(), data);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "file-store")]
+#[tokio::test]
+async fn fragmented_retry() {
+    use crate::file::{Files, Fragmented, MemoryStore};
+    use crate::resp::resp_error;
+
+    let ob = nop_onebot("", &[]);
+    let files = Arc::new(Files::new(MemoryStore::default()));
+    let finishes = Arc::new(AtomicUsize::new(0));
+    // 拒绝首次 finish；篡改首个下载分片
+    let (reject, corrupt) = (Arc::new(AtomicUsize::new(1)), Arc::new(AtomicUsize::new(0)));
+    let (files0, finishes0, reject0, corrupt0) = (
+        files.clone(),
+        finishes.clone(),
+        reject.clone(),
+        corrupt.clone(),
+    );
+    ob.layer_action(from_fn(
+        move |action: Action, next: Next<'_, Action, Resp>| {
+            let (files, finishes, reject, corrupt) = (
+                files0.clone(),
+                finishes0.clone(),
+                reject0.clone(),
+                corrupt0.clone(),
+            );
+            Box::pin(async move {
+                let stage = action.params.get("stage").cloned();
+                if stage == Some("finish".into()) {
+                    finishes.fetch_add(1, Ordering::SeqCst);
+                    if reject.swap(0, Ordering::SeqCst) == 1 {
+                        return Ok(resp_error::bad_param("rejected").into());
+                    }
+                }
+                let download = action.action == "get_file_fragmented";
+                let mut resp = match files.handle(action).await {
+                    Ok(resp) => resp,
+                    Err(action) => return next.run(action).await,
+                };
+                if download
+                    && stage == Some("transfer".into())
+                    && corrupt.swap(0, Ordering::SeqCst) == 1
+                {
+                    if let Value::Map(map) = &mut resp.data {
+                        if let Some(Value::Bytes(data)) = map.get_mut("data") {
+                            data.0[0] ^= 0xff;
+                        }
+                    }
+                }
+                Ok(resp)
+            })
+        },
+    ))
+    .unwrap();
+    let data: Vec<u8> = (0..3_000u32).map(|i| i as u8).collect();
+    let fragmented = Fragmented {
+        chunk_size: 1000,
+        ..Default::default()
+    };
+    let dir = std::env::temp_dir().join(format!("walle-retry-{}", crate::util::new_uuid()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.bin"), &data).unwrap();
+
+    // 对端拒绝时不重试
+    assert!(matches!(
+        fragmented.upload_path(&ob, dir.join("a.bin")).await,
+        Err(WalleError::RespError(_))
+    ));
+    assert_eq!(finishes.load(Ordering::SeqCst), 1);
+    let file_id = fragmented
+        .upload_path(&ob, dir.join("a.bin"))
+        .await
+        .unwrap();
+
+    // sha256 不符时自 0 重新下载
+    corrupt.store(1, Ordering::SeqCst);
+    fragmented
+        .download_path(&ob, file_id, dir.join("b.bin"))
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(dir.join("b.bin")).unwrap(), data);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "impl-obc")]
+#[tokio::test]
+async fn event_lag_policy() {
+    use crate::config::{EventBuffer, LagPolicy};
+    use crate::obc::ImplOBC;
+
+    let ob = Arc::new(OneBot::new(
+        NopAH("a", vec![selft("1")]),
+        ImplOBC::<Event>::new("a".to_string()),
+    ));
+    let buffer = |lag| EventBuffer { capacity: 1, lag };
+    let event_with = |id: &str| Event {
+        id: id.to_string(),
+        ..event()
+    };
+
+    // Skip：缓冲已满时丢弃并计数，不影响已缓冲的 Event
+    let mut skip = ob
+        .event_handler()
+        .subscribers
+        .subscribe(&buffer(LagPolicy::Skip));
+    for id in ["0", "1", "2"] {
+        ob.handle_event(event_with(id)).await.unwrap();
+    }
+    assert_eq!(ob.event_handler().dropped_events(), 2);
+    assert_eq!(skip.recv().await.unwrap().id, "0");
+    ob.handle_event(event_with("3")).await.unwrap();
+    assert_eq!(skip.recv().await.unwrap().id, "3");
+    drop(skip);
+
+    // Block：缓冲已满时 handle_event 等待消费，不丢弃
+    let mut block = ob
+        .event_handler()
+        .subscribers
+        .subscribe(&buffer(LagPolicy::Block));
+    ob.handle_event(event_with("4")).await.unwrap();
+    let handle = ob.handle_event(event_with("5"));
+    tokio::pin!(handle);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), handle.as_mut())
+            .await
+            .is_err()
+    );
+    assert_eq!(block.recv().await.unwrap().id, "4");
+    handle.await.unwrap();
+    assert_eq!(block.recv().await.unwrap().id, "5");
+    assert_eq!(ob.event_handler().dropped_events(), 2);
+}
+
+#[cfg(all(feature = "app-obc", feature = "websocket"))]
+#[tokio::test]
+async fn app_content_negotiation() {
+    use crate::config::{AppConfig, WebSocketClient};
+    use crate::obc::AppOBC;
+    use crate::util::{ContentType, Echo, ProtocolItem};
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    // (应用端指定的编码, 实现端所用编码, 应用端发送 Action 的编码)
+    for (preferred, implt, expected) in [
+        (None, ContentType::Json, ContentType::Json),
+        (None, ContentType::MsgPack, ContentType::MsgPack),
+        (
+            Some(ContentType::MsgPack),
+            ContentType::Json,
+            ContentType::MsgPack,
+        ),
+        (
+            Some(ContentType::Json),
+            ContentType::MsgPack,
+            ContentType::Json,
+        ),
+    ] {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = Arc::new(OneBot::new(
+            AppOBC::<Action, Resp>::new(),
+            CountEH::default(),
+        ));
+        app.start(
+            AppConfig {
+                websocket: vec![WebSocketClient {
+                    url: format!("ws://127.0.0.1:{}", port),
+                    content_type: preferred,
+                    ..Default::default()
+                }],
+                websocket_rev: vec![],
+                ..Default::default()
+            },
+            (),
+            true,
+        )
+        .await
+        .unwrap();
+
+        // 以原始 WebSocket 连接模拟实现端
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let nop = NopAH("a", vec![selft("1")]);
+        for (detail_type, extra) in [
+            (
+                "connect",
+                crate::value_map! { "version": nop.get_version() },
+            ),
+            (
+                "status_update",
+                crate::value_map! { "status": nop.get_status().await },
+            ),
+        ] {
+            let event = Event {
+                detail_type: detail_type.to_string(),
+                extra,
+                ..event()
+            };
+            ws.send(event.to_ws_msg(&implt)).await.unwrap();
+        }
+        for _ in 0..50 {
+            if !app.get_bots().await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let call = app.handle_action::<Event, Action, Resp>(Action {
+            action: "get_version".to_string(),
+            params: Default::default(),
+            selft: Some(selft("1")),
+        });
+        let respond = async {
+            let msg = ws.next().await.unwrap().unwrap();
+            let action: Echo<Action> = match (&msg, expected) {
+                (Message::Text(text), ContentType::Json) => ProtocolItem::json_decode(text),
+                (Message::Binary(data), ContentType::MsgPack) => ProtocolItem::rmp_decode(data),
+                _ => panic!("unexpected frame {:?}, expected {}", msg, expected),
+            }
+            .unwrap();
+            let (action, echo) = action.unpack();
+            assert_eq!(action.action, "get_version");
+            ws.send(echo.pack(Resp::ok("a", "")).to_ws_msg(&implt))
+                .await
+                .unwrap();
+        };
+        let (resp, _) = tokio::join!(call, respond);
+        assert_eq!(resp.unwrap().data, Value::from("a"));
+        app.shutdown(true).await.unwrap();
+    }
+}
+
+#[cfg(all(feature = "impl-obc", feature = "websocket"))]
+#[tokio::test]
+async fn impl_ws_event_encoding() {
+    use crate::config::{ImplConfig, WebSocketServer};
+    use crate::obc::ImplOBC;
+    use crate::util::{ContentType, OneBotBytes, ProtocolItem};
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    let binary = |msg: Message| match msg {
+        Message::Binary(data) => data,
+        msg => panic!("expected binary frame, got {:?}", msg),
+    };
+    // (实现端指定的编码, 应用端发送 Action 所用编码)，预期 Event 均以 MessagePack 发送
+    for (preferred, action) in [
+        (Some(ContentType::MsgPack), None),
+        (None, Some(ContentType::MsgPack)),
+    ] {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let implt = Arc::new(OneBot::new(
+            NopAH("a", vec![selft("1")]),
+            ImplOBC::<Event>::new("a".to_string()),
+        ));
+        implt
+            .start(
+                (),
+                ImplConfig {
+                    websocket: vec![WebSocketServer {
+                        port,
+                        content_type: preferred,
+                        ..Default::default()
+                    }],
+                    websocket_rev: vec![],
+                    ..Default::default()
+                },
+                false,
+            )
+            .await
+            .unwrap();
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{}", port))
+            .await
+            .unwrap();
+
+        for detail_type in ["connect", "status_update"] {
+            let msg = ws.next().await.unwrap().unwrap();
+            let event = match preferred {
+                Some(_) => Event::rmp_decode(&binary(msg)),
+                None => match msg {
+                    Message::Text(text) => Event::json_decode(&text),
+                    msg => panic!("expected text frame, got {:?}", msg),
+                },
+            }
+            .unwrap();
+            assert_eq!(event.detail_type, detail_type);
+        }
+        if let Some(content_type) = action {
+            let action = crate::util::Echo {
+                inner: Action {
+                    action: "get_version".to_string(),
+                    params: Default::default(),
+                    selft: None,
+                },
+                echo: None,
+            };
+            ws.send(action.to_ws_msg(&content_type)).await.unwrap();
+            let resp: Resp =
+                ProtocolItem::rmp_decode(&binary(ws.next().await.unwrap().unwrap())).unwrap();
+            assert_eq!(resp.data, Value::from("a"));
+        }
+
+        let data = vec![0u8, 1, 2, 255];
+        implt
+            .handle_event(Event {
+                extra: crate::value_map! { "data": OneBotBytes(data.clone()) },
+                ..event()
+            })
+            .await
+            .unwrap();
+        let frame = binary(ws.next().await.unwrap().unwrap());
+        // bin 8 格式：0xc4、长度、原始 bytes，而非 base64 字符串
+        let raw = [&[0xc4, data.len() as u8][..], &data].concat();
+        assert!(frame.windows(raw.len()).any(|w| w == raw));
+        let event = Event::rmp_decode(&frame).unwrap();
+        assert_eq!(
+            event.extra.get("data"),
+            Some(&Value::Bytes(OneBotBytes(data)))
+        );
+        implt.shutdown(true).await.unwrap();
+    }
+}
+
+#[cfg(all(feature = "app-obc", feature = "impl-obc", feature = "websocket"))]
+#[tokio::test]
+async fn heartbeat_keeps_bot_online() {
+    use crate::config::{AppConfig, Heartbeat, ImplConfig, WebSocketClient, WebSocketServer};
+    use crate::obc::{AppOBC, ImplOBC};
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let implt = Arc::new(OneBot::new(
+        NopAH("a", vec![selft("1")]),
+        ImplOBC::<Event>::new("a".to_string()),
+    ));
+    implt
+        .start(
+            (),
+            ImplConfig {
+                websocket: vec![WebSocketServer {
+                    port,
+                    ..Default::default()
+                }],
+                websocket_rev: vec![],
+                heartbeat: Heartbeat {
+                    enabled: true,
+                    interval: 1,
+                },
+                ..Default::default()
+            },
+            false,
+        )
+        .await
+        .unwrap();
+    let app = Arc::new(OneBot::new(
+        AppOBC::<Action, Resp>::new(),
+        CountEH::default(),
+    ));
+    app.start(
+        AppConfig {
+            websocket: vec![WebSocketClient {
+                url: format!("ws://127.0.0.1:{}", port),
+                ..Default::default()
+            }],
+            websocket_rev: vec![],
+            ..Default::default()
+        },
+        (),
+        true,
+    )
+    .await
+    .unwrap();
+    let online = || async {
+        app.get_bots()
+            .await
+            .into_iter()
+            .any(|bot| bot.selft == selft("1") && bot.online)
+    };
+    for _ in 0..50 {
+        if online().await {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(online().await);
+    // 收到下一次心跳后，在两倍心跳间隔内仍视为在线
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(app
+        .action_handler()
+        .bots
+        .conns
+        .iter()
+        .all(|conn| conn.heartbeat.is_some()));
+    assert!(online().await);
+    app.shutdown(true).await.unwrap();
+    implt.shutdown(true).await.unwrap();
+}
@@ -1,6 +1,7 @@
+#[cfg(feature = "alt")]
+use crate::alt::ColoredAlt;
 use crate::{
     action::*,
-    alt::ColoredAlt,
     error::WalleError,
     event::*,
     segment::*,
@@ -12,6 +13,7 @@ use walle_macro::{
     _PushToValueMap as PushToValueMap, _ToEvent as ToEvent, _TryFromEvent as TryFromEvent,
 };
 mod event;
+mod handler;
 mod value;
 
 #[test]
@@ -26,6 +28,7 @@ fn event() {
             event.1
         );
         assert_eq!(T::try_from(event.1.clone()).unwrap(), event.2);
+        #[cfg(feature = "alt")]
         println!("{}", event.1.colored_alt());
     }
     #[derive(Debug, PushToValueMap, ToEvent, TryFromEvent)]
@@ -52,6 +55,9 @@ fn event() {
             sub_type: "".to_string(),
             extra: value_map! {
                 "interval": 5000,
+                "status": {
+                    "good": true
+                }
             },
         },
         new_event(
@@ -62,7 +68,11 @@ fn event() {
             (),
             (),
             (),
-            value_map!(),
+            value_map! {
+                "status": {
+                    "good": true
+                }
+            },
         ),
     ));
     test((
@@ -171,6 +181,7 @@ fn action() {
             action.1
         );
         assert_eq!(T::try_from(action.1.clone()).unwrap(), action.2);
+        #[cfg(feature = "alt")]
         println!("{}", action.1.colored_alt());
     }
 
@@ -298,6 +309,84 @@ fn segment() {
     ));
 }
 
+#[test]
+fn ext_segment() {
+    use crate::segment::ext::{Face, Node, Poke};
+    let face: MsgSegment = Face {
+        id: "1".to_string(),
+    }
+    .into();
+    assert_eq!(face.ty, "qq.face");
+    assert!(matches!(
+        face.try_as_ref().unwrap(),
+        MsgSegmentRef::Face { id: "1", .. }
+    ));
+    assert_eq!(Face::try_from(face).unwrap().id, "1");
+    assert!(Face::try_from(MsgSegment::from("face")).is_err());
+
+    let mut poke = MsgSegment::try_from(value!({"type": "qq.poke",
+        "data": {"type": 1, "id": 10000, "name": "戳一戳"}
+    }))
+    .unwrap();
+    assert!(matches!(
+        poke.try_as_ref().unwrap(),
+        MsgSegmentRef::Poke {
+            ty: 1,
+            id: 10000,
+            ..
+        }
+    ));
+    if let MsgSegmentMut::Poke { ty, id } = poke.try_as_mut().unwrap() {
+        assert_eq!((*ty, *id), (1, 10000));
+        *ty = 2;
+    } else {
+        panic!("poke should be MsgSegmentMut::Poke");
+    }
+    assert_eq!(
+        Poke::try_from(poke).unwrap(),
+        Poke {
+            ty: 2,
+            id: 10000,
+            name: Some("戳一戳".to_string())
+        }
+    );
+
+    let node = Node {
+        id: None,
+        user_id: Some("10000".to_string()),
+        nickname: Some("walle".to_string()),
+        content: Some(vec!["hello".into()]),
+    };
+    assert_eq!(
+        Node::try_from(MsgSegment::from(node.clone())).unwrap(),
+        node
+    );
+}
+
+#[test]
+fn ext_segment_ref() {
+    let mut face = MsgSegment {
+        ty: "qq.face".to_string(),
+        data: value_map! {"id": "1"},
+    };
+    assert!(matches!(
+        face.try_as_ref().unwrap(),
+        MsgSegmentRef::Face { id: "1", .. }
+    ));
+    if let MsgSegmentMut::Face { id } = face.try_as_mut().unwrap() {
+        *id = "2".to_string();
+    }
+    assert_eq!(face.data, value_map! {"id": "2"});
+    let mut node = MsgSegment {
+        ty: "qq.node".to_string(),
+        data: value_map! {"user_id": "10000"},
+    };
+    assert!(matches!(
+        node.try_as_mut().unwrap(),
+        MsgSegmentMut::Node { .. }
+    ));
+}
+
 #[test]
 fn valuemap_test() {
     let mut map = ValueMap::new();
@@ -354,3 +443,223 @@ fn option_action() {
         )
     )
 }
+
+#[test]
+fn prefixed_derive() {
+    use walle_macro::{_ToAction as ToAction, _TryFromAction as TryFromAction};
+    #[derive(Debug, PartialEq, Eq, TryFromAction, ToAction, PushToValueMap)]
+    #[action(platform = "qq")]
+    struct SetGroupCard {
+        group_id: String,
+        card: String,
+    }
+
+    assert_eq!(
+        <SetGroupCard as crate::action::ToAction>::PREFIX,
+        Some("qq")
+    );
+    assert_eq!(<GetUserInfo as crate::action::ToAction>::PREFIX, None);
+    let action: Action = SetGroupCard {
+        group_id: "1".to_string(),
+        card: "walle".to_string(),
+    }
+    .into();
+    assert_eq!(action.action, "qq.set_group_card");
+    assert!(SetGroupCard::try_from(action).is_ok());
+    assert!(SetGroupCard::try_from(Action {
+        action: "set_group_card".to_string(),
+        selft: None,
+        params: value_map! {"group_id": "1", "card": "walle"},
+    })
+    .is_err());
+
+    #[derive(Debug, PartialEq, Eq, PushToValueMap, ToEvent, TryFromEvent)]
+    #[event(detail_type, prefix = "qq")]
+    struct Poke {
+        user_id: String,
+    }
+
+    assert_eq!(
+        <Poke as ToEvent<DetailTypeLevel>>::ty(&Poke {
+            user_id: "1".to_string()
+        }),
+        "qq.poke"
+    );
+    let mut event = Event {
+        id: "".to_string(),
+        time: 0.0,
+        ty: "notice".to_string(),
+        detail_type: "qq.poke".to_string(),
+        sub_type: "".to_string(),
+        extra: value_map! {"user_id": "1"},
+    };
+    assert!(
+        <Poke as TryFromEvent<DetailTypeLevel>>::try_from_event_mut(&mut event.clone(), "").is_ok()
+    );
+
+    #[derive(Debug, PartialEq, Eq, PushToValueMap, ToEvent, TryFromEvent)]
+    #[event(platform = "qq", detail_type = "poke")]
+    struct QqPoke {
+        user_id: String,
+    }
+
+    assert_eq!(<QqPoke as ToEvent<DetailTypeLevel>>::PREFIX, Some("qq"));
+    let poke = QqPoke {
+        user_id: "1".to_string(),
+    };
+    assert_eq!(<QqPoke as ToEvent<PlatformLevel>>::ty(&poke), "qq");
+    assert_eq!(<QqPoke as ToEvent<DetailTypeLevel>>::ty(&poke), "qq.poke");
+    assert!(
+        <QqPoke as TryFromEvent<DetailTypeLevel>>::try_from_event_mut(&mut event.clone(), "")
+            .is_ok()
+    );
+
+    #[derive(Debug, PartialEq, Eq, PushToValueMap, ToEvent, TryFromEvent)]
+    #[event(detail_type, prefix = "qq")]
+    enum QqNotice {
+        Poke { user_id: String },
+    }
+
+    let notice = QqNotice::Poke {
+        user_id: "1".to_string(),
+    };
+    assert_eq!(
+        <QqNotice as ToEvent<DetailTypeLevel>>::ty(&notice),
+        "qq.poke"
+    );
+    assert_eq!(
+        <QqNotice as TryFromEvent<DetailTypeLevel>>::try_from_event_mut(&mut event, "").unwrap(),
+        notice
+    );
+}
+
+#[test]
+fn field_attr() {
+    use walle_macro::{
+        _ToAction as ToAction, _TryFromAction as TryFromAction, _TryFromValue as TryFromValue,
+    };
+    #[derive(Debug, Default, PartialEq, Eq, TryFromValue, PushToValueMap)]
+    struct Sender {
+        #[value(rename = "id")]
+        user_id: String,
+        nickname: String,
+    }
+
+    #[derive(Debug, PartialEq, TryFromAction, ToAction, PushToValueMap)]
+    struct SetProfile {
+        #[value(default)]
+        count: i64,
+        #[value(skip)]
+        cache: Vec<String>,
+        #[value(flatten)]
+        sender: Sender,
+        #[value(flatten)]
+        extra: ValueMap,
+    }
+
+    let action = Action {
+        action: "set_profile".to_string(),
+        selft: None,
+        params: value_map! {"id": "1", "nickname": "walle", "cache": ["x"], "age": 1},
+    };
+    let profile = SetProfile::try_from(action.clone()).unwrap();
+    assert_eq!(
+        profile,
+        SetProfile {
+            count: 0,
+            cache: vec![],
+            sender: Sender {
+                user_id: "1".to_string(),
+                nickname: "walle".to_string(),
+            },
+            extra: value_map! {"cache": ["x"], "age": 1},
+        }
+    );
+    let map = ValueMap::from(profile);
+    assert_eq!(
+        map,
+        value_map! {"count": 0, "id": "1", "nickname": "walle", "cache": ["x"], "age": 1}
+    );
+}
+
+#[test]
+fn tuple_enum() {
+    use walle_macro::{
+        _ToAction as ToAction, _ToMsgSegment as ToMsgSegment, _TryFromAction as TryFromAction,
+        _TryFromMsgSegment as TryFromMsgSegment,
+    };
+    #[derive(Debug, PartialEq, TryFromAction, ToAction, PushToValueMap)]
+    enum MyActions {
+        #[action(delegate)]
+        Delete(DeleteMessage),
+        #[action(delegate)]
+        Info(GetUserInfo),
+        Ping,
+    }
+
+    let action: Action = MyActions::Delete(DeleteMessage {
+        message_id: "1".to_string(),
+    })
+    .into();
+    assert_eq!(action.action, "delete_message");
+    assert_eq!(
+        MyActions::try_from(action).unwrap(),
+        MyActions::Delete(DeleteMessage {
+            message_id: "1".to_string()
+        })
+    );
+    let info = Action {
+        action: "get_user_info".to_string(),
+        selft: None,
+        params: value_map! {"user_id": "1"},
+    };
+    assert!(matches!(MyActions::try_from(info), Ok(MyActions::Info(_))));
+    let missed = Action {
+        action: "get_user_info".to_string(),
+        selft: None,
+        params: value_map! {},
+    };
+    assert!(matches!(
+        MyActions::try_from(missed),
+        Err(WalleError::MapMissedKey(_))
+    ));
+    assert!(matches!(
+        MyActions::try_from(Action {
+            action: "unknown".to_string(),
+            selft: None,
+            params: value_map! {},
+        }),
+        Err(WalleError::DeclareNotMatch(..))
+    ));
+
+    #[derive(Debug, PartialEq, TryFromMsgSegment, ToMsgSegment, PushToValueMap)]
+    enum MySegments {
+        #[msg_segment(delegate)]
+        Plain(Text),
+        #[msg_segment(delegate)]
+        At(Mention),
+    }
+
+    let segment: MsgSegment = MySegments::At(Mention {
+        user_id: "1".to_string(),
+    })
+    .into();
+    assert_eq!(segment.ty, "mention");
+    assert!(matches!(
+        MySegments::try_from(segment),
+        Ok(MySegments::At(_))
+    ));
+
+    // 未标注 delegate 时按变体名称分派
+    #[derive(Debug, PartialEq, TryFromAction, ToAction, PushToValueMap)]
+    enum Named {
+        UserInfo(GetUserInfo),
+    }
+
+    let action: Action = Named::UserInfo(GetUserInfo {
+        user_id: "1".to_string(),
+    })
+    .into();
+    assert_eq!(action.action, "user_info");
+    assert!(matches!(Named::try_from(action), Ok(Named::UserInfo(_))));
+}
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use crate::{
     prelude::WalleError,
     util::{PushToValueMap, Value, ValueMap, ValueMapExt},
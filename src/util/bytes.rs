@@ -1,5 +1,13 @@
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use serde::{de::Visitor, Deserialize, Serialize};
 
+/// OneBot 字节数据
+///
+/// 序列化时根据 `Serializer::is_human_readable` 选择编码：文本格式（JSON、TOML、YAML 等）
+/// 使用 base64 字符串，二进制格式（MessagePack 等）使用原始 bytes。
+/// 需要固定编码时，可对字段使用 `#[serde(with = "as_base64")]` 或 `#[serde(with = "as_raw")]`
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct OneBotBytes(pub Vec<u8>);
 
@@ -8,10 +16,54 @@ impl Serialize for OneBotBytes {
     where
         S: serde::Serializer,
     {
-        if std::any::type_name::<S>().starts_with("&mut serde_json") {
-            return serializer.serialize_str(&base64::encode(&self.0));
+        if serializer.is_human_readable() {
+            as_base64::serialize(self, serializer)
+        } else {
+            as_raw::serialize(self, serializer)
         }
-        serializer.serialize_bytes(&self.0)
+    }
+}
+
+/// 解码 base64 字符串，兼容 `base64://` 前缀
+pub fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    BASE64.decode(s.strip_prefix("base64://").unwrap_or(s)).ok()
+}
+
+/// 固定以 base64 字符串序列化，用于 `#[serde(with = "as_base64")]`
+pub mod as_base64 {
+    use super::*;
+
+    pub fn serialize<S>(bytes: &OneBotBytes, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&BASE64.encode(&bytes.0))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OneBotBytes, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        OneBotBytes::deserialize(deserializer)
+    }
+}
+
+/// 固定以原始 bytes 序列化，用于 `#[serde(with = "as_raw")]`
+pub mod as_raw {
+    use super::*;
+
+    pub fn serialize<S>(bytes: &OneBotBytes, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_bytes(&bytes.0)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OneBotBytes, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        OneBotBytes::deserialize(deserializer)
     }
 }
 
@@ -39,9 +91,9 @@ impl<'de> Visitor<'de> for OBBVistor {
     where
         E: serde::de::Error,
     {
-        Ok(OneBotBytes(base64::decode(v).map_err(|_| {
-            serde::de::Error::custom("Not a valid base64 String")
-        })?))
+        decode_base64(v)
+            .map(OneBotBytes)
+            .ok_or_else(|| serde::de::Error::custom("Not a valid base64 String"))
     }
 
     fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
@@ -57,6 +109,18 @@ impl<'de> Visitor<'de> for OBBVistor {
     {
         Ok(OneBotBytes(v))
     }
+
+    // 不支持 bytes 的格式（如 toml）将 bytes 序列化为整数数组
+    fn visit_seq<S>(self, mut seq: S) -> Result<Self::Value, S::Error>
+    where
+        S: serde::de::SeqAccess<'de>,
+    {
+        let mut v = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(b) = seq.next_element::<u8>()? {
+            v.push(b);
+        }
+        Ok(OneBotBytes(v))
+    }
 }
 
 impl<'de> Deserialize<'de> for OneBotBytes {
@@ -68,21 +132,153 @@ impl<'de> Deserialize<'de> for OneBotBytes {
     }
 }
 
+/// OneBot 文件来源，可由字符串的 `base64://`、`http(s)://`、`file://` 形式反序列化
+///
+/// 无前缀的字符串按 base64 处理
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BytesSource {
+    Bytes(OneBotBytes),
+    Url(String),
+    Path(PathBuf),
+}
+
+impl From<OneBotBytes> for BytesSource {
+    fn from(v: OneBotBytes) -> Self {
+        Self::Bytes(v)
+    }
+}
+
+impl Serialize for BytesSource {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Self::Bytes(b) if serializer.is_human_readable() => {
+                serializer.serialize_str(&format!("base64://{}", BASE64.encode(&b.0)))
+            }
+            Self::Bytes(b) => as_raw::serialize(b, serializer),
+            Self::Url(url) => serializer.serialize_str(url),
+            Self::Path(path) => {
+                serializer.serialize_str(&format!("file://{}", path.to_string_lossy()))
+            }
+        }
+    }
+}
+
+struct SourceVistor;
+
+impl<'de> Visitor<'de> for SourceVistor {
+    type Value = BytesSource;
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("expect base64, url, file path or msgpack bytes")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        if v.starts_with("http://") || v.starts_with("https://") {
+            Ok(BytesSource::Url(v.to_owned()))
+        } else if let Some(path) = v.strip_prefix("file://") {
+            Ok(BytesSource::Path(PathBuf::from(path)))
+        } else {
+            OBBVistor.visit_str(v).map(BytesSource::Bytes)
+        }
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        OBBVistor.visit_bytes(v).map(BytesSource::Bytes)
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        OBBVistor.visit_byte_buf(v).map(BytesSource::Bytes)
+    }
+}
+
+impl<'de> Deserialize<'de> for BytesSource {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(SourceVistor)
+    }
+}
+
 #[test]
 fn sertest() {
     let bytes = OneBotBytes(vec![0, 1, 2, 3]);
     assert_eq!("\"AAECAw==\"", &serde_json::to_string(&bytes).unwrap());
     assert_eq!(vec![196, 4, 0, 1, 2, 3], rmp_serde::to_vec(&bytes).unwrap());
+    assert_eq!(
+        serde_json::Value::String("AAECAw==".to_string()),
+        serde_json::to_value(&bytes).unwrap()
+    );
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Wrap {
+        data: OneBotBytes,
+        #[serde(with = "as_raw")]
+        raw: OneBotBytes,
+    }
+    let wrap = Wrap {
+        data: bytes.clone(),
+        raw: bytes,
+    };
+    let toml = toml::to_string(&wrap).unwrap();
+    assert_eq!("data = \"AAECAw==\"\nraw = [0, 1, 2, 3]\n", toml);
+    assert_eq!(wrap, toml::from_str::<Wrap>(&toml).unwrap());
+    let json = serde_json::to_string(&wrap).unwrap();
+    assert_eq!(wrap, serde_json::from_str::<Wrap>(&json).unwrap());
 }
 
 #[test]
 fn detest() {
     let bytes = OneBotBytes(vec![0, 1, 2, 3]);
     let json = "\"AAECAw==\"";
-    assert_eq!(bytes, serde_json::from_str::<OneBotBytes>(&json).unwrap());
+    assert_eq!(bytes, serde_json::from_str::<OneBotBytes>(json).unwrap());
     let msgpack = vec![196, 4, 0, 1, 2, 3];
     assert_eq!(
         bytes,
         rmp_serde::from_slice::<OneBotBytes>(&msgpack).unwrap()
     );
 }
+
+#[test]
+fn source_test() {
+    let bytes = OneBotBytes(vec![0, 1, 2, 3]);
+    assert_eq!(
+        bytes,
+        serde_json::from_str::<OneBotBytes>("\"base64://AAECAw==\"").unwrap()
+    );
+    for (s, source) in [
+        ("\"base64://AAECAw==\"", BytesSource::Bytes(bytes.clone())),
+        (
+            "\"https://example.com/a.png\"",
+            BytesSource::Url("https://example.com/a.png".to_string()),
+        ),
+        (
+            "\"file:///tmp/a.png\"",
+            BytesSource::Path(PathBuf::from("/tmp/a.png")),
+        ),
+    ] {
+        assert_eq!(source, serde_json::from_str::<BytesSource>(s).unwrap());
+        assert_eq!(s, serde_json::to_string(&source).unwrap());
+    }
+    assert_eq!(
+        BytesSource::Bytes(bytes.clone()),
+        serde_json::from_str::<BytesSource>("\"AAECAw==\"").unwrap()
+    );
+    let msgpack = rmp_serde::to_vec(&BytesSource::Bytes(bytes.clone())).unwrap();
+    assert_eq!(vec![196, 4, 0, 1, 2, 3], msgpack);
+    assert_eq!(
+        BytesSource::Bytes(bytes),
+        rmp_serde::from_slice::<BytesSource>(&msgpack).unwrap()
+    );
+}
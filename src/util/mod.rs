@@ -22,7 +22,7 @@ pub fn timestamp_nano_f64() -> f64 {
     timestamp_nano() as f64 / 1_000_000_000.0
 }
 
-#[cfg(feature = "impl-obc")]
+#[cfg(any(feature = "impl-obc", feature = "v11", feature = "file-store"))]
 pub fn new_uuid() -> String {
     uuid::Uuid::from_u128(timestamp_nano()).to_string()
 }
@@ -31,6 +31,25 @@ pub trait GetSelf: Sized {
     fn get_self(&self) -> Selft;
 }
 
+/// 按名称区分 Action，用于按 Action 名称设置超时
+pub trait ActionName {
+    /// 能否按名称区分，为 false 时拒绝按 Action 名称的超时设置
+    const NAMED: bool = true;
+    fn action_name(&self) -> Option<&str>;
+}
+
+impl ActionName for Value {
+    fn action_name(&self) -> Option<&str> {
+        match self {
+            Value::Map(map) => match map.get("action") {
+                Some(Value::Str(action)) => Some(action),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
 #[doc(hidden)]
 pub trait ProtocolItem:
     Serialize + for<'de> Deserialize<'de> + Debug + Send + Sync + 'static
@@ -80,8 +99,10 @@ impl<T> ProtocolItem for T where
 /// Onebot 协议支持的数据编码格式
 ///
 /// Json or MessagePack
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ContentType {
+    #[default]
     Json,
     MsgPack,
 }
@@ -106,6 +127,7 @@ impl std::fmt::Display for ContentType {
     }
 }
 
+#[cfg(any(feature = "websocket", feature = "http"))]
 pub(crate) trait AuthReqHeaderExt {
     fn header_auth_token(self, token: &Option<String>) -> Self;
 }
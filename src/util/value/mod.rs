@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use super::{OneBotBytes, TryAsMut, TryAsRef};
+use super::{decode_base64, OneBotBytes, TryAsMut, TryAsRef};
 use crate::error::{WalleError, WalleResult};
 
 /// 扩展字段 Map
@@ -211,9 +211,9 @@ impl TryFrom<Value> for OneBotBytes {
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
             Value::Bytes(v) => Ok(v),
-            Value::Str(s) => Ok(OneBotBytes(
-                base64::decode(&s).map_err(|_| WalleError::IllegalBase64(s))?,
-            )),
+            Value::Str(s) => decode_base64(&s)
+                .map(OneBotBytes)
+                .ok_or(WalleError::IllegalBase64(s)),
             v => Err(WalleError::ValueTypeNotMatch(
                 "bytes".to_string(),
                 format!("{:?}", v),
@@ -4,29 +4,36 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::string::String as ArcStr;
 
+/// v11 动作请求，echo 由 `Echo` 包装
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Request {
     // 请求 API 端点
-    action: ArcStr,
+    pub action: ArcStr,
     // 请求参数
-    params: Value,
-    // 回显字段
-    echo: ArcStr,
+    pub params: Value,
 }
 
+impl crate::util::ActionName for Request {
+    fn action_name(&self) -> Option<&str> {
+        Some(&self.action)
+    }
+}
+
+/// v11 动作响应，echo 由 `Echo` 包装
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Response {
     // 状态，ok 为成功，其他将在下文中详细说明
-    status: ArcStr,
+    pub status: ArcStr,
     // 返回码，0 为成功，非 0 为失败
-    retcode: u32,
+    pub retcode: u32,
     // 错误信息，仅在 API 调用失败时出现
-    msg: ArcStr,
+    #[serde(default)]
+    pub msg: Option<ArcStr>,
     // 对错误信息的描述，仅在 API 调用失败时出现
-    wording: ArcStr,
-    data: HashMap<ArcStr, Value>,
-    // 回显字段
-    echo: ArcStr,
+    #[serde(default)]
+    pub wording: Option<ArcStr>,
+    #[serde(default)]
+    pub data: Value,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -38,6 +45,9 @@ pub struct V11Event {
     // 上报数据
     #[serde(flatten)]
     pub post_type: Post,
+    // 无法映射到 v11 的额外字段
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub extra: HashMap<ArcStr, Value>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -46,11 +56,11 @@ pub enum Post {
     #[serde(rename = "message")]
     Message(MessageEvent),
     #[serde(rename = "message_sent")]
-    MessageSent(HashMap<ArcStr, Value>),
+    MessageSent(MessageEvent),
     #[serde(rename = "notice")]
-    Notice(HashMap<ArcStr, Value>),
+    Notice(NoticeEvent),
     #[serde(rename = "request")]
-    Request(HashMap<ArcStr, Value>),
+    Request(RequestEvent),
     #[serde(rename = "meta_event")]
     Meta(MetaEvent),
 }
@@ -61,11 +71,19 @@ pub enum MetaEvent {
     #[serde(rename = "heartbeat")]
     HeartBeatEvent {
         interval: u32,
+        #[serde(default, skip_serializing_if = "ArcStr::is_empty")]
         sub_type: ArcStr,
         status: Status,
     },
     #[serde(rename = "lifecycle")]
-    LifecycleEvent { sub_type: ArcStr, status: Status },
+    LifecycleEvent {
+        sub_type: ArcStr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<Status>,
+    },
+    /// 其他元事件，如由 v12 转换的 status_update 与 version
+    #[serde(untagged)]
+    Other(HashMap<ArcStr, Value>),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -76,26 +94,112 @@ pub enum MessageEvent {
         sub_type: V11MsgSubType, // (消息子类型)[https://whitechi73.github.io/OpenShamrock/event/general-data.html#messagesubtype] normal/friend/...
         message_id: i64,         // 消息 ID
         user_id: i64,            // 发送者 QQ 号
+        #[serde(deserialize_with = "crate::cq::deserialize_message")]
         message: Vec<V11MsgSegment>, // 消息内容
         raw_message: ArcStr,     // CQ 码格式消息
         sender: V11Sender,
         target_id: Option<i64>,   // 消息目标（私聊）
         temp_source: Option<i32>, // 临时聊天来源（私聊）
-        peer_id: i64,             // 消息接收者，群聊是群号，私聊时是目标QQ
+        #[serde(default)]
+        peer_id: i64, // 消息接收者，群聊是群号，私聊时是目标QQ
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        font: Option<i32>, // 字体
     },
     #[serde(rename = "group")]
     GroupMessage {
         sub_type: V11MsgSubType, // (消息子类型)[https://whitechi73.github.io/OpenShamrock/event/general-data.html#messagesubtype] normal/friend/...
         message_id: i64,         // 消息 ID
         user_id: i64,            // 发送者 QQ 号
+        #[serde(deserialize_with = "crate::cq::deserialize_message")]
         message: Vec<V11MsgSegment>, // 消息内容
         raw_message: ArcStr,     // CQ 码格式消息
         sender: V11Sender,
         group_id: Option<i64>,    // 群号
         target_id: Option<i64>,   // 消息目标（私聊）
         temp_source: Option<i32>, // 临时聊天来源（私聊）
-        peer_id: i64,             // 消息接收者，群聊是群号，私聊时是目标QQ
+        #[serde(default)]
+        peer_id: i64, // 消息接收者，群聊是群号，私聊时是目标QQ
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        font: Option<i32>, // 字体
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        anonymous: Option<Value>, // 匿名信息，非匿名消息为 null
+    },
+}
+
+/// https://github.com/botuniverse/onebot-11/blob/master/event/notice.md
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "notice_type", rename_all = "snake_case")]
+pub enum NoticeEvent {
+    /// 群文件上传
+    GroupUpload {
+        group_id: i64,
+        user_id: i64,
+        file: Value,
+    },
+    /// 群管理员变动 set/unset
+    GroupAdmin {
+        sub_type: ArcStr,
+        group_id: i64,
+        user_id: i64,
+    },
+    /// 群成员减少 leave/kick/kick_me
+    GroupDecrease {
+        sub_type: ArcStr,
+        group_id: i64,
+        operator_id: i64,
+        user_id: i64,
+    },
+    /// 群成员增加 approve/invite
+    GroupIncrease {
+        sub_type: ArcStr,
+        group_id: i64,
+        operator_id: i64,
+        user_id: i64,
+    },
+    /// 群禁言 ban/lift_ban
+    GroupBan {
+        sub_type: ArcStr,
+        group_id: i64,
+        operator_id: i64,
+        user_id: i64,
+        duration: i64,
+    },
+    /// 好友添加
+    FriendAdd { user_id: i64 },
+    /// 群消息撤回
+    GroupRecall {
+        group_id: i64,
+        user_id: i64,
+        operator_id: i64,
+        message_id: i64,
+    },
+    /// 好友消息撤回
+    FriendRecall { user_id: i64, message_id: i64 },
+    /// 其他通知，如 notify 等
+    #[serde(untagged)]
+    Other(HashMap<ArcStr, Value>),
+}
+
+/// https://github.com/botuniverse/onebot-11/blob/master/event/request.md
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request_type", rename_all = "snake_case")]
+pub enum RequestEvent {
+    /// 加好友请求
+    Friend {
+        user_id: i64,
+        comment: ArcStr,
+        flag: ArcStr,
     },
+    /// 加群请求 add/invite
+    Group {
+        sub_type: ArcStr,
+        group_id: i64,
+        user_id: i64,
+        comment: ArcStr,
+        flag: ArcStr,
+    },
+    #[serde(untagged)]
+    Other(HashMap<ArcStr, Value>),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -111,29 +215,106 @@ pub enum V11MsgSegment {
         /// 图片文件地址 file:// or http(s):// or base64://
         file: ArcStr,
         /// 图片类型, 分为show, flash, original
-        #[serde(rename = "type")]
-        ty: ArcStr,
+        #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+        ty: Option<ArcStr>,
         /// 图片链接地址
-        url: ArcStr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<ArcStr>,
     },
     #[serde(rename = "at")]
     At { qq: ArcStr },
     #[serde(rename = "poke")]
     Poke {
-        #[serde(rename = "type")]
+        #[serde(rename = "type", deserialize_with = "crate::cq::deserialize_number")]
         ty: i32,
+        #[serde(deserialize_with = "crate::cq::deserialize_number")]
         id: i32,
         name: Option<ArcStr>,
     },
     #[serde(rename = "reply")]
-    Reply { id: i32 },
+    Reply {
+        #[serde(deserialize_with = "crate::cq::deserialize_number")]
+        id: i64,
+    },
+    #[serde(rename = "record")]
+    Record {
+        /// 语音文件地址 file:// or http(s):// or base64://
+        file: ArcStr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<ArcStr>,
+    },
+    #[serde(rename = "video")]
+    Video {
+        /// 视频文件地址 file:// or http(s):// or base64://
+        file: ArcStr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<ArcStr>,
+    },
+    #[serde(rename = "share")]
+    Share {
+        url: ArcStr,
+        title: ArcStr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<ArcStr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        image: Option<ArcStr>,
+    },
+    #[serde(rename = "music")]
+    Music {
+        /// qq, 163, xm 或 custom
+        #[serde(rename = "type")]
+        ty: ArcStr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<ArcStr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<ArcStr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        audio: Option<ArcStr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<ArcStr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<ArcStr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        image: Option<ArcStr>,
+    },
+    #[serde(rename = "json")]
+    Json { data: ArcStr },
+    #[serde(rename = "xml")]
+    Xml { data: ArcStr },
+    /// 合并转发
+    #[serde(rename = "forward")]
+    Forward { id: ArcStr },
+    /// 合并转发节点，引用消息时仅有 id
+    #[serde(rename = "node")]
+    Node {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<ArcStr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user_id: Option<ArcStr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nickname: Option<ArcStr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<Value>,
+    },
+    /// 未知或字段不符的消息段
+    #[serde(untagged)]
+    Unknown {
+        #[serde(rename = "type")]
+        ty: ArcStr,
+        #[serde(default)]
+        data: Value,
+    },
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct V11Sender {
     /// 发送者 QQ 号
-    pub user_id: u32,
+    pub user_id: i64,
     /// 发送者昵称
+    #[serde(default)]
     pub nickname: ArcStr,
+    /// 群名片、角色等其余字段
+    #[serde(flatten)]
+    pub extra: HashMap<ArcStr, Value>,
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -146,6 +327,8 @@ pub enum V11MsgSubType {
     Group,
     /// 群消息(自身操作)
     GroupSelf,
+    /// 匿名群消息
+    Anonymous,
     /// 系统提示
     Notice,
 }
@@ -162,13 +345,13 @@ pub enum V11MsgType {
 pub struct Status {
     pub good: bool,
     pub online: bool,
-    #[serde(rename = "qq.status")]
-    pub qq_status: ArcStr,
-    #[serde(rename = "self")]
-    pub bot_self: BotSelf,
+    #[serde(rename = "qq.status", default, skip_serializing_if = "Option::is_none")]
+    pub qq_status: Option<ArcStr>,
+    #[serde(rename = "self", default, skip_serializing_if = "Option::is_none")]
+    pub bot_self: Option<BotSelf>,
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BotSelf {
     pub platform: ArcStr,
-    pub user_id: u32,
+    pub user_id: i64,
 }
@@ -1,8 +1,10 @@
 use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
-use syn::{Attribute, Data, DeriveInput, Fields, Ident, Lit, Meta, NestedMeta, Result};
+use syn::{
+    Attribute, Data, DeriveInput, Fields, Ident, Lit, Meta, MetaNameValue, NestedMeta, Result,
+};
 
-use crate::fields_from_map;
+use crate::{error, fields_from_map};
 
 use super::snake_case;
 
@@ -43,11 +45,14 @@ pub(crate) fn to_action_internal(input: DeriveInput, span: TokenStream2) -> Resu
 
 fn to_internal(input: DeriveInput, trait_name: TokenStream2, ty: &str) -> Result<TokenStream2> {
     let name = input.ident;
+    let attr = attrs_parse(&input.attrs, ty)?;
+    let prefix = attr.prefix_const();
     match input.data {
         Data::Struct(_) | Data::Union(_) => {
-            let s = attrs_parse(&name, &input.attrs, ty)?;
+            let s = attr.name(&name);
             Ok(quote!(
                 impl #trait_name for #name {
+                    #prefix
                     fn ty(&self) -> &'static str {
                         #s
                     }
@@ -61,15 +66,20 @@ fn to_internal(input: DeriveInput, trait_name: TokenStream2, ty: &str) -> Result
                 .map(|v| {
                     let vname = v.ident;
                     // todo attr
-                    let s = snake_case(vname.to_string());
-                    match v.fields {
+                    let s = prefixed(&attr.prefix, snake_case(vname.to_string()));
+                    if delegate_parse(&v.attrs, ty, &v.fields)? {
+                        // 使用内部类型的名称
+                        return Ok(quote!(Self::#vname (inner) => #trait_name::ty(inner)));
+                    }
+                    Ok(match v.fields {
                         Fields::Named(_) => quote!(Self::#vname {..} => #s),
                         Fields::Unnamed(_) => quote!(Self::#vname (..) => #s),
                         Fields::Unit => quote!(Self::#vname => #s),
-                    }
+                    })
                 })
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>>>()?;
             Ok(quote!(impl #trait_name for #name {
+                #prefix
                 fn ty(&self) -> &'static str {
                     match self {
                         #(#v,)*
@@ -97,11 +107,14 @@ pub(crate) fn try_from_msg_segment_internal(
     try_from_internal(
         input,
         quote!(#span::segment::TryFromMsgSegment),
-        quote!(try_from_msg_segment_mut(segment: &mut #span::segment::MsgSegment)),
+        (
+            quote!(try_from_msg_segment_mut(segment: &mut #span::segment::MsgSegment)),
+            quote!(try_from_msg_segment_mut(segment)),
+        ),
         quote!(segment.ty),
         quote!(segment.data),
         span,
-        "msg_sgement",
+        "msg_segment",
     )
     .map(|mut s| {
         s.extend(extra);
@@ -126,11 +139,14 @@ pub(crate) fn try_from_action_internal(
     try_from_internal(
         input,
         quote!(#span::action::TryFromAction),
-        quote!(try_from_action_mut(action: &mut #span::action::Action)),
+        (
+            quote!(try_from_action_mut(action: &mut #span::action::Action)),
+            quote!(try_from_action_mut(action)),
+        ),
         quote!(action.action),
         quote!(action.params),
         span,
-        "msg_sgement",
+        "action",
     )
     .map(|mut s| {
         s.extend(extra);
@@ -141,16 +157,17 @@ pub(crate) fn try_from_action_internal(
 fn try_from_internal(
     input: DeriveInput,
     trait_name: TokenStream2,
-    f: TokenStream2,
+    (f, call): (TokenStream2, TokenStream2),
     field: TokenStream2,
     map: TokenStream2,
     span: TokenStream2,
     ty: &str,
 ) -> Result<TokenStream2> {
     let name = input.ident;
+    let attr = attrs_parse(&input.attrs, ty)?;
     match input.data {
         Data::Union(_) => {
-            let s = attrs_parse(&name, &input.attrs, ty)?;
+            let s = attr.name(&name);
             Ok(quote!(impl #trait_name for #name {
                 fn #f -> #span::WalleResult<Self> {
                     if #field.as_str() == #s {
@@ -162,8 +179,8 @@ fn try_from_internal(
             }))
         }
         Data::Struct(data) => {
-            let s = attrs_parse(&name, &input.attrs, ty)?;
-            let fs = fields_from_map(&data.fields);
+            let s = attr.name(&name);
+            let fs = fields_from_map(&data.fields)?;
             Ok(quote!(impl #trait_name for #name {
                 fn #f -> #span::WalleResult<Self> {
                     use #span::util::value::ValueMapExt;
@@ -178,27 +195,45 @@ fn try_from_internal(
         }
         Data::Enum(data) => {
             let mut ss = Vec::new();
-            let v = data
-                .variants
-                .into_iter()
-                .map(|v| {
-                    let vname = v.ident;
-                    // todo attr
-                    let s = snake_case(vname.to_string());
-                    ss.push(s.clone());
-                    let fs = fields_from_map(&v.fields);
-                    quote!(#s => Ok(Self::#vname #fs))
-                })
-                .collect::<Vec<_>>();
+            let mut v = Vec::new();
+            let mut delegates = Vec::new();
+            for var in data.variants {
+                let vname = var.ident;
+                let delegate = delegate_parse(&var.attrs, ty, &var.fields)?;
+                match var.fields {
+                    // 交由内部类型解析，名称不匹配时尝试下一个
+                    Fields::Unnamed(f) if delegate => {
+                        let t = &f.unnamed.first().unwrap().ty;
+                        ss.push(quote!(#t).to_string());
+                        delegates.push(quote!(
+                            match <#t as #trait_name>::#call {
+                                Err(#span::WalleError::DeclareNotMatch(..)) => {}
+                                r => return r.map(Self::#vname),
+                            }
+                        ));
+                    }
+                    fields => {
+                        // todo attr
+                        let s = prefixed(&attr.prefix, snake_case(vname.to_string()));
+                        ss.push(s.clone());
+                        let fs = fields_from_map(&fields)?;
+                        v.push(quote!(#s => Ok(Self::#vname #fs)));
+                    }
+                }
+            }
             let ss = ss.join("|");
             Ok(quote!(
                 impl #trait_name for #name {
                     fn #f -> #span::WalleResult<Self> {
                         use #span::util::value::ValueMapExt;
+                        #[allow(unused_variables)]
                         let map = &mut #map;
                         match #field.as_str() {
                             #(#v,)*
-                            _ => Err(#span::WalleError::DeclareNotMatch(#ss, #field.to_string()))
+                            _ => {
+                                #(#delegates)*
+                                Err(#span::WalleError::DeclareNotMatch(#ss, #field.to_string()))
+                            }
                         }
                     }
                 }
@@ -207,27 +242,80 @@ fn try_from_internal(
     }
 }
 
-fn attrs_parse(name: &Ident, attrs: &Vec<Attribute>, ty: &str) -> Result<String> {
+struct TypeAttr {
+    rename: Option<String>,
+    prefix: Option<String>,
+}
+
+impl TypeAttr {
+    /// 拼接扩展前缀后的名称
+    fn name(&self, name: &Ident) -> String {
+        let name = self
+            .rename
+            .clone()
+            .unwrap_or_else(|| snake_case(name.to_string()));
+        prefixed(&self.prefix, name)
+    }
+
+    fn prefix_const(&self) -> TokenStream2 {
+        match &self.prefix {
+            Some(p) => quote!(const PREFIX: Option<&'static str> = Some(#p);),
+            None => quote!(),
+        }
+    }
+}
+
+/// 变体级属性 `#[action(delegate)]`，单元素元组变体按内部类型的名称分派
+///
+/// 未标注时按变体名称分派，内部类型由 TryFromValue 解析
+pub(crate) fn delegate_parse(attrs: &[Attribute], ty: &str, fields: &Fields) -> Result<bool> {
+    let mut delegate = false;
     for attr in attrs {
         if attr.path.is_ident(ty) {
             match attr.parse_meta()? {
-                Meta::NameValue(v) => {
-                    if let Lit::Str(s) = v.lit {
-                        if v.path.is_ident("rename") {
-                            return Ok(s.value());
+                Meta::List(l) => {
+                    for nest in l.nested {
+                        match nest {
+                            NestedMeta::Meta(Meta::Path(p)) if p.is_ident("delegate") => {
+                                delegate = true
+                            }
+                            _ => return Err(error("unexpect attr")),
                         }
                     }
                 }
+                _ => return Err(error("unexpect attr")),
+            }
+        }
+    }
+    match fields {
+        Fields::Unnamed(f) if f.unnamed.len() == 1 => Ok(delegate),
+        _ if delegate => Err(error("delegate requires a single-field tuple variant")),
+        _ => Ok(false),
+    }
+}
+
+pub(crate) fn prefixed(prefix: &Option<String>, name: String) -> String {
+    match prefix {
+        Some(p) => format!("{}.{}", p, name),
+        None => name,
+    }
+}
+
+fn attrs_parse(attrs: &Vec<Attribute>, ty: &str) -> Result<TypeAttr> {
+    let mut out = TypeAttr {
+        rename: None,
+        prefix: None,
+    };
+    for attr in attrs {
+        if attr.path.is_ident(ty) {
+            match attr.parse_meta()? {
+                Meta::NameValue(v) => name_value_parse(v, &mut out)?,
                 Meta::List(l) => {
                     for nest in l.nested {
-                        if let NestedMeta::Lit(Lit::Str(s)) = nest {
-                            return Ok(s.value());
-                        } else if let NestedMeta::Meta(Meta::NameValue(v)) = nest {
-                            if let Lit::Str(s) = v.lit {
-                                if v.path.is_ident("rename") {
-                                    return Ok(s.value());
-                                }
-                            }
+                        match nest {
+                            NestedMeta::Lit(Lit::Str(s)) => out.rename = Some(s.value()),
+                            NestedMeta::Meta(Meta::NameValue(v)) => name_value_parse(v, &mut out)?,
+                            _ => return Err(error("unexpect attr")),
                         }
                     }
                 }
@@ -235,5 +323,19 @@ fn attrs_parse(name: &Ident, attrs: &Vec<Attribute>, ty: &str) -> Result<String>
             }
         }
     }
-    Ok(snake_case(name.to_string()))
+    Ok(out)
+}
+
+fn name_value_parse(v: MetaNameValue, out: &mut TypeAttr) -> Result<()> {
+    let Lit::Str(s) = v.lit else {
+        return Err(error("expect str lit"));
+    };
+    if v.path.is_ident("rename") {
+        out.rename = Some(s.value());
+    } else if v.path.is_ident("prefix") || v.path.is_ident("platform") {
+        out.prefix = Some(s.value());
+    } else {
+        return Err(error("unexpect attr"));
+    }
+    Ok(())
 }
@@ -2,45 +2,68 @@ use proc_macro2::{Ident, TokenStream as TokenStream2};
 use quote::quote;
 use syn::{Attribute, Data, DeriveInput, Fields, Lit, Meta, NestedMeta, Result};
 
-use crate::{error, fields_from_map, snake_case};
+use crate::{
+    action_segment::{delegate_parse, prefixed},
+    error, fields_from_map, snake_case,
+};
 
 pub(crate) fn to_event_internal(input: DeriveInput, span: TokenStream2) -> Result<TokenStream2> {
     let name = input.ident;
-    let (tys, _, ss) = attrs_parse(&name, &input.attrs, &span)?;
+    let prefix = prefix_parse(&input.attrs)?;
+    let (tys, _, ss, levels) = attrs_parse(&name, &input.attrs, &span, &prefix)?;
+    let prefix_const = match &prefix {
+        Some(p) => quote!(const PREFIX: Option<&'static str> = Some(#p);),
+        None => quote!(),
+    };
 
     match input.data {
         Data::Struct(_) | Data::Union(_) => Ok(quote!(
             #(impl #span::event::ToEvent<#tys> for #name {
+                #prefix_const
                 fn ty(&self) -> &'static str {
                     #ss
                 }
             })*
         )),
         Data::Enum(ref data) => {
-            let v = data
-                .variants
-                .iter()
-                .map(|var| {
-                    // todo attr
-                    let id = var.ident.clone();
-                    let s = snake_case(id.to_string());
-                    match var.fields {
-                        Fields::Named(_) => quote!(Self::#id {..} => #s),
-                        Fields::Unnamed(_) => quote!(Self::#id (..) => #s),
-                        Fields::Unit => quote!(Self::#id => #s),
-                    }
-                })
-                .collect::<Vec<_>>();
-            let v = quote!(#(#v),*);
-            Ok(quote!(
-                #(impl #span::event::ToEvent<#tys> for #name {
-                    fn ty(&self) -> &'static str {
-                        match self {
-                            #v
+            let mut delegates = Vec::new();
+            for var in &data.variants {
+                if delegate_parse(&var.attrs, "event", &var.fields)? {
+                    delegates.push(var.ident.clone());
+                }
+            }
+            let impls = tys.iter().zip(levels.iter()).map(|(ty, level)| {
+                let v = data
+                    .variants
+                    .iter()
+                    .map(|var| {
+                        // todo attr
+                        let id = var.ident.clone();
+                        let s = level_name(level, &prefix, snake_case(id.to_string()));
+                        match var.fields {
+                            Fields::Named(_) => quote!(Self::#id {..} => #s),
+                            // 使用内部类型的名称
+                            Fields::Unnamed(ref f) if delegates.contains(&id) => {
+                                let t = &f.unnamed.first().unwrap().ty;
+                                quote!(Self::#id (inner) => <#t as #span::event::ToEvent<#ty>>::ty(inner))
+                            }
+                            Fields::Unnamed(_) => quote!(Self::#id (..) => #s),
+                            Fields::Unit => quote!(Self::#id => #s),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                quote!(
+                    impl #span::event::ToEvent<#ty> for #name {
+                        #prefix_const
+                        fn ty(&self) -> &'static str {
+                            match self {
+                                #(#v),*
+                            }
                         }
                     }
-                })*
-            ))
+                )
+            });
+            Ok(quote!(#(#impls)*))
         }
     }
 }
@@ -50,7 +73,8 @@ pub(crate) fn try_from_event_internal(
     span: TokenStream2,
 ) -> Result<TokenStream2> {
     let name = input.ident;
-    let (tys, tids, ss) = attrs_parse(&name, &input.attrs, &span)?;
+    let prefix = prefix_parse(&input.attrs)?;
+    let (tys, tids, ss, levels) = attrs_parse(&name, &input.attrs, &span, &prefix)?;
 
     match input.data {
         Data::Union(_) => Ok(quote!(
@@ -65,7 +89,7 @@ pub(crate) fn try_from_event_internal(
             })*
         )),
         Data::Struct(data) => {
-            let fs = fields_from_map(&data.fields);
+            let fs = fields_from_map(&data.fields)?;
             Ok(quote!(
                 #(impl #span::event::TryFromEvent<#tys> for #name {
                     fn try_from_event_mut(event: &mut #span::event::Event, implt: &str) -> #span::WalleResult<Self> {
@@ -81,33 +105,67 @@ pub(crate) fn try_from_event_internal(
             ))
         }
         Data::Enum(data) => {
-            let mut ss = Vec::new();
-            let arms = data
-                .variants
-                .into_iter()
-                .map(|v| {
-                    let vname = v.ident;
-                    // todo attr
-                    let s = snake_case(vname.to_string());
-                    ss.push(s.clone());
-                    let fs = fields_from_map(&v.fields);
-                    quote!(#s => Ok(Self::#vname #fs))
-                })
-                .collect::<Vec<_>>();
-            let arms = quote!(#(#arms,)*);
-            let ss = ss.join("|");
-            Ok(quote!(
-                #(impl #span::event::TryFromEvent<#tys> for #name {
-                    fn try_from_event_mut(event: &mut #span::event::Event, implt: &str) -> #span::WalleResult<Self> {
-                        use #span::util::value::ValueMapExt;
-                        let map = &mut event.extra;
-                        match #tids {
-                            #arms
-                            _ => Err(#span::WalleError::DeclareNotMatch(#ss, #tids.to_string()))
+            let mut names = Vec::new();
+            let mut fields = Vec::new();
+            let mut inners = Vec::new();
+            for var in data.variants {
+                let vname = var.ident;
+                let delegate = delegate_parse(&var.attrs, "event", &var.fields)?;
+                match var.fields {
+                    // 交由内部类型解析，名称不匹配时尝试下一个
+                    Fields::Unnamed(f) if delegate => {
+                        let t = f.unnamed.into_iter().next().unwrap().ty;
+                        inners.push((vname, t));
+                    }
+                    fs => {
+                        // todo attr
+                        names.push(snake_case(vname.to_string()));
+                        fields.push((vname, fields_from_map(&fs)?));
+                    }
+                }
+            }
+            let mut impls = Vec::new();
+            for ((ty, tid), level) in tys.iter().zip(tids.iter()).zip(levels.iter()) {
+                let names = names
+                    .iter()
+                    .map(|n| level_name(level, &prefix, n.clone()))
+                    .collect::<Vec<_>>();
+                let arms = names
+                    .iter()
+                    .zip(fields.iter())
+                    .map(|(s, (vname, fs))| quote!(#s => Ok(Self::#vname #fs)));
+                let ss = names
+                    .iter()
+                    .cloned()
+                    .chain(inners.iter().map(|(_, t)| quote!(#t).to_string()))
+                    .collect::<Vec<_>>()
+                    .join("|");
+                let delegates = inners.iter().map(|(vname, t)| {
+                    quote!(
+                        match <#t as #span::event::TryFromEvent<#ty>>::try_from_event_mut(event, implt) {
+                            Err(#span::WalleError::DeclareNotMatch(..)) => {}
+                            r => return r.map(Self::#vname),
+                        }
+                    )
+                });
+                impls.push(quote!(
+                    impl #span::event::TryFromEvent<#ty> for #name {
+                        fn try_from_event_mut(event: &mut #span::event::Event, implt: &str) -> #span::WalleResult<Self> {
+                            use #span::util::value::ValueMapExt;
+                            #[allow(unused_variables)]
+                            let map = &mut event.extra;
+                            match #tid {
+                                #(#arms,)*
+                                _ => {
+                                    #(#delegates)*
+                                    Err(#span::WalleError::DeclareNotMatch(#ss, #tid.to_string()))
+                                }
+                            }
                         }
                     }
-                })*
-            ))
+                ));
+            }
+            Ok(quote!(#(#impls)*))
         }
     }
 }
@@ -132,15 +190,45 @@ fn parse_ty(ty: &str, span: &TokenStream2) -> Result<(TokenStream2, TokenStream2
     }
 }
 
+/// 扩展前缀由 `prefix` 或 `platform` 指定，仅作用于 detail_type 与 sub_type
+///
+/// `platform = "qq"` 同时声明 platform 级别
+fn prefix_parse(attrs: &Vec<Attribute>) -> Result<Option<String>> {
+    for attr in attrs {
+        if attr.path.is_ident("event") {
+            if let Meta::List(l) = attr.parse_meta()? {
+                for nest in l.nested {
+                    if let NestedMeta::Meta(Meta::NameValue(v)) = nest {
+                        let named = v.path.is_ident("prefix") || v.path.is_ident("platform");
+                        if let (true, Lit::Str(s)) = (named, &v.lit) {
+                            return Ok(Some(s.value()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 各级别的 Level 类型、取值表达式、声明名称与级别名
+type Levels = (
+    Vec<TokenStream2>,
+    Vec<TokenStream2>,
+    Vec<String>,
+    Vec<String>,
+);
+
 fn attrs_parse(
     name: &Ident,
     attrs: &Vec<Attribute>,
     span: &TokenStream2,
-) -> Result<(Vec<TokenStream2>, Vec<TokenStream2>, Vec<String>)> {
-    let mut vs = (Vec::new(), Vec::new(), Vec::new());
+    prefix: &Option<String>,
+) -> Result<Levels> {
+    let mut vs = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
     for attr in attrs {
         if attr.path.is_ident("event") {
-            meta_parse(name, attr.parse_meta()?, span, &mut vs)?;
+            meta_parse(name, attr.parse_meta()?, span, prefix, &mut vs)?;
         }
     }
     if vs.0.is_empty() {
@@ -154,31 +242,46 @@ fn meta_parse(
     name: &Ident,
     meta: Meta,
     span: &TokenStream2,
-    vs: &mut (Vec<TokenStream2>, Vec<TokenStream2>, Vec<String>),
+    prefix: &Option<String>,
+    vs: &mut Levels,
 ) -> Result<()> {
     match meta {
         Meta::List(l) => {
             for nest in l.nested {
                 match nest {
                     NestedMeta::Lit(_) => return Err(error("unexpect lit")),
-                    NestedMeta::Meta(meta) => meta_parse(name, meta, span, vs)?,
+                    NestedMeta::Meta(meta) => meta_parse(name, meta, span, prefix, vs)?,
                 }
             }
         }
         Meta::Path(p) => {
-            let (t0, t1) = parse_ty(&p.get_ident().unwrap().to_string(), span)?;
+            let ty = p.get_ident().unwrap().to_string();
+            let (t0, t1) = parse_ty(&ty, span)?;
             vs.0.push(t0);
             vs.1.push(t1);
-            vs.2.push(snake_case(name.to_string()));
+            vs.2.push(level_name(&ty, prefix, snake_case(name.to_string())));
+            vs.3.push(ty);
         }
         Meta::NameValue(v) => {
+            if v.path.is_ident("prefix") {
+                return Ok(());
+            }
             if let Lit::Str(s) = &v.lit {
-                let (t0, t1) = parse_ty(&v.path.get_ident().unwrap().to_string(), span)?;
+                let ty = v.path.get_ident().unwrap().to_string();
+                let (t0, t1) = parse_ty(&ty, span)?;
                 vs.0.push(t0);
                 vs.1.push(t1);
-                vs.2.push(s.value());
+                vs.2.push(level_name(&ty, prefix, s.value()));
+                vs.3.push(ty);
             }
         }
     }
     Ok(())
 }
+
+fn level_name(ty: &str, prefix: &Option<String>, name: String) -> String {
+    match ty {
+        "detail_type" | "sub_type" if !name.is_empty() => prefixed(prefix, name),
+        _ => name,
+    }
+}
@@ -1,7 +1,9 @@
 use proc_macro::TokenStream;
 use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::quote;
-use syn::{DeriveInput, Error, Fields, Result, Type};
+use syn::{
+    DeriveInput, Error, Field, Fields, Ident, Lit, Meta, MetaNameValue, NestedMeta, Result, Type,
+};
 
 mod action_segment;
 mod event;
@@ -18,7 +20,7 @@ fn snake_case(s: String) -> String {
     let mut out = String::default();
     let mut chars = s.chars();
     out.push(chars.next().unwrap().to_ascii_lowercase());
-    while let Some(c) = chars.next() {
+    for c in chars {
         if c.is_ascii_uppercase() {
             out.push('_');
             out.push(c.to_ascii_lowercase());
@@ -54,13 +56,13 @@ macro_rules! ob {
 
 use value::try_from_value_internal;
 
-ob!(TryFromValue => try_from_value, try_from_value_internal, walle_core);
-ob!(_TryFromValue => _try_from_value, try_from_value_internal, crate);
+ob!(TryFromValue: value => try_from_value, try_from_value_internal, walle_core);
+ob!(_TryFromValue: value => _try_from_value, try_from_value_internal, crate);
 
 use value::push_to_value_map_internal;
 
-ob!(PushToValueMap => push_to_value_map, push_to_value_map_internal, walle_core);
-ob!(_PushToValueMap => _push_to_value_map, push_to_value_map_internal, crate);
+ob!(PushToValueMap: value => push_to_value_map, push_to_value_map_internal, walle_core);
+ob!(_PushToValueMap: value => _push_to_value_map, push_to_value_map_internal, crate);
 
 use event::to_event_internal;
 
@@ -69,8 +71,8 @@ ob!(_ToEvent: event => _to_event, to_event_internal, crate);
 
 use event::try_from_event_internal;
 
-ob!(TryFromEvent: event => try_from_event, try_from_event_internal, walle_core);
-ob!(_TryFromEvent: event => _try_from_event, try_from_event_internal, crate);
+ob!(TryFromEvent: event, value => try_from_event, try_from_event_internal, walle_core);
+ob!(_TryFromEvent: event, value => _try_from_event, try_from_event_internal, crate);
 
 use action_segment::to_action_internal;
 
@@ -84,40 +86,94 @@ ob!(_ToMsgSegment: msg_segment => _to_msg_segment, to_msg_segment_internal, crat
 
 use action_segment::try_from_action_internal;
 
-ob!(TryFromAction: action => try_from_action, try_from_action_internal, walle_core);
-ob!(_TryFromAction: action => _try_from_action, try_from_action_internal, crate);
+ob!(TryFromAction: action, value => try_from_action, try_from_action_internal, walle_core);
+ob!(_TryFromAction: action, value => _try_from_action, try_from_action_internal, crate);
 
 use action_segment::try_from_msg_segment_internal;
 
-ob!(TryFromMsgSegment: msg_segment => try_from_msg_segment, try_from_msg_segment_internal, walle_core);
-ob!(_TryFromMsgSegment: msg_segment => _try_from_msg_segment, try_from_msg_segment_internal, crate);
+ob!(TryFromMsgSegment: msg_segment, value => try_from_msg_segment, try_from_msg_segment_internal, walle_core);
+ob!(_TryFromMsgSegment: msg_segment, value => _try_from_msg_segment, try_from_msg_segment_internal, crate);
 
-fn fields_from_map(fields: &Fields) -> TokenStream2 {
+/// 字段级属性 `#[value(rename = "..", default, skip, flatten)]`
+#[derive(Default)]
+struct FieldAttr {
+    rename: Option<String>,
+    default: bool,
+    skip: bool,
+    flatten: bool,
+}
+
+impl FieldAttr {
+    fn parse(field: &Field) -> Result<Self> {
+        let mut out = Self::default();
+        for attr in &field.attrs {
+            if !attr.path.is_ident("value") {
+                continue;
+            }
+            let Meta::List(l) = attr.parse_meta()? else {
+                return Err(error("expect #[value(..)]"));
+            };
+            for nest in l.nested {
+                match nest {
+                    NestedMeta::Meta(Meta::Path(p)) if p.is_ident("default") => out.default = true,
+                    NestedMeta::Meta(Meta::Path(p)) if p.is_ident("skip") => out.skip = true,
+                    NestedMeta::Meta(Meta::Path(p)) if p.is_ident("flatten") => out.flatten = true,
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                        path,
+                        lit: Lit::Str(s),
+                        ..
+                    })) if path.is_ident("rename") => out.rename = Some(s.value()),
+                    _ => return Err(error("unsupportted value attr")),
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// map 中对应的键名
+    fn key(&self, ident: &Ident) -> String {
+        self.rename.clone().unwrap_or_else(|| {
+            let mut s = ident.to_string();
+            escape(&mut s);
+            s
+        })
+    }
+}
+
+fn is_type(ty: &Type, name: &str) -> bool {
+    if let Type::Path(ref p) = ty {
+        p.path.segments.last().map(|s| s.ident == name) == Some(true)
+    } else {
+        false
+    }
+}
+
+fn fields_from_map(fields: &Fields) -> Result<TokenStream2> {
     match fields {
         Fields::Named(named) => {
-            let v = named
-                .named
-                .iter()
-                .map(|f| {
-                    let field_name = f.ident.clone().unwrap();
-                    let mut s = field_name.to_string();
-                    escape(&mut s);
-                    if let Type::Path(ref p) = f.ty {
-                        if p.path
-                            .segments
-                            .first()
-                            .unwrap()
-                            .ident
-                            .to_string()
-                            .starts_with("Option")
-                        {
-                            return quote!(#field_name: map.try_remove_downcast(#s)?);
-                        }
-                    }
-                    quote!(#field_name: map.remove_downcast(#s)?)
-                })
-                .collect::<Vec<_>>();
-            quote!({#(#v),*})
+            let mut v = Vec::new();
+            // flatten 字段最后解析，仅消费剩余的键
+            let mut flattens = Vec::new();
+            for f in named.named.iter() {
+                let attr = FieldAttr::parse(f)?;
+                let field_name = f.ident.clone().unwrap();
+                let s = attr.key(&field_name);
+                if attr.skip {
+                    v.push(quote!(#field_name: Default::default()));
+                } else if attr.flatten && is_type(&f.ty, "ValueMap") {
+                    flattens.push(quote!(#field_name: std::mem::take(map)));
+                } else if attr.flatten {
+                    flattens.push(quote!(#field_name: TryFrom::try_from(&mut *map)?));
+                } else if attr.default {
+                    v.push(quote!(#field_name: map.try_remove_downcast(#s)?.unwrap_or_default()));
+                } else if is_type(&f.ty, "Option") {
+                    v.push(quote!(#field_name: map.try_remove_downcast(#s)?));
+                } else {
+                    v.push(quote!(#field_name: map.remove_downcast(#s)?));
+                }
+            }
+            v.extend(flattens);
+            Ok(quote!({#(#v),*}))
         }
         Fields::Unnamed(unamed) => {
             let v = unamed
@@ -128,8 +184,8 @@ fn fields_from_map(fields: &Fields) -> TokenStream2 {
                     quote!(#t::try_from(map)?)
                 })
                 .collect::<Vec<_>>();
-            quote!((#(#v),*))
+            Ok(quote!((#(#v),*)))
         }
-        Fields::Unit => quote!(),
+        Fields::Unit => Ok(quote!()),
     }
 }
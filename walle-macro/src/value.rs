@@ -1,8 +1,8 @@
 use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
 use quote::quote;
-use syn::{Data, DeriveInput, Fields, Result};
+use syn::{Data, DeriveInput, Fields, Result, Type};
 
-use crate::{error, escape, fields_from_map};
+use crate::{error, fields_from_map, is_type, FieldAttr};
 
 pub(crate) fn try_from_value_internal(
     input: DeriveInput,
@@ -11,7 +11,7 @@ pub(crate) fn try_from_value_internal(
     let name = input.ident;
 
     let fields = if let Data::Struct(data) = input.data {
-        fields_from_map(&data.fields)
+        fields_from_map(&data.fields)?
     } else {
         return Err(error("only support struct"));
     };
@@ -48,9 +48,14 @@ pub(crate) fn push_to_value_map_internal(
                 let mut stream = TokenStream2::new();
                 for field in named.named.iter() {
                     let name = field.ident.clone().unwrap();
-                    let mut s = name.to_string();
-                    escape(&mut s);
-                    stream.extend(quote!(map.insert(#s.to_string(), self.#name.into());));
+                    let attr = FieldAttr::parse(field)?;
+                    stream.extend(push_field(
+                        &attr,
+                        &field.ty,
+                        quote!(self.#name),
+                        &name,
+                        &span,
+                    ));
                 }
                 stream
             }
@@ -72,36 +77,39 @@ pub(crate) fn push_to_value_map_internal(
                     let vname = var.ident;
                     match var.fields {
                         Fields::Named(named) => {
-                            let idents = named
-                                .named
-                                .into_iter()
-                                .map(|f| f.ident.unwrap())
-                                .collect::<Vec<_>>();
-                            let ss = idents
-                                .iter()
-                                .map(|i| {
-                                    let mut s = i.to_string();
-                                    escape(&mut s);
-                                    s
-                                })
-                                .collect::<Vec<_>>();
-                            quote!(Self::#vname{#(#idents),*} => {
-                                #(map.insert(#ss.to_string(), #idents.into());)*
-                            })
+                            let mut idents = Vec::new();
+                            let mut stream = TokenStream2::new();
+                            for field in named.named.iter() {
+                                let name = field.ident.clone().unwrap();
+                                let attr = FieldAttr::parse(field)?;
+                                if !attr.skip {
+                                    stream.extend(push_field(
+                                        &attr,
+                                        &field.ty,
+                                        quote!(#name),
+                                        &name,
+                                        &span,
+                                    ));
+                                    idents.push(name);
+                                }
+                            }
+                            Ok(quote!(Self::#vname{#(#idents,)* ..} => {
+                                #stream
+                            }))
                         }
                         Fields::Unnamed(unamed) => {
                             let idents = (0..unamed.unnamed.len())
                                 .map(|i| Ident::new(&format!("f{}", i), Span::call_site()))
                                 .collect::<Vec<_>>();
-                            quote!(Self::#vname(#(#idents),*) => {
+                            Ok(quote!(Self::#vname(#(#idents),*) => {
                                 use #span::util::value::PushToValueMap;
                                 #(#idents.push_to(map);)*
-                            })
+                            }))
                         }
-                        Fields::Unit => quote!(Self::#vname => {}),
+                        Fields::Unit => Ok(quote!(Self::#vname => {})),
                     }
                 })
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>>>()?;
             quote!(match self {
                 #(#v)*
             })
@@ -132,3 +140,22 @@ pub(crate) fn push_to_value_map_internal(
         }
     ))
 }
+
+fn push_field(
+    attr: &FieldAttr,
+    ty: &Type,
+    value: TokenStream2,
+    ident: &Ident,
+    span: &TokenStream2,
+) -> TokenStream2 {
+    let s = attr.key(ident);
+    if attr.skip {
+        quote!()
+    } else if attr.flatten && is_type(ty, "ValueMap") {
+        quote!(map.extend(#value);)
+    } else if attr.flatten {
+        quote!(#span::util::value::PushToValueMap::push_to(#value, map);)
+    } else {
+        quote!(map.insert(#s.to_string(), #value.into());)
+    }
+}